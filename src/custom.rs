@@ -25,6 +25,7 @@ use crate::{
     types::TypeConverter,
 };
 
+pub mod coverage;
 pub mod extension_op;
 pub mod load_constant;
 pub mod types;
@@ -105,6 +106,18 @@ impl<'a, H: HugrView + 'a> CodegenExtsBuilder<'a, H> {
         self
     }
 
+    /// Register a callback to emit any [ExtensionOp] for which no other
+    /// callback is registered.
+    ///
+    /// ```
+    /// use hugr_llvm::{custom::extension_op::emit_extern_call_stub, CodegenExtsBuilder};
+    /// CodegenExtsBuilder::<hugr::Hugr>::default().extension_op_fallback(emit_extern_call_stub);
+    /// ```
+    pub fn extension_op_fallback(mut self, handler: impl ExtensionOpFn<'a, H>) -> Self {
+        self.extension_op_handlers.fallback(handler);
+        self
+    }
+
     /// Register callbacks to emit [ExtensionOp]s that match the
     /// definitions generated by `Op`s impl of [strum::IntoEnumIterator]>
     pub fn simple_extension_op<Op: MakeOpDef + IntoEnumIterator>(
@@ -166,7 +179,10 @@ mod test {
     };
     use itertools::Itertools as _;
 
-    use crate::{emit::libc::emit_libc_printf, CodegenExtsBuilder};
+    use crate::{
+        emit::{libc::emit_libc_printf, test::SimpleHugrConfig},
+        CodegenExtsBuilder,
+    };
 
     #[test]
     fn types_with_lifetimes() {
@@ -221,4 +237,73 @@ mod test {
             })
             .finish();
     }
+
+    #[test]
+    fn check_coverage() {
+        use hugr::{
+            builder::{Dataflow as _, DataflowSubContainer as _},
+            extension::{prelude::BOOL_T, ExtensionRegistry, PRELUDE},
+            ops::NamedOp as _,
+            std_extensions::{
+                arithmetic::{int_ops, int_types},
+                logic::{self, LogicOp},
+            },
+        };
+
+        use crate::utils::IntOpBuilder as _;
+
+        let int_ty = int_types::INT_TYPES[6].clone();
+        let hugr = SimpleHugrConfig::new()
+            .with_ins(vec![BOOL_T, BOOL_T, int_ty.clone()])
+            .with_outs(vec![BOOL_T, int_ty])
+            .with_extensions(
+                ExtensionRegistry::try_new(vec![
+                    PRELUDE.to_owned(),
+                    logic::EXTENSION.to_owned(),
+                    int_types::EXTENSION.to_owned(),
+                    int_ops::EXTENSION.to_owned(),
+                ])
+                .unwrap(),
+            )
+            .finish(|mut builder| {
+                let [a, b, i] = builder.input_wires_arr();
+                let [and] = builder
+                    .add_dataflow_op(LogicOp::And, [a, b])
+                    .unwrap()
+                    .outputs_arr();
+                let one = builder.add_load_value(int_types::ConstInt::new_u(6, 1).unwrap());
+                let sum = builder.add_iadd(6, i, one).unwrap();
+                builder.finish_with_outputs([and, sum]).unwrap()
+            });
+
+        let missing = CodegenExtsBuilder::<Hugr>::default()
+            .add_default_prelude_extensions()
+            .finish()
+            .check_coverage(&hugr)
+            .unwrap_err();
+        assert_eq!(
+            missing.ops,
+            [
+                (logic::EXTENSION_ID, LogicOp::And.name()),
+                (int_ops::EXTENSION_ID, "iadd".into())
+            ]
+            .into_iter()
+            .collect()
+        );
+        assert_eq!(
+            missing.types,
+            [(int_types::EXTENSION_ID, "int".into())]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(missing.consts.len(), 1);
+
+        CodegenExtsBuilder::<Hugr>::default()
+            .add_default_prelude_extensions()
+            .add_int_extensions()
+            .add_logic_extensions()
+            .finish()
+            .check_coverage(&hugr)
+            .unwrap();
+    }
 }
@@ -0,0 +1,179 @@
+//! Provides [CodegenExtsMap::check_coverage], which reports all elements of a
+//! HUGR for which no codegen callback is registered.
+use std::{collections::BTreeSet, fmt};
+
+use hugr::{
+    extension::ExtensionId,
+    ops::{constant::CustomConst, OpName, OpType, Value},
+    types::{EdgeKind, TypeArg, TypeEnum, TypeRow},
+    HugrView,
+};
+use itertools::Itertools as _;
+
+use crate::types::{HugrFuncType, HugrSumType, HugrType};
+
+use super::{types::CustomTypeKey, CodegenExtsMap};
+
+/// The elements of a HUGR for which no callback is registered in a
+/// [CodegenExtsMap]. Returned by [CodegenExtsMap::check_coverage].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MissingHandlers {
+    /// The fully qualified names of [hugr::ops::ExtensionOp]s with no
+    /// [super::extension_op::ExtensionOpFn].
+    pub ops: BTreeSet<(ExtensionId, OpName)>,
+    /// The [hugr::types::CustomType]s with no
+    /// [super::types::LLVMCustomTypeFn].
+    pub types: BTreeSet<CustomTypeKey>,
+    /// The types of [CustomConst]s with no
+    /// [super::load_constant::LoadConstantFn].
+    pub consts: BTreeSet<String>,
+}
+
+impl MissingHandlers {
+    /// Returns true if no handlers are missing.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty() && self.types.is_empty() && self.consts.is_empty()
+    }
+}
+
+impl fmt::Display for MissingHandlers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Missing codegen handlers.")?;
+        if !self.ops.is_empty() {
+            let ops = self.ops.iter().map(|(e, o)| format!("{e}.{o}")).join(", ");
+            write!(f, " Ops: [{ops}].")?;
+        }
+        if !self.types.is_empty() {
+            let types = self
+                .types
+                .iter()
+                .map(|(e, t)| format!("{e}.{t}"))
+                .join(", ");
+            write!(f, " Types: [{types}].")?;
+        }
+        if !self.consts.is_empty() {
+            write!(
+                f,
+                " Constants of types: [{}].",
+                self.consts.iter().join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for MissingHandlers {}
+
+impl<H: HugrView> CodegenExtsMap<'_, H> {
+    /// Walks `hugr`, including the HUGRs of any function-valued constants, and
+    /// returns every [hugr::ops::ExtensionOp], [hugr::types::CustomType] and
+    /// [CustomConst] for which there is no registered callback.
+    ///
+    /// No LLVM is generated, so this can be used to check that a HUGR is
+    /// supported before emitting it. If a fallback callback for
+    /// [hugr::ops::ExtensionOp]s is registered, all ops are considered to be
+    /// covered. Note that the types of polymorphic
+    /// functions are only checked for their concrete parts, and that
+    /// callbacks may themselves still fail.
+    pub fn check_coverage(&self, hugr: &impl HugrView) -> Result<(), MissingHandlers> {
+        let mut missing = MissingHandlers::default();
+        self.check_hugr(hugr, &mut missing);
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    fn check_hugr(&self, hugr: &impl HugrView, missing: &mut MissingHandlers) {
+        for node in hugr.nodes() {
+            let optype = hugr.get_optype(node);
+            match optype {
+                OpType::ExtensionOp(op) => {
+                    let key = (op.def().extension().clone(), op.def().name().clone());
+                    if !self.extension_op_handlers.has_fallback()
+                        && !self.extension_op_handlers.contains(&key.0, &key.1)
+                    {
+                        missing.ops.insert(key);
+                    }
+                }
+                OpType::Const(konst) => self.check_value(konst.value(), missing),
+                _ => (),
+            }
+            for port in hugr.node_outputs(node) {
+                match optype.port_kind(port) {
+                    Some(EdgeKind::Value(t)) | Some(EdgeKind::Const(t)) => {
+                        self.check_type(&t, missing)
+                    }
+                    Some(EdgeKind::Function(poly_func_type)) => {
+                        self.check_signature(poly_func_type.body(), missing)
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    fn check_value(&self, value: &Value, missing: &mut MissingHandlers) {
+        match value {
+            Value::Extension { e } => {
+                let konst: &dyn CustomConst = e.value();
+                if !self.load_constant_handlers.contains(konst) {
+                    missing.consts.insert(konst.get_type().to_string());
+                }
+            }
+            Value::Function { hugr } => self.check_hugr(hugr.as_ref(), missing),
+            Value::Sum(sum) => sum.values.iter().for_each(|v| self.check_value(v, missing)),
+        }
+    }
+
+    fn check_type(&self, hugr_type: &HugrType, missing: &mut MissingHandlers) {
+        match hugr_type.as_type_enum() {
+            TypeEnum::Extension(custom_type) => {
+                let key = (custom_type.extension().clone(), custom_type.name().clone());
+                if !self.type_converter.has_custom_type(&key) {
+                    missing.types.insert(key);
+                }
+                custom_type
+                    .args()
+                    .iter()
+                    .for_each(|arg| self.check_type_arg(arg, missing));
+            }
+            TypeEnum::Sum(sum_type) => self.check_sum_type(sum_type, missing),
+            TypeEnum::Function(function_type) => {
+                // Function types with row variables can't be emitted anyway.
+                if let Ok(sig) = HugrFuncType::try_from(function_type.as_ref().clone()) {
+                    self.check_signature(&sig, missing)
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn check_type_arg(&self, arg: &TypeArg, missing: &mut MissingHandlers) {
+        match arg {
+            TypeArg::Type { ty } => self.check_type(ty, missing),
+            TypeArg::Sequence { elems } => elems
+                .iter()
+                .for_each(|arg| self.check_type_arg(arg, missing)),
+            _ => (),
+        }
+    }
+
+    fn check_sum_type(&self, sum_type: &HugrSumType, missing: &mut MissingHandlers) {
+        for i in 0..sum_type.num_variants() {
+            if let Ok(row) = TypeRow::try_from(sum_type.get_variant(i).unwrap().clone()) {
+                self.check_type_row(&row, missing)
+            }
+        }
+    }
+
+    fn check_signature(&self, sig: &HugrFuncType, missing: &mut MissingHandlers) {
+        self.check_type_row(&sig.input, missing);
+        self.check_type_row(&sig.output, missing);
+    }
+
+    fn check_type_row(&self, row: &TypeRow, missing: &mut MissingHandlers) {
+        row.iter().for_each(|t| self.check_type(t, missing))
+    }
+}
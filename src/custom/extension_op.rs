@@ -5,15 +5,22 @@ use hugr::{
         simple_op::{MakeExtensionOp, MakeOpDef},
         ExtensionId,
     },
-    ops::{ExtensionOp, OpName},
+    ops::{DataflowOpTrait as _, ExtensionOp, OpName},
+    types::TypeArg,
     HugrView,
 };
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 
+use itertools::Itertools as _;
 use strum::IntoEnumIterator;
 
-use crate::emit::{EmitFuncContext, EmitOpArgs};
+use crate::{
+    emit::{
+        deaggregate_call_result, namer::mangle_type_arg, EmitErrorKind, EmitFuncContext, EmitOpArgs,
+    },
+    utils::substitute::substitute_type_arg,
+};
 
 /// A helper trait for describing the callback used for emitting [ExtensionOp]s,
 /// and for hanging documentation. We have the appropriate `Fn` as a supertrait,
@@ -52,11 +59,16 @@ impl<
 {
 }
 
-/// A collection of [ExtensionOpFn] callbacks keyed the fully qualified [OpName].
+/// A collection of [ExtensionOpFn] callbacks keyed the fully qualified [OpName],
+/// together with an optional fallback callback for ops with no registered
+/// callback.
 ///
 /// Those callbacks may hold references with lifetimes older than `'a`.
 #[derive(Default)]
-pub struct ExtensionOpMap<'a, H>(HashMap<(ExtensionId, OpName), Box<dyn ExtensionOpFn<'a, H>>>);
+pub struct ExtensionOpMap<'a, H> {
+    handlers: HashMap<(ExtensionId, OpName), Box<dyn ExtensionOpFn<'a, H>>>,
+    fallback: Option<Box<dyn ExtensionOpFn<'a, H>>>,
+}
 
 impl<'a, H: HugrView> ExtensionOpMap<'a, H> {
     /// Register a callback to emit a [ExtensionOp], keyed by fully
@@ -67,7 +79,15 @@ impl<'a, H: HugrView> ExtensionOpMap<'a, H> {
         op: OpName,
         handler: impl ExtensionOpFn<'a, H>,
     ) {
-        self.0.insert((extension, op), Box::new(handler));
+        self.handlers.insert((extension, op), Box::new(handler));
+    }
+
+    /// Register a callback to emit any [ExtensionOp] for which no other
+    /// callback is registered.
+    ///
+    /// [emit_extern_call_stub] is provided as such a callback.
+    pub fn fallback(&mut self, handler: impl ExtensionOpFn<'a, H>) {
+        self.fallback = Some(Box::new(handler));
     }
 
     /// Register callbacks to emit [ExtensionOp]s that match the
@@ -91,9 +111,21 @@ impl<'a, H: HugrView> ExtensionOpMap<'a, H> {
         }
     }
 
+    /// Returns whether a callback is registered for the fully qualified
+    /// [OpName]. The fallback callback is not considered.
+    pub fn contains(&self, extension: &ExtensionId, op: &OpName) -> bool {
+        self.handlers.contains_key(&(extension.clone(), op.clone()))
+    }
+
+    /// Returns whether a fallback callback is registered.
+    pub fn has_fallback(&self) -> bool {
+        self.fallback.is_some()
+    }
+
     /// Emit an [ExtensionOp]  by delegating to the collected callbacks.
     ///
-    /// If no handler is registered for the op an error will be returned.
+    /// If no handler is registered for the op, the fallback handler is used.
+    /// If there is no fallback handler an error will be returned.
     pub fn emit_extension_op<'c>(
         &self,
         context: &mut EmitFuncContext<'c, 'a, H>,
@@ -101,9 +133,75 @@ impl<'a, H: HugrView> ExtensionOpMap<'a, H> {
     ) -> Result<()> {
         let node = args.node();
         let key = (node.def().extension().clone(), node.def().name().clone());
-        let Some(handler) = self.0.get(&key) else {
-            bail!("No extension could emit extension op: {key:?}")
+        let Some(handler) = self.handlers.get(&key).or(self.fallback.as_ref()) else {
+            Err(EmitErrorKind::UnsupportedOp(format!(
+                "No extension could emit extension op: {key:?}"
+            )))?
         };
         (handler.as_ref())(context, args)
     }
 }
+
+/// Returns the symbol of the external function called by
+/// [emit_extern_call_stub] for the op `op` of extension `extension`
+/// instantiated with `type_args`.
+///
+/// The components are separated by `__`. ASCII letters and digits are kept,
+/// and any other character, including `_`, is escaped as `_` followed by its
+/// code in hex and `_`. An escape is never empty, so the separators are
+/// unambiguous and distinct ops have distinct symbols.
+///
+/// ```
+/// use hugr::{extension::ExtensionId, types::TypeArg};
+/// use hugr_llvm::custom::extension_op::extern_call_stub_symbol;
+/// let ext = ExtensionId::new("my.ext").unwrap();
+/// let type_args = [TypeArg::BoundedNat { n: 6 }];
+/// assert_eq!(extern_call_stub_symbol(&ext, "op", &type_args), "my_2e_ext__op__n6");
+/// let ext = ExtensionId::new("my_ext").unwrap();
+/// assert_eq!(extern_call_stub_symbol(&ext, "op", &type_args), "my_5f_ext__op__n6");
+/// ```
+pub fn extern_call_stub_symbol(extension: &ExtensionId, op: &str, type_args: &[TypeArg]) -> String {
+    [extension.to_string(), op.to_string()]
+        .into_iter()
+        .chain(type_args.iter().map(mangle_type_arg))
+        .map(|s| {
+            s.chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() {
+                        c.to_string()
+                    } else {
+                        format!("_{:x}_", c as u32)
+                    }
+                })
+                .collect::<String>()
+        })
+        .join("__")
+}
+
+/// An [ExtensionOpFn] which emits an [ExtensionOp] as a call to an external
+/// function, to be provided at link time. The symbol of the function is given
+/// by [extern_call_stub_symbol], and its type by
+/// [EmitFuncContext::llvm_func_type] on the signature of the op.
+///
+/// This is intended to be registered with [ExtensionOpMap::fallback], so that
+/// ops may be implemented in a runtime library before codegen extensions for
+/// them exist.
+pub fn emit_extern_call_stub<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+) -> Result<()> {
+    let node = args.node();
+    let type_args = node
+        .args()
+        .iter()
+        .map(|arg| substitute_type_arg(arg, context.type_args()))
+        .collect::<Result<Vec<_>>>()?;
+    let symbol = extern_call_stub_symbol(node.def().extension(), node.def().name(), &type_args);
+    let func_type = context.llvm_func_type(&node.signature())?;
+    let func = context.get_extern_func(symbol, func_type)?;
+    let inputs = args.inputs.into_iter().map_into().collect_vec();
+    let builder = context.builder();
+    let call = builder.build_call(func, inputs.as_slice(), "")?;
+    let results = deaggregate_call_result(builder, call, args.outputs.len())?;
+    args.outputs.finish(builder, results)
+}
@@ -4,9 +4,9 @@ use std::{any::TypeId, collections::HashMap};
 use hugr::{ops::constant::CustomConst, HugrView};
 use inkwell::values::BasicValueEnum;
 
-use anyhow::{anyhow, bail, ensure, Result};
+use anyhow::{anyhow, ensure, Result};
 
-use crate::emit::EmitFuncContext;
+use crate::emit::{EmitErrorKind, EmitFuncContext};
 
 /// A helper trait for describing the callback used for emitting [CustomConst]s,
 /// and for hanging documentation. We have the appropriate `Fn` as a supertrait,
@@ -57,6 +57,11 @@ impl<'a, H: HugrView> LoadConstantsMap<'a, H> {
         );
     }
 
+    /// Returns whether a callback is registered for the type of `konst`.
+    pub fn contains(&self, konst: &dyn CustomConst) -> bool {
+        self.0.contains_key(&konst.type_id())
+    }
+
     /// Emit instructions to materialise `konst` by delegating to the
     /// appropriate inner callbacks.
     pub fn emit_load_constant<'c>(
@@ -66,10 +71,10 @@ impl<'a, H: HugrView> LoadConstantsMap<'a, H> {
     ) -> Result<BasicValueEnum<'c>> {
         let type_id = konst.type_id();
         let Some(handler) = self.0.get(&type_id) else {
-            bail!(
+            Err(EmitErrorKind::UnsupportedConst(format!(
                 "No extension could load constant name: {} type_id: {type_id:?}",
                 konst.name()
-            )
+            )))?
         };
         let r = handler(context, konst)?;
         let r_type = r.get_type();
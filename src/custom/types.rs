@@ -10,8 +10,9 @@ use inkwell::types::{BasicMetadataTypeEnum, BasicType as _, BasicTypeEnum, Funct
 pub use crate::utils::type_map::CustomTypeKey;
 
 use crate::{
+    emit::EmitErrorKind,
     sum::LLVMSumType,
-    types::{HugrFuncType, HugrSumType, TypingSession},
+    types::{HugrFuncType, HugrSumType, HugrType, TypingSession},
     utils::type_map::TypeMapping,
 };
 
@@ -60,6 +61,12 @@ impl<'a> TypeMapping for LLVMTypeMapping<'a> {
         )
     }
 
+    fn default_out<'c>(&self, _: Self::InV<'c>, hugr_type: &HugrType) -> Result<Self::OutV<'c>> {
+        Err(EmitErrorKind::UnsupportedType(format!(
+            "No extension could convert type: {hugr_type}"
+        )))?
+    }
+
     fn map_function_type<'c>(
         &self,
         _: &HugrFuncType,
@@ -1,9 +1,9 @@
 use anyhow::{anyhow, Result};
 use delegate::delegate;
 use hugr::{
-    ops::{FuncDecl, FuncDefn, OpType},
-    types::PolyFuncType,
-    HugrView, Node,
+    ops::{Const, FuncDecl, FuncDefn, OpTrait, OpType, Value},
+    types::{PolyFuncType, TypeArg, TypeRow},
+    Hugr, HugrView, Node, NodeIndex as _,
 };
 use inkwell::{
     builder::Builder,
@@ -13,22 +13,36 @@ use inkwell::{
     types::{AnyType, BasicType, BasicTypeEnum, FunctionType},
     values::{BasicValueEnum, CallSiteValue, FunctionValue, GlobalValue},
 };
-use std::{collections::HashSet, rc::Rc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    rc::Rc,
+};
 
 use crate::types::{HugrFuncType, HugrSumType, HugrType, TypingSession};
 
-use crate::{custom::CodegenExtsMap, types::LLVMSumType, utils::fat::FatNode};
+use self::{debug_info::DebugInfo, qir::QirBaseProfile};
+use crate::{
+    custom::CodegenExtsMap,
+    types::LLVMSumType,
+    utils::fat::{FatExt as _, FatNode},
+};
 
 pub mod args;
+pub mod debug_info;
+pub mod error;
 pub mod func;
 pub mod libc;
 pub mod namer;
 pub mod ops;
+pub mod qir;
 
 pub use args::EmitOpArgs;
-pub use func::{EmitFuncContext, RowPromise};
+pub use debug_info::DebugInfoOptions;
+pub use error::{EmitError, EmitErrorKind, ErrorContext};
+pub use func::{EmitFuncContext, MailBoxMode, RowPromise};
 pub use namer::Namer;
 pub use ops::emit_value;
+pub use qir::QirBaseProfileOptions;
 
 /// A context holding data required for emitting HUGRs into an LLVM module.
 /// This includes the module itself, a set of extensions for lowering custom
@@ -44,6 +58,10 @@ where
     module: Module<'c>,
     extensions: Rc<CodegenExtsMap<'a, H>>,
     namer: Rc<Namer>,
+    mailbox_mode: MailBoxMode,
+    debug_info: Option<Rc<DebugInfo<'c>>>,
+    node_metadata: bool,
+    qir_base_profile: Option<Rc<QirBaseProfile>>,
 }
 
 impl<'c, 'a, H> EmitModuleContext<'c, 'a, H> {
@@ -60,6 +78,8 @@ impl<'c, 'a, H> EmitModuleContext<'c, 'a, H> {
         to self.namer {
             /// Mangle the name of a [FuncDefn]  or a [FuncDecl].
             pub fn name_func(&self, name: impl AsRef<str>, node: Node) -> String;
+            /// Mangle the name of an instantiation of a [FuncDefn] or a [FuncDecl].
+            pub fn name_func_instance(&self, name: impl AsRef<str>, node: Node, type_args: &[TypeArg]) -> String;
         }
     }
 
@@ -80,9 +100,62 @@ impl<'c, 'a, H> EmitModuleContext<'c, 'a, H> {
             module,
             namer,
             extensions,
+            mailbox_mode: MailBoxMode::default(),
+            debug_info: None,
+            node_metadata: false,
+            qir_base_profile: None,
         }
     }
 
+    /// Enables the emission of debug info into the [Module], with a single
+    /// compile unit described by `options`.
+    pub fn with_debug_info(mut self, options: &DebugInfoOptions) -> Self {
+        let debug_info = DebugInfo::new(self.iw_context, &self.module, options);
+        self.debug_info = Some(Rc::new(debug_info));
+        self
+    }
+
+    /// Returns the [DebugInfo] of the [Module], if debug info is enabled.
+    pub fn debug_info(&self) -> Option<Rc<DebugInfo<'c>>> {
+        self.debug_info.clone()
+    }
+
+    /// Sets the [MailBoxMode] with which functions are emitted.
+    pub fn with_mailbox_mode(mut self, mailbox_mode: MailBoxMode) -> Self {
+        self.mailbox_mode = mailbox_mode;
+        self
+    }
+
+    /// Returns the [MailBoxMode] with which functions are emitted.
+    pub fn mailbox_mode(&self) -> MailBoxMode {
+        self.mailbox_mode
+    }
+
+    /// Sets whether instructions are tagged with
+    /// [debug_info::NODE_METADATA_KIND] metadata.
+    pub fn with_node_metadata(mut self, node_metadata: bool) -> Self {
+        self.node_metadata = node_metadata;
+        self
+    }
+
+    /// Returns whether instructions are tagged with
+    /// [debug_info::NODE_METADATA_KIND] metadata.
+    pub fn node_metadata(&self) -> bool {
+        self.node_metadata
+    }
+
+    /// Enables emission under the QIR base profile, described by `options`.
+    pub fn with_qir_base_profile(mut self, options: &QirBaseProfileOptions) -> Self {
+        self.qir_base_profile = Some(Rc::new(QirBaseProfile::new(options)));
+        self
+    }
+
+    /// Returns the [QirBaseProfile] of the [Module], if emission is under the
+    /// QIR base profile.
+    pub fn qir_base_profile(&self) -> Option<Rc<QirBaseProfile>> {
+        self.qir_base_profile.clone()
+    }
+
     /// Returns a reference to the inner [Module]. Note that this type has
     /// "interior mutability", and this reference can be used to add functions
     /// and globals to the [Module].
@@ -128,12 +201,21 @@ impl<'c, 'a, H> EmitModuleContext<'c, 'a, H> {
         name: impl AsRef<str>,
         node: Node,
         func_ty: &PolyFuncType,
+        type_args: &[TypeArg],
     ) -> Result<FunctionValue<'c>> {
-        let func_ty = (func_ty.params().is_empty())
-            .then_some(func_ty.body())
-            .ok_or(anyhow!("function has type params"))?;
-        let llvm_func_ty = self.llvm_func_type(func_ty)?;
-        let name = self.name_func(name, node);
+        if func_ty.params().len() != type_args.len() {
+            Err(EmitErrorKind::GenericFunction(format!(
+                "'{}' has {} type params but {} type args were provided",
+                name.as_ref(),
+                func_ty.params().len(),
+                type_args.len()
+            )))?
+        }
+        let llvm_func_ty = self
+            .typing_session()
+            .with_type_args(type_args)
+            .llvm_func_type(func_ty.body())?;
+        let name = self.name_func_instance(name, node, type_args);
         self.get_func_impl(name, llvm_func_ty, None)
     }
 
@@ -147,7 +229,23 @@ impl<'c, 'a, H> EmitModuleContext<'c, 'a, H> {
     where
         H: HugrView,
     {
-        self.get_hugr_func_impl(&node.name, node.node(), &node.signature)
+        self.get_func_defn_instance(node, &[])
+    }
+
+    /// Adds or gets the [FunctionValue] in the [Module] corresponding to the
+    /// instantiation of the given [FuncDefn] with `type_args`, which must be
+    /// free of type variables.
+    ///
+    /// The name of the result is mangled by [EmitModuleContext::name_func_instance].
+    pub fn get_func_defn_instance<'hugr>(
+        &self,
+        node: FatNode<'hugr, FuncDefn, H>,
+        type_args: &[TypeArg],
+    ) -> Result<FunctionValue<'c>>
+    where
+        H: HugrView,
+    {
+        self.get_hugr_func_impl(&node.name, node.node(), &node.signature, type_args)
     }
 
     /// Adds or gets the [FunctionValue] in the [Module] corresponding to the given [FuncDecl].
@@ -160,7 +258,46 @@ impl<'c, 'a, H> EmitModuleContext<'c, 'a, H> {
     where
         H: HugrView,
     {
-        self.get_hugr_func_impl(&node.name, node.node(), &node.signature)
+        self.get_func_decl_instance(node, &[])
+    }
+
+    /// Adds or gets the [FunctionValue] in the [Module] corresponding to the
+    /// instantiation of the given [FuncDecl] with `type_args`, which must be
+    /// free of type variables.
+    ///
+    /// The name of the result is mangled by [EmitModuleContext::name_func_instance].
+    pub fn get_func_decl_instance<'hugr>(
+        &self,
+        node: FatNode<'hugr, FuncDecl, H>,
+        type_args: &[TypeArg],
+    ) -> Result<FunctionValue<'c>>
+    where
+        H: HugrView,
+    {
+        self.get_hugr_func_impl(&node.name, node.node(), &node.signature, type_args)
+    }
+
+    /// Adds or gets the private [FunctionValue] in the [Module] corresponding
+    /// to the given [Const] node, which must hold a [Value::Function].
+    ///
+    /// The name of the result is mangled by [EmitModuleContext::name_func].
+    pub fn get_const_func<'hugr>(&self, node: FatNode<'hugr, Const, H>) -> Result<FunctionValue<'c>>
+    where
+        H: HugrView,
+    {
+        let Value::Function { hugr } = node.value() else {
+            Err(anyhow!(
+                "Const node does not hold a function: {}",
+                node.node()
+            ))?
+        };
+        let func_ty = hugr.inner_function_type().ok_or(anyhow!(
+            "Constant function has no inner function type: {}",
+            node.node()
+        ))?;
+        let llvm_func_ty = self.llvm_func_type(&func_ty)?;
+        let name = self.name_func(format!("const_fun_{}", node.node().index()), node.node());
+        self.get_func_impl(name, llvm_func_ty, Some(Linkage::Private))
     }
 
     /// Adds or get the [FunctionValue] in the [Module] with the given symbol
@@ -230,12 +367,58 @@ impl<'c, 'a, H> EmitModuleContext<'c, 'a, H> {
     }
 
     /// Consumes the `EmitModuleContext` and returns the internal [Module].
+    ///
+    /// If debug info is enabled, it is finalized.
     pub fn finish(self) -> Module<'c> {
+        if let Some(debug_info) = self.debug_info {
+            debug_info.finalize();
+        }
         self.module
     }
 }
 
-type EmissionSet = HashSet<Node>;
+/// A [FuncDefn] node, together with the [TypeArg]s with which it is
+/// instantiated. The [TypeArg]s of a monomorphic function are empty.
+///
+/// The node may also be a [Const] holding a [Value::Function], in which case
+/// the [TypeArg]s are empty.
+type FuncInstance = (Node, Vec<TypeArg>);
+
+/// A [HugrView] which can present the [Hugr] nested in a [Value::Function]
+/// as a view of the same type, so that it is emitted with the same codegen
+/// extensions as the rest of the HUGR.
+///
+/// This is implemented for [Hugr] and `&Hugr`, neither of which copies the
+/// nested [Hugr].
+pub trait ConstFunctionView: HugrView {
+    /// Calls `f` with a view of the [Hugr] held by the [Const] node `node`, or
+    /// returns `None` if `node` does not hold a [Value::Function].
+    fn with_const_function<R>(&self, node: Node, f: impl FnOnce(&Self) -> R) -> Option<R>;
+}
+
+impl ConstFunctionView for Hugr {
+    fn with_const_function<R>(&self, node: Node, f: impl FnOnce(&Self) -> R) -> Option<R> {
+        match self.get_optype(node).as_const()?.value() {
+            Value::Function { hugr } => Some(f(hugr)),
+            _ => None,
+        }
+    }
+}
+
+impl<'h> ConstFunctionView for &'h Hugr {
+    fn with_const_function<R>(&self, node: Node, f: impl FnOnce(&Self) -> R) -> Option<R> {
+        let outer: &'h Hugr = self;
+        match outer.get_optype(node).as_const()?.value() {
+            Value::Function { hugr } => Some(f(&hugr.as_ref())),
+            _ => None,
+        }
+    }
+}
+
+type EmissionSet = HashSet<FuncInstance>;
+
+/// Functions to be emitted, in the order in which they were discovered.
+type Worklist = VecDeque<FuncInstance>;
 
 /// Emits [HugrView]s into an LLVM [Module].
 pub struct EmitHugr<'c, 'a, H>
@@ -243,10 +426,15 @@ where
     'a: 'c,
 {
     emitted: EmissionSet,
+    instance_counts: HashMap<Node, usize>,
+    max_instances: usize,
     module_context: EmitModuleContext<'c, 'a, H>,
 }
 
-impl<'c, 'a, H: HugrView> EmitHugr<'c, 'a, H> {
+impl<'c, 'a, H: ConstFunctionView> EmitHugr<'c, 'a, H> {
+    /// The default for [Self::with_max_instances].
+    pub const DEFAULT_MAX_INSTANCES: usize = 256;
+
     delegate! {
         to self.module_context {
             /// Returns a reference to the inner [Context].
@@ -268,38 +456,132 @@ impl<'c, 'a, H: HugrView> EmitHugr<'c, 'a, H> {
         assert_eq!(iw_context, &module.get_context());
         Self {
             emitted: Default::default(),
+            instance_counts: Default::default(),
+            max_instances: Self::DEFAULT_MAX_INSTANCES,
             module_context: EmitModuleContext::new(iw_context, module, namer, extensions),
         }
     }
 
+    /// Sets the maximum number of distinct instantiations emitted for any one
+    /// polymorphic [FuncDefn], [Self::DEFAULT_MAX_INSTANCES] by default.
+    ///
+    /// A polymorphic function which calls itself with ever-growing
+    /// [TypeArg]s has infinitely many instantiations. Emission of such a
+    /// function fails once it exceeds this limit.
+    pub fn with_max_instances(mut self, max_instances: usize) -> Self {
+        self.max_instances = max_instances;
+        self
+    }
+
+    /// Sets the [MailBoxMode] with which functions are emitted.
+    ///
+    /// With [MailBoxMode::Ssa] the emitted functions are in SSA form without
+    /// the need to run `mem2reg`.
+    pub fn with_mailbox_mode(mut self, mailbox_mode: MailBoxMode) -> Self {
+        self.module_context = self.module_context.with_mailbox_mode(mailbox_mode);
+        self
+    }
+
+    /// Enables the emission of DWARF debug info, described by `options`.
+    ///
+    /// A single compile unit is created for the [Module], and a subprogram for
+    /// each emitted function. Each instruction is given a location from the
+    /// node from which it was emitted: the line and column of the
+    /// [debug_info::SPAN_METADATA_KEY] metadata of that node if present, and
+    /// otherwise its node index. Codegen extensions need take no action.
+    pub fn with_debug_info(mut self, options: &DebugInfoOptions) -> Self {
+        self.module_context = self.module_context.with_debug_info(options);
+        self
+    }
+
+    /// Sets whether each instruction is tagged with
+    /// [debug_info::NODE_METADATA_KIND] metadata, holding the index and op
+    /// name of the node from which it was emitted. This includes instructions
+    /// emitted by codegen extensions. Instructions not emitted for any node,
+    /// e.g. function returns, are not tagged.
+    ///
+    /// This is independent of [EmitHugr::with_debug_info].
+    pub fn with_node_metadata(mut self, node_metadata: bool) -> Self {
+        self.module_context = self.module_context.with_node_metadata(node_metadata);
+        self
+    }
+
+    /// Emits modules under the
+    /// [QIR base profile](https://github.com/qir-alliance/qir-spec), described
+    /// by `options`. See [qir] for details.
+    ///
+    /// [EmitHugr::emit_module] then emits only the entry point named in
+    /// `options`, which is given the `EntryPoint` attribute and attributes
+    /// recording the numbers of qubits and results it requires. It fails with
+    /// [EmitErrorKind::QirBaseProfile] if the module can't satisfy the profile,
+    /// e.g. if it defines other functions or the entry point contains loops.
+    ///
+    /// Codegen extensions for quantum operations must allocate qubits and
+    /// results through [EmitFuncContext::qir_base_profile].
+    pub fn with_qir_base_profile(mut self, options: &QirBaseProfileOptions) -> Self {
+        self.module_context = self.module_context.with_qir_base_profile(options);
+        self
+    }
+
     /// Emits a FuncDefn into the inner [Module].
     ///
     /// `node` need not be a child of a hugr [Module](hugr::ops::Module), but it will
     /// be emitted as a top-level function in the inner [Module]. Indeed, there
     /// are only top-level functions in LLVM IR.
     ///
-    /// Any child [FuncDefn] will also be emitted.
+    /// Any child [FuncDefn] will also be emitted, as will an instantiation of
+    /// each polymorphic [FuncDefn] for each distinct set of [TypeArg]s with
+    /// which it is called or loaded.
+    ///
+    /// `node` itself must not be polymorphic.
+    ///
+    /// Functions are emitted in the order in which they are discovered, so
+    /// the resulting [Module] is deterministic.
     ///
     /// It is safe to emit the same node multiple times: the second and further
     /// emissions will be no-ops.
     ///
     /// If any LLVM IR declaration which is to be emitted already exists in the
     /// [Module] and it differs from what would be emitted, then we fail.
-    pub fn emit_func(mut self, node: FatNode<'_, FuncDefn, H>) -> Result<Self> {
-        let mut worklist: EmissionSet = [node.node()].into_iter().collect();
-        let pop = |wl: &mut EmissionSet| wl.iter().next().cloned().map(|x| wl.take(&x).unwrap());
-
-        while let Some(next_node) = pop(&mut worklist) {
-            use crate::utils::fat::FatExt as _;
-            let Some(func) = node.hugr().try_fat(next_node) else {
-                panic!(
-                    "emit_func: node in worklist was not a FuncDefn: {:?}",
-                    node.hugr().get_optype(next_node)
-                )
-            };
-            let (new_self, new_tasks) = self.emit_func_impl(func)?;
+    pub fn emit_func(self, node: FatNode<'_, FuncDefn, H>) -> Result<Self, EmitError> {
+        if !node.signature.params().is_empty() {
+            return Err(EmitError {
+                context: ErrorContext::new(node.hugr(), node.node(), None),
+                kind: EmitErrorKind::GenericFunction(format!(
+                    "Cannot emit polymorphic function '{}' without type args",
+                    node.name
+                )),
+            });
+        }
+        self.emit_func_instance(node, vec![])
+    }
+
+    fn emit_func_instance(
+        self,
+        node: FatNode<'_, FuncDefn, H>,
+        type_args: Vec<TypeArg>,
+    ) -> Result<Self, EmitError> {
+        self.emit_worklist(node.hugr(), [(node.node(), type_args)].into())
+    }
+
+    /// Emits each function in `worklist`, and each function discovered while
+    /// doing so, in the order in which they are discovered.
+    fn emit_worklist(mut self, hugr: &H, mut worklist: Worklist) -> Result<Self, EmitError> {
+        while let Some((next_node, type_args)) = worklist.pop_front() {
+            let (new_self, new_tasks) = match hugr.get_optype(next_node) {
+                OpType::FuncDefn(fd) => {
+                    self.emit_func_impl(FatNode::new(hugr, next_node, fd), type_args)
+                }
+                OpType::Const(konst) => {
+                    self.emit_const_func_impl(FatNode::new(hugr, next_node, konst))
+                }
+                optype => {
+                    panic!("emit_func: node in worklist was not a FuncDefn or Const: {optype:?}")
+                }
+            }
+            .map_err(|err| EmitError::new(ErrorContext::new(hugr, next_node, None), err))?;
             self = new_self;
-            worklist.extend(new_tasks.into_iter());
+            worklist.extend(new_tasks);
         }
         Ok(self)
     }
@@ -310,9 +592,29 @@ impl<'c, 'a, H: HugrView> EmitHugr<'c, 'a, H> {
     /// and [hugr::ops::FuncDecl] nodes are not emitted directly, but instead by
     /// emission of ops with static edges from them. So [FuncDefn] are the only
     /// interesting children.
-    pub fn emit_module(mut self, node: FatNode<'_, hugr::ops::Module, H>) -> Result<Self> {
+    ///
+    /// Polymorphic [FuncDefn]s are not emitted directly, but are instantiated
+    /// as they are called or loaded from other functions.
+    ///
+    /// See [EmitHugr::with_qir_base_profile] for emission under the QIR base
+    /// profile.
+    pub fn emit_module(
+        mut self,
+        node: FatNode<'_, hugr::ops::Module, H>,
+    ) -> Result<Self, EmitError> {
+        let module_error = |err: anyhow::Error| {
+            EmitError::new(ErrorContext::new(node.hugr(), node.node(), None), err)
+        };
+        let qir_base_profile = self.module_context.qir_base_profile();
+        let entry_point = qir_base_profile
+            .as_ref()
+            .map(|profile| profile.find_entry_point(node))
+            .transpose()
+            .map_err(module_error)?;
         for c in node.children() {
             match c.as_ref() {
+                // Polymorphic FuncDefns are emitted on demand.
+                OpType::FuncDefn(ref fd) if !fd.signature.params().is_empty() => (),
                 OpType::FuncDefn(ref fd) => {
                     let fat_ot = c.into_ot(fd);
                     self = self.emit_func(fat_ot)?;
@@ -321,44 +623,135 @@ impl<'c, 'a, H: HugrView> EmitHugr<'c, 'a, H> {
                 OpType::FuncDecl(_) => (),
                 // Consts are allowed, but we don't need to do anything here.
                 OpType::Const(_) => (),
-                _ => Err(anyhow!("Module has invalid child: {c}"))?,
+                _ => Err(EmitError {
+                    context: ErrorContext::new(c.hugr(), c.node(), None),
+                    kind: EmitErrorKind::InvalidHierarchy(format!("Module has invalid child: {c}")),
+                })?,
             }
         }
+        if let Some((profile, entry_point)) = qir_base_profile.zip(entry_point) {
+            let func = self
+                .module_context
+                .get_func_defn(entry_point)
+                .map_err(module_error)?;
+            profile
+                .finish_entry_point(&self.module_context, entry_point, func)
+                .map_err(module_error)?;
+        }
         Ok(self)
     }
 
-    fn emit_func_impl(mut self, node: FatNode<'_, FuncDefn, H>) -> Result<(Self, EmissionSet)> {
-        if !self.emitted.insert(node.node()) {
-            return Ok((self, EmissionSet::default()));
+    fn emit_func_impl(
+        mut self,
+        node: FatNode<'_, FuncDefn, H>,
+        type_args: Vec<TypeArg>,
+    ) -> Result<(Self, Worklist)> {
+        // A polymorphic FuncDefn reached without type args, e.g. as a child
+        // of another function, is only emitted when instantiated.
+        if type_args.is_empty() && !node.signature.params().is_empty() {
+            return Ok((self, Worklist::default()));
         }
-        let func = self.module_context.get_func_defn(node)?;
-        let mut func_ctx = EmitFuncContext::new(self.module_context, func)?;
-        let ret_rmb = func_ctx.new_row_mail_box(node.signature.body().output.iter(), "ret")?;
-        ops::emit_dataflow_parent(
-            &mut func_ctx,
-            EmitOpArgs {
-                node,
-                inputs: func.get_params(),
-                outputs: ret_rmb.promise(),
-            },
-        )?;
-        let builder = func_ctx.builder();
-        match &ret_rmb.read::<Vec<_>>(builder, [])?[..] {
-            [] => builder.build_return(None)?,
-            [x] => builder.build_return(Some(x))?,
-            xs => builder.build_aggregate_return(xs)?,
-        };
-        let (mctx, todos) = func_ctx.finish()?;
+        let instance = (node.node(), type_args.clone());
+        if !self.emitted.insert(instance) {
+            return Ok((self, Worklist::default()));
+        }
+        let count = self.instance_counts.entry(node.node()).or_default();
+        *count += 1;
+        if *count > self.max_instances {
+            Err(EmitErrorKind::GenericFunction(format!(
+                "Function '{}' has more than {} instantiations; \
+                is it polymorphically recursive?",
+                node.name, self.max_instances
+            )))?
+        }
+        let func = self
+            .module_context
+            .get_func_defn_instance(node, &type_args)?;
+        if let Some(debug_info) = self.module_context.debug_info() {
+            debug_info.attach_subprogram(func, &node.name, node.hugr(), node.node());
+        }
+        let func_ctx = EmitFuncContext::new(self.module_context, func)?.with_type_args(type_args);
+        let (mctx, todos) = emit_func_body(func_ctx, func, node, &node.signature.body().output)?;
         self.module_context = mctx;
         Ok((self, todos))
     }
 
+    fn emit_const_func_impl(mut self, node: FatNode<'_, Const, H>) -> Result<(Self, Worklist)> {
+        let instance = (node.node(), vec![]);
+        if !self.emitted.insert(instance) {
+            return Ok((self, Worklist::default()));
+        }
+        let func = self.module_context.get_const_func(node)?;
+        if let Some(debug_info) = self.module_context.debug_info() {
+            let name = func.get_name().to_string_lossy().into_owned();
+            debug_info.attach_subprogram(func, &name, node.hugr(), node.node());
+        }
+
+        // The nodes of the nested HUGR are unrelated to those of our HUGR, so
+        // the functions emitted from it are named within a scope unique to
+        // this constant, and tracked separately.
+        let namer = self.module_context.namer.clone();
+        let scope = format!("const_fun_{}.", node.node().index());
+        self.module_context.namer = Rc::new(namer.scoped(scope));
+        let emitted = std::mem::take(&mut self.emitted);
+        let instance_counts = std::mem::take(&mut self.instance_counts);
+        self = node
+            .hugr()
+            .with_const_function(node.node(), |nested_hugr| {
+                let func_ty = nested_hugr.inner_function_type().unwrap();
+                let func_ctx = EmitFuncContext::new(self.module_context, func)?;
+                let root = nested_hugr.fat_optype(nested_hugr.root());
+                let (mctx, todos) = emit_func_body(func_ctx, func, root, &func_ty.output)?;
+                self.module_context = mctx;
+                // Any scoped definitions in the nested HUGR must be emitted
+                // from that HUGR.
+                Ok::<_, anyhow::Error>(self.emit_worklist(nested_hugr, todos)?)
+            })
+            .ok_or(anyhow!(
+                "Const node does not hold a function: {}",
+                node.node()
+            ))??;
+        self.emitted = emitted;
+        self.instance_counts = instance_counts;
+        self.module_context.namer = namer;
+        Ok((self, Worklist::default()))
+    }
+
     /// Consumes the `EmitHugr` and returns the internal [Module].
     pub fn finish(self) -> Module<'c> {
         self.module_context.finish()
     }
 }
 
+/// Emit the body of `func` from the dataflow parent `node`, whose outputs
+/// have types `output_types`, and return from `func`.
+fn emit_func_body<'c, 'a, OT: OpTrait, H: HugrView>(
+    mut func_ctx: EmitFuncContext<'c, 'a, H>,
+    func: FunctionValue<'c>,
+    node: FatNode<'_, OT, H>,
+    output_types: &TypeRow,
+) -> Result<(EmitModuleContext<'c, 'a, H>, Worklist)>
+where
+    for<'b> &'b OpType: TryInto<&'b OT>,
+{
+    let ret_rmb = func_ctx.new_row_mail_box(output_types.iter(), "ret")?;
+    ops::emit_dataflow_parent(
+        &mut func_ctx,
+        EmitOpArgs {
+            node,
+            inputs: func.get_params(),
+            outputs: ret_rmb.promise(),
+        },
+    )?;
+    let builder = func_ctx.builder();
+    match &ret_rmb.read::<Vec<_>>(builder, [])?[..] {
+        [] => builder.build_return(None)?,
+        [x] => builder.build_return(Some(x))?,
+        xs => builder.build_aggregate_return(xs)?,
+    };
+    func_ctx.finish()
+}
+
 /// Extract all return values from the result of a `call`.
 ///
 /// LLVM only supports functions with exactly zero or one return value.
@@ -0,0 +1,299 @@
+//! Support for emitting DWARF debug info, relating emitted LLVM instructions
+//! to the HUGR nodes from which they were emitted.
+//!
+//! See [crate::emit::EmitHugr::with_debug_info] and
+//! [crate::emit::EmitHugr::with_node_metadata].
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use hugr::{HugrView, Node, NodeIndex as _};
+use inkwell::{
+    basic_block::BasicBlock,
+    builder::Builder,
+    context::Context,
+    debug_info::{
+        debug_metadata_version, AsDIScope, DICompileUnit, DIFlags, DIFlagsConstants, DILocation,
+        DIScope, DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+    },
+    module::{FlagBehavior, Linkage, Module},
+    values::{FunctionValue, InstructionOpcode, InstructionValue},
+};
+
+/// The key of the HUGR node metadata from which we read source locations.
+///
+/// The metadata is expected to be an object with integer fields `line` and
+/// (optionally) `column`, e.g. `{"line": 3, "column": 7}`. Nodes without such
+/// metadata are given a location with their node index as line number.
+pub const SPAN_METADATA_KEY: &str = "span";
+
+/// Options for emitting DWARF debug info.
+#[derive(Clone, Debug)]
+pub struct DebugInfoOptions {
+    /// The name of the file recorded in the compile unit.
+    pub filename: String,
+    /// The directory recorded in the compile unit.
+    pub directory: String,
+    /// The producer recorded in the compile unit.
+    pub producer: String,
+}
+
+impl Default for DebugInfoOptions {
+    fn default() -> Self {
+        Self {
+            filename: "hugr".into(),
+            directory: ".".into(),
+            producer: "hugr-llvm".into(),
+        }
+    }
+}
+
+/// Holds a [DebugInfoBuilder] and the single [DICompileUnit] of a
+/// [Module].
+pub struct DebugInfo<'c> {
+    builder: DebugInfoBuilder<'c>,
+    compile_unit: DICompileUnit<'c>,
+}
+
+impl<'c> DebugInfo<'c> {
+    /// Creates a new compile unit in `module`.
+    pub fn new(iw_context: &'c Context, module: &Module<'c>, options: &DebugInfoOptions) -> Self {
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            iw_context
+                .i32_type()
+                .const_int(debug_metadata_version() as u64, false),
+        );
+        let (builder, compile_unit) = module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &options.filename,
+            &options.directory,
+            &options.producer,
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        Self {
+            builder,
+            compile_unit,
+        }
+    }
+
+    /// Creates a subprogram for `func`, which was emitted from `node`, and
+    /// attaches it to `func`.
+    pub fn attach_subprogram(
+        &self,
+        func: FunctionValue<'c>,
+        name: &str,
+        hugr: &impl HugrView,
+        node: Node,
+    ) {
+        let file = self.compile_unit.get_file();
+        let (line, _) = node_line_column(hugr, node);
+        let subroutine_type = self
+            .builder
+            .create_subroutine_type(file, None, &[], DIFlags::PUBLIC);
+        let subprogram = self.builder.create_function(
+            self.compile_unit.as_debug_info_scope(),
+            name,
+            func.get_name().to_str().ok(),
+            file,
+            line,
+            subroutine_type,
+            func.get_linkage() == Linkage::Private,
+            true,
+            line,
+            DIFlags::PUBLIC,
+            false,
+        );
+        func.set_subprogram(subprogram);
+    }
+
+    /// Returns a [DILocation] in `scope` at `line` and `column`.
+    pub fn location(
+        &self,
+        iw_context: &'c Context,
+        scope: DIScope<'c>,
+        line: u32,
+        column: u32,
+    ) -> DILocation<'c> {
+        self.builder
+            .create_debug_location(iw_context, line, column, scope, None)
+    }
+
+    /// Returns a [DILocation] in `scope` for `node`. This is taken from the
+    /// [SPAN_METADATA_KEY] metadata of `node` if present, and otherwise has
+    /// the node index as line number.
+    pub fn node_location(
+        &self,
+        iw_context: &'c Context,
+        scope: DIScope<'c>,
+        hugr: &impl HugrView,
+        node: Node,
+    ) -> DILocation<'c> {
+        let (line, column) = node_line_column(hugr, node);
+        self.location(iw_context, scope, line, column)
+    }
+
+    /// Finalizes the debug info. This must be called before the [Module] is
+    /// verified.
+    pub fn finalize(&self) {
+        self.builder.finalize()
+    }
+}
+
+fn node_line_column(hugr: &impl HugrView, node: Node) -> (u32, u32) {
+    let field = |span: &hugr::hugr::NodeMetadata, name| {
+        span.get(name)
+            .and_then(|x| x.as_u64())
+            .and_then(|x| x.try_into().ok())
+    };
+    hugr.get_metadata(node, SPAN_METADATA_KEY)
+        .and_then(|span| Some((field(span, "line")?, field(span, "column").unwrap_or(0))))
+        .unwrap_or((node.index() as u32, 0))
+}
+
+/// The kind of the LLVM metadata which, when [node metadata] is enabled,
+/// relates each instruction to the HUGR node from which it was emitted.
+///
+/// The metadata is a tuple of the node index and the name of its op, e.g.
+/// `!hugr.node !{i64 7, !"arithmetic.int.iadd"}`.
+///
+/// [node metadata]: crate::emit::EmitHugr::with_node_metadata
+pub const NODE_METADATA_KIND: &str = "hugr.node";
+
+/// The basic blocks of a function, in the order in which they were found.
+///
+/// Blocks created through [crate::emit::func::EmitFuncContext] are recorded
+/// as they are created. Blocks created directly with inkwell, e.g. by
+/// [crate::sum::LLVMSumValue::build_destructure], are found by
+/// [attach_node_metadata] after the blocks in which they are laid out.
+#[derive(Debug, Default)]
+pub(crate) struct BlockLog<'c> {
+    blocks: Vec<BasicBlock<'c>>,
+    known: HashSet<BasicBlock<'c>>,
+}
+
+impl<'c> BlockLog<'c> {
+    /// Records `block`, if it has not already been recorded.
+    pub(crate) fn record(&mut self, block: BasicBlock<'c>) {
+        if self.known.insert(block) {
+            self.blocks.push(block);
+        }
+    }
+
+    /// Records the unrecorded blocks laid out directly after `block`.
+    fn record_successors(&mut self, block: BasicBlock<'c>) {
+        let mut next = block.get_next_basic_block();
+        while let Some(succ) = next.filter(|b| !self.known.contains(b)) {
+            self.record(succ);
+            next = succ.get_next_basic_block();
+        }
+    }
+}
+
+/// A position in a function before a node is emitted, after which
+/// [attach_node_metadata] looks for the instructions emitted for that node.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct InstructionMark<'c> {
+    prologue: BasicBlock<'c>,
+    prologue_last: Option<InstructionValue<'c>>,
+    block: Option<BasicBlock<'c>>,
+    block_last: Option<InstructionValue<'c>>,
+    blocks: usize,
+}
+
+impl<'c> InstructionMark<'c> {
+    /// Marks the current end of `prologue`, the current position of
+    /// `builder`, and the blocks recorded so far in `blocks`.
+    pub(crate) fn new(
+        prologue: BasicBlock<'c>,
+        builder: &Builder<'c>,
+        blocks: &BlockLog<'c>,
+    ) -> Self {
+        let block = builder.get_insert_block();
+        Self {
+            prologue,
+            prologue_last: prologue.get_last_instruction(),
+            block,
+            block_last: block.and_then(|b| b.get_last_instruction()),
+            blocks: blocks.blocks.len(),
+        }
+    }
+}
+
+/// Attaches [NODE_METADATA_KIND] metadata for `node`, whose op is named
+/// `op_name`, to the instructions emitted since `mark` which do not already
+/// have it.
+///
+/// This is called once each node has been emitted. Instructions emitted for
+/// the children of `node` have already been tagged with those children, so
+/// those that remain were emitted for `node` itself. Instructions are emitted
+/// after the builder's position, into the prologue, and into blocks created
+/// since `mark`. The only instructions inserted before the builder's position
+/// are `phi`s at the start of its block. Blocks which existed before `mark`,
+/// such as the exit block of a parent of `node`, are not otherwise visited.
+pub(crate) fn attach_node_metadata<'c>(
+    iw_context: &'c Context,
+    blocks: &mut BlockLog<'c>,
+    mark: InstructionMark<'c>,
+    node: Node,
+    op_name: &str,
+) -> Result<()> {
+    let kind_id = iw_context.get_kind_id(NODE_METADATA_KIND);
+    let metadata = iw_context.metadata_node(&[
+        iw_context
+            .i64_type()
+            .const_int(node.index() as u64, false)
+            .into(),
+        iw_context.metadata_string(op_name).into(),
+    ]);
+    let attach = |first: Option<InstructionValue<'c>>,
+                  stop: fn(&InstructionValue<'c>) -> bool|
+     -> Result<()> {
+        let mut next = first;
+        while let Some(instruction) = next.filter(|i| !stop(i)) {
+            if instruction.get_metadata(kind_id).is_none() {
+                instruction
+                    .set_metadata(metadata, kind_id)
+                    .map_err(|e| anyhow!("Failed to attach node metadata: {e}"))?;
+            }
+            next = instruction.get_next_instruction();
+        }
+        Ok(())
+    };
+    let after = |block: BasicBlock<'c>, last: Option<InstructionValue<'c>>| match last {
+        Some(last) => last.get_next_instruction(),
+        None => block.get_first_instruction(),
+    };
+
+    if mark.block != Some(mark.prologue) {
+        attach(after(mark.prologue, mark.prologue_last), |_| false)?;
+    }
+    if let Some(block) = mark.block {
+        if mark.block_last.is_some() {
+            attach(block.get_first_instruction(), |i| {
+                i.get_opcode() != InstructionOpcode::Phi
+            })?;
+        }
+        attach(after(block, mark.block_last), |_| false)?;
+        blocks.record_successors(block);
+    }
+    let mut i = mark.blocks;
+    while let Some(&block) = blocks.blocks.get(i) {
+        blocks.record_successors(block);
+        if Some(block) != mark.block {
+            attach(block.get_first_instruction(), |_| false)?;
+        }
+        i += 1;
+    }
+    Ok(())
+}
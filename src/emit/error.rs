@@ -0,0 +1,119 @@
+//! Structured errors for failures to emit a HUGR.
+//!
+//! [EmitHugr](super::EmitHugr) returns [EmitError]s, which identify the node
+//! which failed to emit and the [EmitErrorKind] of the failure. Codegen
+//! extensions may fail with arbitrary [anyhow::Error]s, which become
+//! [EmitErrorKind::Other] unless they are [EmitErrorKind]s:
+//!
+//! ```
+//! # use hugr_llvm::emit::{EmitError, EmitErrorKind};
+//! fn unsupported_op(err: &EmitError) -> Option<&str> {
+//!     match &err.kind {
+//!         EmitErrorKind::UnsupportedOp(op) => Some(op),
+//!         _ => None,
+//!     }
+//! }
+//! ```
+use std::fmt;
+
+use hugr::{ops::NamedOp as _, HugrView, Node};
+use thiserror::Error;
+
+/// The kind of an [EmitError].
+///
+/// Emission code raises these, converted to [anyhow::Error]s, for failures
+/// which users may wish to distinguish. They are converted into [EmitError]s
+/// as they propagate out of the emission of a node.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum EmitErrorKind {
+    /// No codegen extension could emit an op.
+    #[error("Unsupported op: {0}")]
+    UnsupportedOp(String),
+    /// No codegen extension could convert a type.
+    #[error("Unsupported type: {0}")]
+    UnsupportedType(String),
+    /// No codegen extension could load a constant.
+    #[error("Unsupported constant: {0}")]
+    UnsupportedConst(String),
+    /// A polymorphic function was used without, or with the wrong number of,
+    /// type args.
+    #[error("Generic function: {0}")]
+    GenericFunction(String),
+    /// The hierarchy of the HUGR was not as expected.
+    #[error("Invalid hierarchy: {0}")]
+    InvalidHierarchy(String),
+    /// The HUGR can't be emitted under the QIR base profile, see
+    /// [super::EmitHugr::with_qir_base_profile].
+    #[error("Not supported by the QIR base profile: {0}")]
+    QirBaseProfile(String),
+    /// Any other failure, e.g. from a codegen extension or from LLVM.
+    #[error("{0:#}")]
+    Other(anyhow::Error),
+}
+
+/// The node at which an [EmitError] occurred.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// The node which failed to emit.
+    pub node: Node,
+    /// The name of the op of `node`.
+    pub op_name: String,
+    /// The name of the LLVM function into which `node` was being emitted, if
+    /// any.
+    pub func: Option<String>,
+}
+
+impl ErrorContext {
+    /// Creates an `ErrorContext` for `node` in `hugr`, which was being emitted
+    /// into the LLVM function named `func`, if any.
+    pub fn new(hugr: &impl HugrView, node: Node, func: Option<String>) -> Self {
+        Self {
+            node,
+            op_name: hugr.get_optype(node).name().to_string(),
+            func,
+        }
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to emit {} node {}", self.op_name, self.node)?;
+        if let Some(func) = &self.func {
+            write!(f, " in function '{func}'")?;
+        }
+        Ok(())
+    }
+}
+
+/// A failure to emit a node.
+#[derive(Debug, Error)]
+#[error("{context}: {kind}")]
+pub struct EmitError {
+    /// The node which failed to emit.
+    pub context: ErrorContext,
+    /// What went wrong.
+    pub kind: EmitErrorKind,
+}
+
+impl EmitError {
+    /// Associates `err` with `context`.
+    ///
+    /// If `err` is already an [EmitError] it is returned unchanged, since it
+    /// refers to a more specific node. If it is an [EmitErrorKind] it is used
+    /// as the kind of the result. Otherwise the kind is
+    /// [EmitErrorKind::Other].
+    pub fn new(context: ErrorContext, err: anyhow::Error) -> Self {
+        let err = match err.downcast::<EmitError>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        let kind = err.downcast().unwrap_or_else(EmitErrorKind::Other);
+        Self { context, kind }
+    }
+
+    /// Returns the node which failed to emit.
+    pub fn node(&self) -> Node {
+        self.context.node
+    }
+}
@@ -2,30 +2,36 @@ use std::{collections::HashMap, rc::Rc};
 
 use anyhow::{anyhow, Result};
 use hugr::{
-    ops::{constant::CustomConst, ExtensionOp, FuncDecl, FuncDefn},
-    types::Type,
-    HugrView, NodeIndex, PortIndex, Wire,
+    ops::{constant::CustomConst, Const, ExtensionOp, FuncDecl, FuncDefn, NamedOp as _, OpType},
+    types::{Type, TypeArg},
+    HugrView, Node, NodeIndex, PortIndex, Wire,
 };
 use inkwell::{
     basic_block::BasicBlock,
     builder::Builder,
     context::Context,
+    debug_info::{AsDIScope as _, DIScope},
     module::Module,
     types::{BasicType, BasicTypeEnum, FunctionType},
     values::{BasicValueEnum, FunctionValue, GlobalValue},
 };
-use itertools::zip_eq;
+use itertools::{zip_eq, Itertools as _};
 
 use crate::types::{HugrFuncType, HugrSumType, HugrType, TypingSession};
+use crate::utils::substitute::substitute_type_arg;
 use crate::{custom::CodegenExtsMap, types::LLVMSumType, utils::fat::FatNode};
 use delegate::delegate;
 
 use self::mailbox::ValueMailBox;
 
-use super::{EmissionSet, EmitModuleContext, EmitOpArgs};
+use super::{
+    debug_info::{attach_node_metadata, BlockLog, InstructionMark},
+    qir::QirBaseProfile,
+    EmitError, EmitModuleContext, EmitOpArgs, ErrorContext, Worklist,
+};
 
 mod mailbox;
-pub use mailbox::{RowMailBox, RowPromise};
+pub use mailbox::{MailBoxMode, RowMailBox, RowPromise};
 
 /// A context for emitting an LLVM function.
 ///
@@ -40,20 +46,26 @@ pub use mailbox::{RowMailBox, RowPromise};
 /// [BasicBlock]. This invariant is not checked when the builder is accessed
 /// through [EmitFuncContext::builder].
 ///
-/// [MailBox](RowMailBox)es are stack allocations that are `alloca`ed in the
-/// first basic block of the function, read from to get the input values of each
-/// node, and written to with the output values of each node.
+/// [MailBox](RowMailBox)es are read from to get the input values of each node,
+/// and written to with the output values of each node. In the default
+/// [MailBoxMode::Alloca] they are stack allocations that are `alloca`ed in the
+/// first basic block of the function. In [MailBoxMode::Ssa] they carry SSA
+/// values directly.
 pub struct EmitFuncContext<'c, 'a, H>
 where
     'a: 'c,
 {
     emit_context: EmitModuleContext<'c, 'a, H>,
-    todo: EmissionSet,
+    todo: Worklist,
+    type_args: Rc<[TypeArg]>,
     func: FunctionValue<'c>,
     env: HashMap<Wire, ValueMailBox<'c>>,
     builder: Builder<'c>,
     prologue_bb: BasicBlock<'c>,
     launch_bb: BasicBlock<'c>,
+    debug_scope: Option<DIScope<'c>>,
+    current_node: Option<Node>,
+    blocks: BlockLog<'c>,
 }
 
 impl<'c, 'a, H: HugrView> EmitFuncContext<'c, 'a, H> {
@@ -63,14 +75,18 @@ impl<'c, 'a, H: HugrView> EmitFuncContext<'c, 'a, H> {
             pub fn iw_context(&self) ->  &'c Context;
             /// Returns the internal [CodegenExtsMap] .
             pub fn extensions(&self) ->  Rc<CodegenExtsMap<'a,H>>;
-            /// Returns a new [TypingSession].
-            pub fn typing_session(&self) -> TypingSession<'c, 'a>;
+        }
+
+        to self.typing_session() {
             /// Convert hugr [HugrType] into an LLVM [Type](BasicTypeEnum).
             pub fn llvm_type(&self, hugr_type: &HugrType) -> Result<BasicTypeEnum<'c> >;
             /// Convert a [HugrFuncType] into an LLVM [FunctionType].
             pub fn llvm_func_type(&self, hugr_type: &HugrFuncType) -> Result<FunctionType<'c> >;
             /// Convert a hugr [HugrSumType] into an LLVM [LLVMSumType].
             pub fn llvm_sum_type(&self, sum_type: HugrSumType) -> Result<LLVMSumType<'c>>;
+        }
+
+        to self.emit_context {
             /// Adds or gets the [FunctionValue] in the [inkwell::module::Module] corresponding to the given [FuncDefn].
             ///
             /// The name of the result may have been mangled.
@@ -102,10 +118,92 @@ impl<'c, 'a, H: HugrView> EmitFuncContext<'c, 'a, H> {
         }
     }
 
+    /// Returns a new [TypingSession], which substitutes the [TypeArg]s of the
+    /// function being emitted for type variables.
+    pub fn typing_session(&self) -> TypingSession<'c, 'a> {
+        self.emit_context
+            .typing_session()
+            .with_type_args(self.type_args.clone())
+    }
+
+    /// Returns the [TypeArg]s with which the function being emitted is
+    /// instantiated. These are empty unless the function is polymorphic.
+    pub fn type_args(&self) -> &[TypeArg] {
+        &self.type_args
+    }
+
+    /// Sets the [TypeArg]s with which the function being emitted is
+    /// instantiated. These are substituted for type variables in all
+    /// conversions of types.
+    pub fn with_type_args(self, type_args: impl Into<Rc<[TypeArg]>>) -> Self {
+        Self {
+            type_args: type_args.into(),
+            ..self
+        }
+    }
+
     /// Used when emitters encounter a scoped definition. `node` will be
     /// returned from [EmitFuncContext::finish].
     pub fn push_todo_func(&mut self, node: FatNode<'_, FuncDefn, H>) {
-        self.todo.insert(node.node());
+        self.push_todo(node.node(), vec![]);
+    }
+
+    fn push_todo(&mut self, node: Node, type_args: Vec<TypeArg>) {
+        self.todo.push_back((node, type_args));
+    }
+
+    /// Adds or gets the [FunctionValue] in the [inkwell::module::Module]
+    /// corresponding to the instantiation of the given [FuncDefn] with
+    /// `type_args`.
+    ///
+    /// `type_args` may refer to the type variables of the function being
+    /// emitted; these are substituted first. The instantiation is returned
+    /// from [EmitFuncContext::finish], to be emitted later.
+    pub fn get_func_defn_instance(
+        &mut self,
+        node: FatNode<'_, FuncDefn, H>,
+        type_args: &[TypeArg],
+    ) -> Result<FunctionValue<'c>> {
+        let type_args = self.substitute_type_args(type_args)?;
+        let func = self.emit_context.get_func_defn_instance(node, &type_args)?;
+        if !type_args.is_empty() {
+            self.push_todo(node.node(), type_args);
+        }
+        Ok(func)
+    }
+
+    /// Adds or gets the [FunctionValue] in the [inkwell::module::Module]
+    /// corresponding to the instantiation of the given [FuncDecl] with
+    /// `type_args`.
+    ///
+    /// `type_args` may refer to the type variables of the function being
+    /// emitted; these are substituted first.
+    pub fn get_func_decl_instance(
+        &self,
+        node: FatNode<'_, FuncDecl, H>,
+        type_args: &[TypeArg],
+    ) -> Result<FunctionValue<'c>> {
+        let type_args = self.substitute_type_args(type_args)?;
+        self.emit_context.get_func_decl_instance(node, &type_args)
+    }
+
+    /// Adds or gets the private [FunctionValue] in the
+    /// [inkwell::module::Module] corresponding to the given [Const] node, which
+    /// must hold a [hugr::ops::Value::Function].
+    ///
+    /// The function will be emitted later, and so `node` will be returned from
+    /// [EmitFuncContext::finish].
+    pub fn get_const_func(&mut self, node: FatNode<'_, Const, H>) -> Result<FunctionValue<'c>> {
+        let func = self.emit_context.get_const_func(node)?;
+        self.push_todo(node.node(), vec![]);
+        Ok(func)
+    }
+
+    fn substitute_type_args(&self, type_args: &[TypeArg]) -> Result<Vec<TypeArg>> {
+        type_args
+            .iter()
+            .map(|ta| substitute_type_arg(ta, &self.type_args))
+            .collect()
     }
 
     /// Returns the internal [Builder]. Callers must ensure that it is
@@ -123,12 +221,17 @@ impl<'c, 'a, H: HugrView> EmitFuncContext<'c, 'a, H> {
         name: impl AsRef<str>,
         before: Option<BasicBlock<'c>>,
     ) -> BasicBlock<'c> {
-        if let Some(before) = before {
+        let block = if let Some(before) = before {
             self.iw_context().prepend_basic_block(before, name.as_ref())
         } else {
             self.iw_context()
                 .append_basic_block(self.func, name.as_ref())
+        };
+        self.blocks.record(block);
+        if let Some((profile, node)) = self.qir_base_profile().zip(self.current_node) {
+            profile.record_block(block, node);
         }
+        block
     }
 
     fn prologue_block(&self) -> BasicBlock<'c> {
@@ -159,28 +262,71 @@ impl<'c, 'a, H: HugrView> EmitFuncContext<'c, 'a, H> {
         let launch_bb = emit_context
             .iw_context()
             .append_basic_block(func, "entry_block");
+        let mut blocks = BlockLog::default();
+        blocks.record(prologue_bb);
+        blocks.record(launch_bb);
         let builder = emit_context.iw_context().create_builder();
         builder.position_at_end(launch_bb);
+        // When emitting debug info, every instruction in a function with a
+        // subprogram must have a location. Instructions not emitted from any
+        // node are placed at line 0.
+        let debug_scope =
+            emit_context
+                .debug_info()
+                .zip(func.get_subprogram())
+                .map(|(debug_info, subprogram)| {
+                    let scope = subprogram.as_debug_info_scope();
+                    builder.set_current_debug_location(debug_info.location(
+                        emit_context.iw_context(),
+                        scope,
+                        0,
+                        0,
+                    ));
+                    scope
+                });
         Ok(Self {
             emit_context,
             todo: Default::default(),
+            type_args: Rc::new([]),
             func,
             env: Default::default(),
             builder,
             prologue_bb,
             launch_bb,
+            debug_scope,
+            current_node: None,
+            blocks,
         })
     }
 
-    fn new_value_mail_box(&mut self, t: &Type, name: impl AsRef<str>) -> Result<ValueMailBox<'c>> {
+    /// Create a new [ValueMailBox]. In [MailBoxMode::Ssa], a mailbox that is
+    /// to be written from several blocks must be a `join` mailbox.
+    fn new_value_mail_box(
+        &mut self,
+        t: &Type,
+        name: impl AsRef<str>,
+        join: bool,
+    ) -> Result<ValueMailBox<'c>> {
         let bte = self.llvm_type(t)?;
-        let ptr = self.build_prologue(|builder| builder.build_alloca(bte, name.as_ref()))?;
-        Ok(ValueMailBox::new(bte, ptr, Some(name.as_ref().into())))
+        let name = name.as_ref();
+        Ok(match (self.mailbox_mode(), join) {
+            (MailBoxMode::Alloca, _) => {
+                let ptr = self.build_prologue(|builder| builder.build_alloca(bte, name))?;
+                ValueMailBox::new(bte, ptr, Some(name.into()))
+            }
+            (MailBoxMode::Ssa, false) => ValueMailBox::new_value(bte, Some(name.into())),
+            (MailBoxMode::Ssa, true) => ValueMailBox::new_phi(bte, Some(name.into())),
+        })
     }
 
     /// Create a new anonymous [RowMailBox]. This mailbox is not mapped to any
     /// [Wire]s, and so will not interact with any mailboxes returned from
     /// [EmitFuncContext::node_ins_rmb] or [EmitFuncContext::node_outs_rmb].
+    ///
+    /// The mailbox may be written from several blocks before being read. In
+    /// [MailBoxMode::Ssa] each of those blocks must either be a predecessor
+    /// of the block in which the mailbox is read, in which case the values are
+    /// joined by a `phi`, or that block itself.
     pub fn new_row_mail_box<'t>(
         &mut self,
         ts: impl IntoIterator<Item = &'t Type>,
@@ -189,13 +335,28 @@ impl<'c, 'a, H: HugrView> EmitFuncContext<'c, 'a, H> {
         Ok(RowMailBox::new(
             ts.into_iter()
                 .enumerate()
-                .map(|(i, t)| self.new_value_mail_box(t, format!("{i}")))
+                .map(|(i, t)| self.new_value_mail_box(t, format!("{i}"), true))
                 .collect::<Result<Vec<_>>>()?,
             Some(name.as_ref().into()),
         ))
     }
 
-    fn build_prologue<T>(&mut self, f: impl FnOnce(&Builder<'c>) -> T) -> T {
+    /// Returns the [MailBoxMode] with which values are passed between nodes.
+    pub fn mailbox_mode(&self) -> MailBoxMode {
+        self.emit_context.mailbox_mode()
+    }
+
+    /// Returns the [QirBaseProfile] under which the module is emitted, if
+    /// any. See [crate::emit::EmitHugr::with_qir_base_profile].
+    pub fn qir_base_profile(&self) -> Option<Rc<QirBaseProfile>> {
+        self.emit_context.qir_base_profile()
+    }
+
+    /// Calls `f` with the internal builder positioned at the end of the
+    /// prologue block of the function, which is executed once per call. This
+    /// is where `alloca`s belong, so that they do not grow the stack when
+    /// emitted in a loop.
+    pub fn build_prologue<T>(&mut self, f: impl FnOnce(&Builder<'c>) -> T) -> T {
         let b = self.prologue_block();
         self.build_positioned(b, |x| f(&x.builder))
     }
@@ -251,6 +412,48 @@ impl<'c, 'a, H: HugrView> EmitFuncContext<'c, 'a, H> {
         Ok(r)
     }
 
+    /// Returns a [RowMailBox] to receive the inputs of the dataflow parent
+    /// whose [hugr::ops::Input] node is `node`, when these are written from
+    /// several blocks, e.g. on entry to a loop body or CFG block.
+    ///
+    /// In [MailBoxMode::Alloca] this is [EmitFuncContext::node_outs_rmb]. In
+    /// [MailBoxMode::Ssa] it is a new mailbox as from
+    /// [EmitFuncContext::new_row_mail_box], since the output wires of `node`
+    /// may only be written once.
+    pub(crate) fn node_outs_join_rmb<'hugr, OT: 'hugr>(
+        &mut self,
+        node: FatNode<'hugr, OT, H>,
+    ) -> Result<RowMailBox<'c>> {
+        match self.mailbox_mode() {
+            MailBoxMode::Alloca => self.node_outs_rmb(node),
+            MailBoxMode::Ssa => {
+                let types = node.out_value_types().map(|x| x.1).collect_vec();
+                self.new_row_mail_box(types.iter(), format!("{}_in", node.node().index()))
+            }
+        }
+    }
+
+    /// Returns a [RowMailBox] to receive the outputs of the dataflow parent
+    /// whose [hugr::ops::Output] node is `node`, when these are read after
+    /// emitting that parent, e.g. at the end of a loop body or CFG block.
+    ///
+    /// In [MailBoxMode::Alloca] this is [EmitFuncContext::node_ins_rmb]. In
+    /// [MailBoxMode::Ssa] it is a new mailbox as from
+    /// [EmitFuncContext::new_row_mail_box], since the input wires of `node`
+    /// are already written by the nodes from which they come.
+    pub(crate) fn parent_outputs_rmb<'hugr, OT: 'hugr>(
+        &mut self,
+        node: FatNode<'hugr, OT, H>,
+    ) -> Result<RowMailBox<'c>> {
+        match self.mailbox_mode() {
+            MailBoxMode::Alloca => self.node_ins_rmb(node),
+            MailBoxMode::Ssa => {
+                let types = node.in_value_types().map(|x| x.1).collect_vec();
+                self.new_row_mail_box(types.iter(), format!("{}_out", node.node().index()))
+            }
+        }
+    }
+
     /// Returns a [RowMailBox] mapped to thie ouput wires of `node`. When emitting a node
     /// output values are written to this mailbox.
     pub fn node_outs_rmb<'hugr, OT: 'hugr>(
@@ -280,11 +483,68 @@ impl<'c, 'a, H: HugrView> EmitFuncContext<'c, 'a, H> {
         let mb = self.new_value_mail_box(
             hugr_type,
             format!("{}_{}", node.node().index(), port.index()),
+            false,
         )?;
         self.env.insert(wire, mb.clone());
         Ok(mb)
     }
 
+    /// Returns the node currently being emitted, if any.
+    pub fn current_node(&self) -> Option<Node> {
+        self.current_node
+    }
+
+    /// Calls `f` to emit `node`, which is recorded as the
+    /// [EmitFuncContext::current_node] for the duration.
+    ///
+    /// If debug info is enabled, instructions emitted by `f` are given the
+    /// location of `node`. If node metadata is enabled, they are tagged with
+    /// `node` once `f` returns. Errors are wrapped in an [EmitError] for
+    /// `node`, unless they already refer to a more specific node.
+    pub(crate) fn emit_node<T>(
+        &mut self,
+        node: FatNode<'_, OpType, H>,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let parent = self.current_node.replace(node.node());
+        self.set_debug_location(node.hugr(), Some(node.node()));
+        let mark = self
+            .emit_context
+            .node_metadata()
+            .then(|| InstructionMark::new(self.prologue_bb, &self.builder, &self.blocks));
+        let r = f(self).and_then(|r| {
+            if let Some(mark) = mark {
+                attach_node_metadata(
+                    self.emit_context.iw_context(),
+                    &mut self.blocks,
+                    mark,
+                    node.node(),
+                    &node.name(),
+                )?;
+            }
+            Ok(r)
+        });
+        self.current_node = parent;
+        self.set_debug_location(node.hugr(), parent);
+        r.map_err(|err| {
+            let func = self.func.get_name().to_string_lossy().into_owned();
+            EmitError::new(ErrorContext::new(node.hugr(), node.node(), Some(func)), err).into()
+        })
+    }
+
+    /// Sets the debug location of subsequently emitted instructions to that of
+    /// `node`, or to line 0 if there is no node. Does nothing unless debug info
+    /// is enabled.
+    fn set_debug_location(&self, hugr: &H, node: Option<Node>) {
+        if let Some((debug_info, scope)) = self.emit_context.debug_info().zip(self.debug_scope) {
+            let location = match node {
+                Some(node) => debug_info.node_location(self.iw_context(), scope, hugr, node),
+                None => debug_info.location(self.iw_context(), scope, 0, 0),
+            };
+            self.builder.set_current_debug_location(location);
+        }
+    }
+
     pub fn get_current_module(&self) -> &Module<'c> {
         self.emit_context.module()
     }
@@ -307,8 +567,10 @@ impl<'c, 'a, H: HugrView> EmitFuncContext<'c, 'a, H> {
     }
 
     /// Consumes the `EmitFuncContext` and returns both the inner
-    /// [EmitModuleContext] and the scoped [FuncDefn]s that were encountered.
-    pub fn finish(self) -> Result<(EmitModuleContext<'c, 'a, H>, EmissionSet)> {
+    /// [EmitModuleContext] and the scoped [FuncDefn]s, instantiations of
+    /// polymorphic [FuncDefn]s, and function-valued [Const]s that were
+    /// encountered.
+    pub fn finish(self) -> Result<(EmitModuleContext<'c, 'a, H>, Worklist)> {
         self.builder.position_at_end(self.prologue_bb);
         self.builder.build_unconditional_branch(self.launch_bb)?;
         Ok((self.emit_context, self.todo))
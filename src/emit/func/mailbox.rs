@@ -1,18 +1,49 @@
-use std::{borrow::Cow, rc::Rc};
+use std::{borrow::Cow, cell::RefCell, rc::Rc};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use delegate::delegate;
 use inkwell::{
+    basic_block::BasicBlock,
     builder::Builder,
     types::{BasicType, BasicTypeEnum},
-    values::{BasicValue, BasicValueEnum, PointerValue},
+    values::{BasicValue, BasicValueEnum, PhiValue, PointerValue},
 };
 use itertools::{zip_eq, Itertools as _};
 
+/// How an [super::EmitFuncContext] passes values between the nodes it emits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MailBoxMode {
+    /// Each mailbox is an `alloca` in the first block of the function. Values
+    /// are stored to and loaded from it. The `mem2reg` pass is required to
+    /// obtain SSA form.
+    #[default]
+    Alloca,
+    /// Mailboxes carry SSA values directly. Where control flow joins, values
+    /// are merged with `phi` nodes.
+    Ssa,
+}
+
+#[derive(Eq, PartialEq, Clone)]
+enum MailBoxStorage<'c> {
+    /// An `alloca`ed pointer.
+    Alloca(PointerValue<'c>),
+    /// A single SSA value, which must be written before it is read.
+    Value(Rc<RefCell<Option<BasicValueEnum<'c>>>>),
+    /// SSA values written from the predecessors of the block in which they are
+    /// read, merged by a `phi` in that block.
+    Phi(Rc<RefCell<PhiState<'c>>>),
+}
+
+#[derive(Eq, PartialEq, Default)]
+struct PhiState<'c> {
+    phi: Option<PhiValue<'c>>,
+    incoming: Vec<(BasicValueEnum<'c>, BasicBlock<'c>)>,
+}
+
 #[derive(Eq, PartialEq, Clone)]
 pub struct ValueMailBox<'c> {
     typ: BasicTypeEnum<'c>,
-    ptr: PointerValue<'c>,
+    storage: MailBoxStorage<'c>,
     name: Cow<'static, str>,
 }
 
@@ -29,13 +60,35 @@ impl<'c> ValueMailBox<'c> {
         typ: impl BasicType<'c>,
         ptr: PointerValue<'c>,
         name: Option<String>,
+    ) -> Self {
+        Self::new_impl(typ, MailBoxStorage::Alloca(ptr), name)
+    }
+
+    /// Create a mailbox holding a single SSA value. It must be written before
+    /// it is read, and written at most once.
+    pub(super) fn new_value(typ: impl BasicType<'c>, name: Option<String>) -> Self {
+        Self::new_impl(typ, MailBoxStorage::Value(Default::default()), name)
+    }
+
+    /// Create a mailbox that may be written from several blocks, each of which
+    /// must be a predecessor of the block from which it is read. Values are
+    /// merged by a `phi` built at the start of the reading block.
+    pub(super) fn new_phi(typ: impl BasicType<'c>, name: Option<String>) -> Self {
+        Self::new_impl(typ, MailBoxStorage::Phi(Default::default()), name)
+    }
+
+    fn new_impl(
+        typ: impl BasicType<'c>,
+        storage: MailBoxStorage<'c>,
+        name: Option<String>,
     ) -> Self {
         Self {
             typ: typ.as_basic_type_enum(),
-            ptr,
+            storage,
             name: name.map_or(Cow::Borrowed(""), Cow::Owned),
         }
     }
+
     pub fn get_type(&self) -> BasicTypeEnum<'c> {
         self.typ
     }
@@ -53,20 +106,68 @@ impl<'c> ValueMailBox<'c> {
         builder: &Builder<'c>,
         labels: impl IntoIterator<Item = &'a str>,
     ) -> Result<BasicValueEnum<'c>> {
-        let r = builder.build_load(
-            self.ptr,
-            &join_names(
-                labels
-                    .into_iter()
-                    .chain(std::iter::once(self.name.as_ref())),
-            ),
-        )?;
+        let name = join_names(
+            labels
+                .into_iter()
+                .chain(std::iter::once(self.name.as_ref())),
+        );
+        let r = match &self.storage {
+            MailBoxStorage::Alloca(ptr) => builder.build_load(*ptr, &name)?,
+            MailBoxStorage::Value(v) => {
+                (*v.borrow()).ok_or(anyhow!("ValueMailBox '{name}' read before it was written"))?
+            }
+            MailBoxStorage::Phi(state) => {
+                let mut state = state.borrow_mut();
+                if let Some(phi) = state.phi {
+                    return Ok(phi.as_basic_value());
+                }
+                let block = builder
+                    .get_insert_block()
+                    .ok_or(anyhow!("ValueMailBox::read: builder is not positioned"))?;
+                // A value written earlier in this same block dominates the
+                // read, and so needs no phi.
+                if let Some((v, _)) = state.incoming.iter().rev().find(|(_, b)| *b == block) {
+                    return Ok(*v);
+                }
+                if let Some(first) = block.get_first_instruction() {
+                    builder.position_before(&first);
+                }
+                let phi = builder.build_phi(self.typ, &name);
+                builder.position_at_end(block);
+                let phi = phi?;
+                for (v, b) in state.incoming.drain(..) {
+                    phi.add_incoming(&[(&v, b)]);
+                }
+                state.phi = Some(phi);
+                phi.as_basic_value()
+            }
+        };
         debug_assert_eq!(r.get_type(), self.get_type());
         Ok(r)
     }
 
     fn write(&self, builder: &Builder<'c>, v: impl BasicValue<'c>) -> Result<()> {
-        builder.build_store(self.ptr, v)?;
+        let v = v.as_basic_value_enum();
+        match &self.storage {
+            MailBoxStorage::Alloca(ptr) => {
+                builder.build_store(*ptr, v)?;
+            }
+            MailBoxStorage::Value(value) => {
+                if value.replace(Some(v)).is_some() {
+                    Err(anyhow!("ValueMailBox '{}' written twice", self.name))?
+                }
+            }
+            MailBoxStorage::Phi(state) => {
+                let block = builder
+                    .get_insert_block()
+                    .ok_or(anyhow!("ValueMailBox::write: builder is not positioned"))?;
+                let mut state = state.borrow_mut();
+                match state.phi {
+                    Some(phi) => phi.add_incoming(&[(&v, block)]),
+                    None => state.incoming.push((v, block)),
+                }
+            }
+        }
         Ok(())
     }
 }
@@ -86,8 +187,8 @@ impl<'c> ValuePromise<'c> {
     }
 }
 
-/// Holds a vector of [PointerValue]s pointing to `alloca`s in the first block
-/// of a function.
+/// Holds a vector of [ValueMailBox]es. Depending on the [MailBoxMode] these
+/// are either `alloca`s in the first block of a function, or SSA values.
 #[derive(Eq, PartialEq, Clone)]
 #[allow(clippy::len_without_is_empty)]
 pub struct RowMailBox<'c>(Rc<Vec<ValueMailBox<'c>>>, Cow<'static, str>);
@@ -1,6 +1,9 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use hugr::HugrView;
-use inkwell::{values::BasicMetadataValueEnum, AddressSpace};
+use inkwell::{
+    values::{BasicMetadataValueEnum, IntValue, PointerValue},
+    AddressSpace,
+};
 
 use crate::emit::func::EmitFuncContext;
 
@@ -26,3 +29,57 @@ pub fn emit_libc_printf<H: HugrView>(
     context.builder().build_call(printf, args, "")?;
     Ok(())
 }
+
+/// Emits a call to the libc `int snprintf(char*, size_t, char*, ...)`
+/// function. `size_t` is assumed to be 64 bits wide.
+pub fn emit_libc_snprintf<H: HugrView>(
+    context: &mut EmitFuncContext<H>,
+    args: &[BasicMetadataValueEnum],
+) -> Result<()> {
+    let iw_ctx = context.typing_session().iw_context();
+    let str_ty = iw_ctx.i8_type().ptr_type(AddressSpace::default());
+    let snprintf_sig = iw_ctx.i32_type().fn_type(
+        &[str_ty.into(), iw_ctx.i64_type().into(), str_ty.into()],
+        true,
+    );
+
+    let snprintf = context.get_extern_func("snprintf", snprintf_sig)?;
+    context.builder().build_call(snprintf, args, "")?;
+    Ok(())
+}
+
+/// Emits a call to the libc `void* malloc(size_t)` function, returning the
+/// allocated pointer as an `i8*`. `size_t` is assumed to be 64 bits wide.
+pub fn emit_libc_malloc<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    size: IntValue<'c>,
+) -> Result<PointerValue<'c>> {
+    let iw_ctx = context.typing_session().iw_context();
+    let ptr_ty = iw_ctx.i8_type().ptr_type(AddressSpace::default());
+    let malloc_sig = ptr_ty.fn_type(&[iw_ctx.i64_type().into()], false);
+
+    let malloc = context.get_extern_func("malloc", malloc_sig)?;
+    Ok(context
+        .builder()
+        .build_call(malloc, &[size.into()], "")?
+        .try_as_basic_value()
+        .left()
+        .ok_or(anyhow!("malloc returned void"))?
+        .into_pointer_value())
+}
+
+/// Emits a call to the libc `void free(void*)` function. `ptr` may be of any
+/// pointer type.
+pub fn emit_libc_free<H: HugrView>(
+    context: &mut EmitFuncContext<H>,
+    ptr: PointerValue,
+) -> Result<()> {
+    let iw_ctx = context.typing_session().iw_context();
+    let ptr_ty = iw_ctx.i8_type().ptr_type(AddressSpace::default());
+    let free_sig = iw_ctx.void_type().fn_type(&[ptr_ty.into()], false);
+
+    let free = context.get_extern_func("free", free_sig)?;
+    let ptr = context.builder().build_pointer_cast(ptr, ptr_ty, "")?;
+    context.builder().build_call(free, &[ptr.into()], "")?;
+    Ok(())
+}
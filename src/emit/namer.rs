@@ -1,4 +1,9 @@
-use hugr::{Node, NodeIndex as _};
+use hugr::{
+    extension::ExtensionSet,
+    types::{RowVariable, Type, TypeArg, TypeEnum, TypeRowRV},
+    Node, NodeIndex as _,
+};
+use itertools::Itertools as _;
 
 /// A type with features for mangling the naming of symbols.
 #[derive(Clone)]
@@ -45,6 +50,122 @@ impl Namer {
         };
         format!("{prefix}{name}{postfix}")
     }
+
+    /// Mangle the name of an instantiation of a polymorphic
+    /// [hugr::ops::FuncDefn] or [hugr::ops::FuncDecl] with `type_args`.
+    ///
+    /// When `type_args` is empty the result is that of [Namer::name_func].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hugr_llvm::emit::Namer;
+    /// use hugr::{Node, extension::prelude::USIZE_T, types::TypeArg};
+    /// let node = Node::from(portgraph::NodeIndex::new(7));
+    /// let namer = Namer::default();
+    /// let type_args = [USIZE_T.into(), TypeArg::BoundedNat { n: 3 }];
+    /// assert_eq!(namer.name_func_instance("name", node, &type_args), "_hl.name.7<tE7:prelude5:usize0:,n3>");
+    /// assert_eq!(namer.name_func_instance("name", node, &[]), "_hl.name.7");
+    /// ```
+    pub fn name_func_instance(
+        &self,
+        name: impl AsRef<str>,
+        node: Node,
+        type_args: &[TypeArg],
+    ) -> String {
+        let name = self.name_func(name, node);
+        if type_args.is_empty() {
+            name
+        } else {
+            format!(
+                "{name}<{}>",
+                type_args.iter().map(mangle_type_arg).join(",")
+            )
+        }
+    }
+
+    /// Returns a `Namer` which inserts `scope` after the prefix of this one.
+    pub(crate) fn scoped(&self, scope: impl AsRef<str>) -> Self {
+        Self {
+            prefix: format!("{}{}", self.prefix, scope.as_ref()),
+            postfix_node: self.postfix_node,
+        }
+    }
+}
+
+/// Mangles a [TypeArg] into a string, such that distinct [TypeArg]s produce
+/// distinct strings.
+///
+/// Each variant is tagged with a leading character and every variable-length
+/// payload is length-prefixed, so the encoding is self-delimiting.
+pub(crate) fn mangle_type_arg(type_arg: &TypeArg) -> String {
+    match type_arg {
+        TypeArg::Type { ty } => format!("t{}", mangle_type(ty)),
+        TypeArg::BoundedNat { n } => format!("n{n}"),
+        TypeArg::String { arg } => format!("s{}", mangle_str(arg)),
+        TypeArg::Sequence { elems } => format!(
+            "q{}:{}",
+            elems.len(),
+            elems.iter().map(mangle_type_arg).join("")
+        ),
+        TypeArg::Extensions { es } => format!("e{}", mangle_extension_set(es)),
+        TypeArg::Variable { v } => format!("v{}", v.index()),
+        _ => format!("x{}", mangle_str(type_arg.to_string())),
+    }
+}
+
+fn mangle_str(s: impl AsRef<str>) -> String {
+    let s = s.as_ref();
+    format!("{}:{s}", s.len())
+}
+
+fn mangle_extension_set(es: &ExtensionSet) -> String {
+    format!(
+        "{}:{}",
+        es.iter().count(),
+        es.iter().map(|id| mangle_str(&**id)).join("")
+    )
+}
+
+fn mangle_type(ty: &Type) -> String {
+    match ty.as_type_enum() {
+        TypeEnum::Extension(custom) => format!(
+            "E{}{}{}:{}",
+            mangle_str(&**custom.extension()),
+            mangle_str(custom.name()),
+            custom.args().len(),
+            custom.args().iter().map(mangle_type_arg).join("")
+        ),
+        TypeEnum::Alias(alias) => format!("A{}", mangle_str(&alias.name)),
+        TypeEnum::Function(func) => format!(
+            "F{}{}{}",
+            mangle_type_row(func.input()),
+            mangle_type_row(func.output()),
+            mangle_extension_set(&func.extension_reqs)
+        ),
+        TypeEnum::Variable(idx, _) => format!("V{idx}"),
+        TypeEnum::RowVar(rv) => match *rv {},
+        TypeEnum::Sum(sum) => format!(
+            "S{}:{}",
+            sum.num_variants(),
+            (0..sum.num_variants())
+                .map(|tag| mangle_type_row(sum.get_variant(tag).unwrap()))
+                .join("")
+        ),
+    }
+}
+
+fn mangle_type_row(row: &TypeRowRV) -> String {
+    format!(
+        "{}:{}",
+        row.len(),
+        row.iter()
+            .map(|ty| match Type::try_from(ty.clone()) {
+                Ok(ty) => mangle_type(&ty),
+                Err(RowVariable(idx, _)) => format!("R{idx}"),
+            })
+            .join("")
+    )
 }
 
 impl Default for Namer {
@@ -1,7 +1,7 @@
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, Result};
 use hugr::ops::{
     constant::Sum, Call, CallIndirect, Case, Conditional, Const, ExtensionOp, Input, LoadConstant,
-    LoadFunction, OpTag, OpTrait, OpType, Output, Tag, Value, CFG,
+    LoadFunction, OpTag, OpTrait, OpType, Output, Tag, TailLoop, Value, CFG,
 };
 use hugr::{
     hugr::views::SiblingGraph,
@@ -22,7 +22,7 @@ use crate::{
 use super::{
     deaggregate_call_result,
     func::{EmitFuncContext, RowPromise},
-    EmitOpArgs,
+    EmitErrorKind, EmitOpArgs,
 };
 
 mod cfg;
@@ -62,42 +62,50 @@ where
         use petgraph::visit::Topo;
         let node = self.node;
         if !OpTag::DataflowParent.is_superset(node.tag()) {
-            Err(anyhow!("Not a dataflow parent"))?
+            Err(EmitErrorKind::InvalidHierarchy(format!(
+                "Not a dataflow parent: {}",
+                node.node()
+            )))?
         };
 
-        let (i, o): (FatNode<Input, H>, FatNode<Output, H>) = node
-            .get_io()
-            .ok_or(anyhow!("emit_dataflow_parent: no io nodes"))?;
+        let (i, o): (FatNode<Input, H>, FatNode<Output, H>) =
+            node.get_io()
+                .ok_or(EmitErrorKind::InvalidHierarchy(format!(
+                    "Dataflow parent has no io nodes: {}",
+                    node.node()
+                )))?;
         debug_assert!(i.out_value_types().count() == self.inputs.as_ref().unwrap().len());
         debug_assert!(o.in_value_types().count() == self.outputs.as_ref().unwrap().len());
 
         let region: SiblingGraph = node.try_new_hierarchy_view().unwrap();
         Topo::new(&region.as_petgraph())
             .iter(&region.as_petgraph())
-            .filter(|x| (*x != node.node()))
+            .filter(|x| *x != node.node())
             .map(|x| node.hugr().fat_optype(x))
             .try_for_each(|node| {
-                let inputs_rmb = context.node_ins_rmb(node)?;
-                let inputs = inputs_rmb.read(context.builder(), [])?;
-                let outputs = context.node_outs_rmb(node)?.promise();
-                match node.as_ref() {
-                    OpType::Input(_) => {
-                        let i = self.take_input()?;
-                        outputs.finish(context.builder(), i)
+                context.emit_node(node, |context| {
+                    let inputs_rmb = context.node_ins_rmb(node)?;
+                    let inputs = inputs_rmb.read(context.builder(), [])?;
+                    let outputs = context.node_outs_rmb(node)?.promise();
+                    match node.as_ref() {
+                        OpType::Input(_) => {
+                            let i = self.take_input()?;
+                            outputs.finish(context.builder(), i)
+                        }
+                        OpType::Output(_) => {
+                            let o = self.take_output()?;
+                            o.finish(context.builder(), inputs)
+                        }
+                        _ => emit_optype(
+                            context,
+                            EmitOpArgs {
+                                node,
+                                inputs,
+                                outputs,
+                            },
+                        ),
                     }
-                    OpType::Output(_) => {
-                        let o = self.take_output()?;
-                        o.finish(context.builder(), inputs)
-                    }
-                    _ => emit_optype(
-                        context,
-                        EmitOpArgs {
-                            node,
-                            inputs,
-                            outputs,
-                        },
-                    ),
-                }
+                })
             })
     }
 }
@@ -120,11 +128,12 @@ pub fn emit_value<'c, H: HugrView>(
 ) -> Result<BasicValueEnum<'c>> {
     match v {
         Value::Extension { e } => context.emit_custom_const(e.value()),
-        Value::Function { .. } => bail!(
-            "Value::Function Const nodes are not supported. \
-            Ensure you eliminate these from the HUGR before lowering to LLVM. \
-            `hugr_llvm::utils::inline_constant_functions` is provided for this purpose."
-        ),
+        Value::Function { .. } => Err(EmitErrorKind::UnsupportedConst(
+            "Value::Function is only supported as the value of a Const node, \
+            not nested within another value. \
+            `hugr_llvm::utils::inline_constant_functions` eliminates these from the HUGR."
+                .to_string(),
+        ))?,
         Value::Sum(Sum {
             tag,
             values,
@@ -221,6 +230,80 @@ fn emit_conditional<'c, H: HugrView>(
     Ok(())
 }
 
+fn emit_tail_loop<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    EmitOpArgs {
+        node,
+        inputs,
+        outputs,
+    }: EmitOpArgs<'c, '_, TailLoop, H>,
+) -> Result<()> {
+    let exit_rmb = context.new_row_mail_box(
+        node.dataflow_signature().unwrap().output.iter(),
+        "loop_exit_rmb",
+    )?;
+    let exit_block = context.build_positioned_new_block(
+        format!("loop_exit_{}", node.node().index()),
+        None,
+        |context, bb| {
+            let builder = context.builder();
+            outputs.finish(builder, exit_rmb.read_vec(builder, [])?)?;
+            Ok::<_, anyhow::Error>(bb)
+        },
+    )?;
+
+    // The mailbox of the body's Input node holds the loop-carried values. We
+    // write the inputs of the TailLoop node into it before entering the loop,
+    // and write the "just inputs" and "rest" outputs of each iteration into it
+    // before branching back to the loop header.
+    let (i, o) = node
+        .get_io()
+        .ok_or(anyhow!("emit_tail_loop: no io nodes"))?;
+    let body_inputs_rmb = context.node_outs_join_rmb(i)?;
+    let body_outputs_rmb = context.parent_outputs_rmb(o)?;
+    let control_sum_type = context.llvm_sum_type(SumType::new([
+        node.just_inputs.clone(),
+        node.just_outputs.clone(),
+    ]))?;
+
+    let body_block = context.build_positioned_new_block(
+        format!("loop_body_{}", node.node().index()),
+        Some(exit_block),
+        |context, bb| {
+            let inputs = body_inputs_rmb.read_vec(context.builder(), [])?;
+            emit_dataflow_parent(
+                context,
+                EmitOpArgs {
+                    node,
+                    inputs,
+                    outputs: body_outputs_rmb.promise(),
+                },
+            )?;
+            let body_outputs = body_outputs_rmb.read_vec(context.builder(), [])?;
+            let control = LLVMSumValue::try_new(body_outputs[0], control_sum_type)?;
+            control.build_destructure(context.builder(), |builder, tag, mut vs| {
+                vs.extend(&body_outputs[1..]);
+                // tag 0 is `Continue`, tag 1 is `Break`
+                if tag == 0 {
+                    body_inputs_rmb.write(builder, vs)?;
+                    builder.build_unconditional_branch(bb)?;
+                } else {
+                    exit_rmb.write(builder, vs)?;
+                    builder.build_unconditional_branch(exit_block)?;
+                }
+                Ok(())
+            })?;
+            Ok::<_, anyhow::Error>(bb)
+        },
+    )?;
+
+    let builder = context.builder();
+    body_inputs_rmb.write(builder, inputs)?;
+    builder.build_unconditional_branch(body_block)?;
+    builder.position_at_end(exit_block);
+    Ok(())
+}
+
 fn emit_load_constant<'c, H: HugrView>(
     context: &mut EmitFuncContext<'c, '_, H>,
     args: EmitOpArgs<'c, '_, LoadConstant, H>,
@@ -232,7 +315,14 @@ fn emit_load_constant<'c, H: HugrView>(
         .0
         .try_into_ot::<Const>()
         .unwrap();
-    let r = emit_value(context, konst_node.value())?;
+    let r = match konst_node.value() {
+        Value::Function { .. } => context
+            .get_const_func(konst_node)?
+            .as_global_value()
+            .as_pointer_value()
+            .into(),
+        v => emit_value(context, v)?,
+    };
     args.outputs.finish(context.builder(), [r])
 }
 
@@ -240,17 +330,22 @@ fn emit_call<'c, H: HugrView>(
     context: &mut EmitFuncContext<'c, '_, H>,
     args: EmitOpArgs<'c, '_, Call, H>,
 ) -> Result<()> {
-    if !args.node.called_function_type().params().is_empty() {
-        return Err(anyhow!("Call of generic function"));
-    }
     let (func_node, _) = args
         .node
         .single_linked_output(args.node.called_function_port())
         .unwrap();
+    let type_args = &args.node.type_args;
     let func = match func_node.as_ref() {
-        OpType::FuncDecl(_) => context.get_func_decl(func_node.try_into_ot().unwrap()),
-        OpType::FuncDefn(_) => context.get_func_defn(func_node.try_into_ot().unwrap()),
-        _ => Err(anyhow!("emit_call: Not a Decl or Defn")),
+        OpType::FuncDecl(_) => {
+            context.get_func_decl_instance(func_node.try_into_ot().unwrap(), type_args)
+        }
+        OpType::FuncDefn(_) => {
+            context.get_func_defn_instance(func_node.try_into_ot().unwrap(), type_args)
+        }
+        _ => Err(EmitErrorKind::InvalidHierarchy(format!(
+            "Called function is not a FuncDecl or FuncDefn: {func_node}"
+        ))
+        .into()),
     };
     let inputs = args.inputs.into_iter().map_into().collect_vec();
     let builder = context.builder();
@@ -280,18 +375,23 @@ fn emit_load_function<'c, H: HugrView>(
     context: &mut EmitFuncContext<'c, '_, H>,
     args: EmitOpArgs<'c, '_, LoadFunction, H>,
 ) -> Result<()> {
-    if !args.node.func_sig.params().is_empty() {
-        return Err(anyhow!("Load of generic function"));
-    }
     let (func_node, _) = args
         .node
         .single_linked_output(args.node.function_port())
         .unwrap();
 
+    let type_args = &args.node.type_args;
     let func = match func_node.as_ref() {
-        OpType::FuncDecl(_) => context.get_func_decl(func_node.try_into_ot().unwrap()),
-        OpType::FuncDefn(_) => context.get_func_defn(func_node.try_into_ot().unwrap()),
-        _ => Err(anyhow!("emit_call: Not a Decl or Defn")),
+        OpType::FuncDecl(_) => {
+            context.get_func_decl_instance(func_node.try_into_ot().unwrap(), type_args)
+        }
+        OpType::FuncDefn(_) => {
+            context.get_func_defn_instance(func_node.try_into_ot().unwrap(), type_args)
+        }
+        _ => Err(EmitErrorKind::InvalidHierarchy(format!(
+            "Loaded function is not a FuncDecl or FuncDefn: {func_node}"
+        ))
+        .into()),
     }?;
     args.outputs.finish(
         context.builder(),
@@ -321,6 +421,7 @@ fn emit_optype<'c, H: HugrView>(
         OpType::CallIndirect(ref cl) => emit_call_indirect(context, args.into_ot(cl)),
         OpType::LoadFunction(ref lf) => emit_load_function(context, args.into_ot(lf)),
         OpType::Conditional(ref co) => emit_conditional(context, args.into_ot(co)),
+        OpType::TailLoop(ref tl) => emit_tail_loop(context, args.into_ot(tl)),
         OpType::CFG(ref cfg) => emit_cfg(context, args.into_ot(cfg)),
         // Const is allowed, but requires no work here. FuncDecl is technically
         // not allowed, but there is no harm in allowing it.
@@ -331,7 +432,9 @@ fn emit_optype<'c, H: HugrView>(
             Ok(())
         }
 
-        _ => Err(anyhow!("Invalid child for Dataflow Parent: {node}")),
+        _ => Err(EmitErrorKind::InvalidHierarchy(format!(
+            "Invalid child for Dataflow Parent: {node}"
+        )))?,
     }
 }
 
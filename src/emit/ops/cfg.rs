@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use anyhow::{anyhow, Result};
 use hugr::{
@@ -11,8 +11,8 @@ use itertools::Itertools as _;
 
 use crate::{
     emit::{
-        func::{EmitFuncContext, RowMailBox, RowPromise},
-        EmitOpArgs,
+        func::{EmitFuncContext, MailBoxMode, RowMailBox, RowPromise},
+        EmitErrorKind, EmitOpArgs,
     },
     sum::LLVMSumValue,
     utils::fat::FatNode,
@@ -58,7 +58,7 @@ impl<'c, 'hugr, H: HugrView> CfgEmitter<'c, 'hugr, H> {
             } else if child.is_dataflow_block() {
                 let bb = context.new_basic_block("", Some(exit_block));
                 let (i, _) = child.get_io().unwrap();
-                bbs.insert(child, (bb, context.node_outs_rmb(i)?));
+                bbs.insert(child, (bb, context.node_outs_join_rmb(i)?));
             }
         }
         let (entry_node, exit_node) = node.get_entry_exit();
@@ -105,8 +105,15 @@ impl<'c, 'hugr, H: HugrView> CfgEmitter<'c, 'hugr, H> {
         inputs_rmb.write(builder, inputs)?;
         builder.build_unconditional_branch(entry_bb)?;
 
+        // In SSA mode a value must be emitted before it is used, and blocks
+        // may use values from the blocks that dominate them.
+        let children = match context.mailbox_mode() {
+            MailBoxMode::Alloca => self.node.children().collect_vec(),
+            MailBoxMode::Ssa => self.children_in_dominance_order(),
+        };
+
         // emit each child by delegating to the `impl EmitOp<_>` of self.
-        for child_node in self.node.children() {
+        for child_node in children {
             let (inputs, outputs) = (vec![], RowMailBox::new_empty().promise());
             match child_node.as_ref() {
                 OpType::DataflowBlock(ref dfb) => self.emit_dataflow_block(
@@ -135,7 +142,9 @@ impl<'c, 'hugr, H: HugrView> CfgEmitter<'c, 'hugr, H> {
                     Ok(())
                 }
 
-                ot => Err(anyhow!("unknown optype: {ot:?}")),
+                _ => Err(EmitErrorKind::InvalidHierarchy(format!(
+                    "Invalid child for CFG: {child_node}"
+                )))?,
             }?;
         }
 
@@ -144,6 +153,33 @@ impl<'c, 'hugr, H: HugrView> CfgEmitter<'c, 'hugr, H> {
         context.builder().position_at_end(exit_bb);
         Ok(())
     }
+
+    /// Returns our children, with the blocks reachable from the entry block in
+    /// reverse post-order, so that each comes after all its dominators.
+    /// Other children follow in their original order.
+    fn children_in_dominance_order(&self) -> Vec<FatNode<'hugr, OpType, H>> {
+        let mut post_order = vec![];
+        let mut visited = HashSet::new();
+        let mut stack = vec![(self.entry_node.generalise(), false)];
+        while let Some((node, finished)) = stack.pop() {
+            if finished {
+                post_order.push(node);
+            } else if visited.insert(node) {
+                stack.push((node, true));
+                stack.extend(
+                    node.output_neighbours()
+                        .filter(|succ| !visited.contains(succ))
+                        .map(|succ| (succ, false)),
+                );
+            }
+        }
+        post_order
+            .into_iter()
+            .rev()
+            .chain(self.node.children().filter(|c| !visited.contains(c)))
+            .collect()
+    }
+
     fn emit_dataflow_block(
         &mut self,
         context: &mut EmitFuncContext<'c, '_, H>,
@@ -164,7 +200,7 @@ impl<'c, 'hugr, H: HugrView> CfgEmitter<'c, 'hugr, H> {
         context.build_positioned(bb, |context| {
             let (_, o) = node.get_io().unwrap();
             // get the rowmailbox for our output node
-            let outputs_rmb = context.node_ins_rmb(o)?;
+            let outputs_rmb = context.parent_outputs_rmb(o)?;
             // read the values from our input node
             let inputs = inputs_rmb.read_vec(context.builder(), [])?;
 
@@ -0,0 +1,614 @@
+//! Support for emitting modules which conform to the
+//! [QIR base profile](https://github.com/qir-alliance/qir-spec/blob/main/specification/under_development/profiles/Base_Profile.md).
+//!
+//! See [crate::emit::EmitHugr::with_qir_base_profile].
+//!
+//! Under the base profile a module defines a single entry point, which takes
+//! no arguments, returns nothing, and contains no control flow. Its body must
+//! therefore be emitted as a single basic block, so ops which branch, e.g.
+//! the checked ops which may panic, are rejected. Qubits and
+//! results are not allocated at runtime, but are given static addresses,
+//! i.e. `inttoptr`s of consecutive integers, and the results of all
+//! measurements are recorded as output, in the order in which they are made,
+//! before the entry point returns.
+//!
+//! Codegen extensions which allocate qubits or results should do so through
+//! the [QirBaseProfile] returned by
+//! [crate::emit::EmitFuncContext::qir_base_profile].
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+};
+
+use anyhow::{anyhow, Result};
+use hugr::{
+    extension::prelude::{PANIC_OP_ID, PRELUDE_ID},
+    ops::{FuncDefn, OpType},
+    HugrView, Node,
+};
+use inkwell::{
+    attributes::AttributeLoc,
+    basic_block::BasicBlock,
+    context::Context,
+    llvm_sys::{core::LLVMReplaceAllUsesWith, prelude::LLVMBasicBlockRef},
+    module::FlagBehavior,
+    types::PointerType,
+    values::{AsValueRef as _, FunctionValue, InstructionOpcode, PhiValue, PointerValue},
+    AddressSpace,
+};
+use itertools::{Either, Itertools as _};
+
+use crate::utils::fat::FatNode;
+
+use super::{EmitError, EmitErrorKind, EmitModuleContext, ErrorContext};
+
+/// The name of the function attribute which marks the entry point.
+pub const ENTRY_POINT_ATTRIBUTE: &str = "EntryPoint";
+
+/// The name of the function attribute holding the number of qubits used.
+pub const REQUIRED_NUM_QUBITS_ATTRIBUTE: &str = "required_num_qubits";
+
+/// The name of the function attribute holding the number of results used.
+pub const REQUIRED_NUM_RESULTS_ATTRIBUTE: &str = "required_num_results";
+
+fn qir_opaque_ptr_type<'c>(iw_context: &'c Context, name: &str) -> PointerType<'c> {
+    iw_context
+        .get_struct_type(name)
+        .unwrap_or_else(|| iw_context.opaque_struct_type(name))
+        .ptr_type(AddressSpace::default())
+}
+
+/// Returns the QIR type `%Qubit*`, a pointer to the opaque struct `Qubit`.
+pub fn qir_qubit_type(iw_context: &Context) -> PointerType<'_> {
+    qir_opaque_ptr_type(iw_context, "Qubit")
+}
+
+/// Returns the QIR type `%Result*`, a pointer to the opaque struct `Result`.
+pub fn qir_result_type(iw_context: &Context) -> PointerType<'_> {
+    qir_opaque_ptr_type(iw_context, "Result")
+}
+
+fn static_address<'c>(
+    iw_context: &'c Context,
+    ty: PointerType<'c>,
+    index: u64,
+) -> PointerValue<'c> {
+    iw_context
+        .i64_type()
+        .const_int(index, false)
+        .const_to_pointer(ty)
+}
+
+/// Options for emitting a module under the QIR base profile.
+#[derive(Clone, Debug)]
+pub struct QirBaseProfileOptions {
+    /// The name of the [FuncDefn] which is emitted as the entry point.
+    pub entry_point: String,
+}
+
+impl Default for QirBaseProfileOptions {
+    fn default() -> Self {
+        Self {
+            entry_point: "main".into(),
+        }
+    }
+}
+
+/// The state of the emission of a module under the QIR base profile: the
+/// numbers of qubits and results allocated so far, and the nodes from which
+/// basic blocks were emitted.
+#[derive(Debug)]
+pub struct QirBaseProfile {
+    options: QirBaseProfileOptions,
+    num_qubits: Cell<u64>,
+    num_results: Cell<u64>,
+    block_nodes: RefCell<HashMap<LLVMBasicBlockRef, Node>>,
+}
+
+impl QirBaseProfile {
+    /// Creates a new `QirBaseProfile`, with no qubits or results allocated.
+    pub fn new(options: &QirBaseProfileOptions) -> Self {
+        Self {
+            options: options.clone(),
+            num_qubits: Cell::new(0),
+            num_results: Cell::new(0),
+            block_nodes: Default::default(),
+        }
+    }
+
+    /// Returns the name of the [FuncDefn] which is emitted as the entry point.
+    pub fn entry_point(&self) -> &str {
+        &self.options.entry_point
+    }
+
+    /// Returns the number of qubits allocated so far.
+    pub fn num_qubits(&self) -> u64 {
+        self.num_qubits.get()
+    }
+
+    /// Returns the number of results allocated so far.
+    pub fn num_results(&self) -> u64 {
+        self.num_results.get()
+    }
+
+    /// Allocates a new qubit, returning its static address as a `%Qubit*`.
+    pub fn allocate_qubit<'c>(&self, iw_context: &'c Context) -> PointerValue<'c> {
+        let index = self.num_qubits.replace(self.num_qubits() + 1);
+        static_address(iw_context, qir_qubit_type(iw_context), index)
+    }
+
+    /// Allocates a new result, returning its static address as a `%Result*`.
+    ///
+    /// All allocated results are recorded as output when the entry point
+    /// returns.
+    pub fn allocate_result<'c>(&self, iw_context: &'c Context) -> PointerValue<'c> {
+        let index = self.num_results.replace(self.num_results() + 1);
+        static_address(iw_context, qir_result_type(iw_context), index)
+    }
+
+    /// Records that `block` was created while emitting `node`, so that
+    /// [Self::finish_entry_point] can name the node if the block remains.
+    pub(crate) fn record_block(&self, block: BasicBlock<'_>, node: Node) {
+        self.block_nodes
+            .borrow_mut()
+            .entry(block.as_mut_ptr())
+            .or_insert(node);
+    }
+
+    /// Finds the entry point among the children of `module`, and checks that
+    /// it, and the rest of `module`, can be emitted under the base profile.
+    pub(crate) fn find_entry_point<'hugr, H: HugrView>(
+        &self,
+        module: FatNode<'hugr, hugr::ops::Module, H>,
+    ) -> Result<FatNode<'hugr, FuncDefn, H>> {
+        let entry_point = self.entry_point();
+        let mut found = None;
+        for c in module.children() {
+            match c.as_ref() {
+                OpType::FuncDefn(ref fd) if fd.name == entry_point => found = Some(c.into_ot(fd)),
+                OpType::FuncDefn(ref fd) => Err(profile_error(
+                    c.hugr(),
+                    c.node(),
+                    format!(
+                        "Only the entry point '{entry_point}' may be defined, but found function '{}'. \
+                        Other functions must be inlined into the entry point.",
+                        fd.name
+                    ),
+                ))?,
+                _ => (),
+            }
+        }
+        let entry = found.ok_or_else(|| {
+            profile_error(
+                module.hugr(),
+                module.node(),
+                format!("Module has no entry point function '{entry_point}'"),
+            )
+        })?;
+        let sig = &entry.signature;
+        if !sig.params().is_empty() || !sig.body().input.is_empty() || !sig.body().output.is_empty()
+        {
+            Err(profile_error(
+                entry.hugr(),
+                entry.node(),
+                format!(
+                    "The entry point must be monomorphic, take no inputs and return no outputs, but has signature {sig}"
+                ),
+            ))?
+        }
+        check_straight_line(entry.hugr(), entry.node())?;
+        Ok(entry)
+    }
+
+    /// Completes the entry point `func`, once its body has been emitted.
+    ///
+    /// The straight-line sequence of basic blocks emitted for the entry point
+    /// is merged into a single block. Any other block must have been emitted
+    /// for an op which branches, e.g. to panic, and is rejected. The runtime
+    /// is initialized at the start of the entry point, and every allocated
+    /// result is recorded as output immediately before it returns. The
+    /// attributes of the entry point and the flags of the module are set.
+    pub(crate) fn finish_entry_point<'c, H: HugrView>(
+        &self,
+        context: &EmitModuleContext<'c, '_, H>,
+        entry_point: FatNode<'_, FuncDefn, H>,
+        func: FunctionValue<'c>,
+    ) -> Result<()> {
+        let iw_context = context.iw_context();
+        let builder = iw_context.create_builder();
+        let void_ty = iw_context.void_type();
+        let i8_ptr_ty = iw_context.i8_type().ptr_type(AddressSpace::default());
+
+        let first_bb = merge_straight_line_blocks(func)?;
+        if let Some(bb) = first_bb.get_next_basic_block() {
+            // Blocks emitted other than through `EmitFuncContext` are blamed
+            // on the entry point.
+            let node = self.block_nodes.borrow().get(&bb.as_mut_ptr()).copied();
+            Err(profile_error(
+                entry_point.hugr(),
+                node.unwrap_or(entry_point.node()),
+                "Ops which branch are not supported",
+            ))?
+        }
+        match first_bb.get_first_instruction() {
+            Some(inst) => builder.position_before(&inst),
+            None => builder.position_at_end(first_bb),
+        }
+        let initialize = context.get_extern_func(
+            "__quantum__rt__initialize",
+            void_ty.fn_type(&[i8_ptr_ty.into()], false),
+        )?;
+        builder.build_call(initialize, &[i8_ptr_ty.const_null().into()], "")?;
+
+        let ret = func
+            .get_basic_blocks()
+            .into_iter()
+            .filter_map(|bb| bb.get_terminator())
+            .filter(|inst| inst.get_opcode() == InstructionOpcode::Return)
+            .exactly_one()
+            .map_err(|_| anyhow!("Entry point must return exactly once"))?;
+        builder.position_before(&ret);
+        if self.num_results() > 0 {
+            let result_ty = qir_result_type(iw_context);
+            let record_output = context.get_extern_func(
+                "__quantum__rt__result_record_output",
+                void_ty.fn_type(&[result_ty.into(), i8_ptr_ty.into()], false),
+            )?;
+            for index in 0..self.num_results() {
+                builder.build_call(
+                    record_output,
+                    &[
+                        static_address(iw_context, result_ty, index).into(),
+                        i8_ptr_ty.const_null().into(),
+                    ],
+                    "",
+                )?;
+            }
+        }
+
+        for (key, value) in [
+            (ENTRY_POINT_ATTRIBUTE, "".to_string()),
+            ("output_labeling_schema", "".to_string()),
+            ("qir_profiles", "base_profile".to_string()),
+            (REQUIRED_NUM_QUBITS_ATTRIBUTE, self.num_qubits().to_string()),
+            (
+                REQUIRED_NUM_RESULTS_ATTRIBUTE,
+                self.num_results().to_string(),
+            ),
+        ] {
+            func.add_attribute(
+                AttributeLoc::Function,
+                iw_context.create_string_attribute(key, &value),
+            );
+        }
+
+        let module = context.module();
+        let i32_ty = iw_context.i32_type();
+        let bool_ty = iw_context.bool_type();
+        module.add_basic_value_flag(
+            "qir_major_version",
+            FlagBehavior::Error,
+            i32_ty.const_int(1, false),
+        );
+        module.add_basic_value_flag(
+            "qir_minor_version",
+            FlagBehavior::Error,
+            i32_ty.const_int(0, false),
+        );
+        module.add_basic_value_flag(
+            "dynamic_qubit_management",
+            FlagBehavior::Error,
+            bool_ty.const_zero(),
+        );
+        module.add_basic_value_flag(
+            "dynamic_result_management",
+            FlagBehavior::Error,
+            bool_ty.const_zero(),
+        );
+        Ok(())
+    }
+}
+
+/// Merges the first basic block of `func` into its successor for as long as
+/// it ends with an unconditional branch to a block with no other predecessor,
+/// returning the resulting first block.
+fn merge_straight_line_blocks(func: FunctionValue<'_>) -> Result<BasicBlock<'_>> {
+    let mut first_bb = func
+        .get_first_basic_block()
+        .ok_or(anyhow!("Entry point has no body"))?;
+    loop {
+        let Some(branch) = first_bb.get_terminator() else {
+            return Ok(first_bb);
+        };
+        // An unconditional branch has the single operand of its destination,
+        // and a `switch` with no cases has a condition and a default
+        // destination, as emitted for sums with a single variant.
+        let dest_operand = match (branch.get_opcode(), branch.get_num_operands()) {
+            (InstructionOpcode::Br, 1) => 0,
+            (InstructionOpcode::Switch, 2) => 1,
+            _ => return Ok(first_bb),
+        };
+        let Some(Either::Right(next_bb)) = branch.get_operand(dest_operand) else {
+            return Ok(first_bb);
+        };
+        // The only use of a block is by the terminators of its predecessors.
+        if next_bb == first_bb
+            || next_bb
+                .get_first_use()
+                .and_then(|u| u.get_next_use())
+                .is_some()
+        {
+            return Ok(first_bb);
+        }
+        // The phis of `next_bb` have a single incoming value, from `first_bb`.
+        let next_first_non_phi = loop {
+            match next_bb.get_first_instruction() {
+                Some(inst) if inst.get_opcode() == InstructionOpcode::Phi => {
+                    let phi: PhiValue = inst.try_into().map_err(|_| anyhow!("Expected a phi"))?;
+                    let (v, _) = phi.get_incoming(0).ok_or(anyhow!("Phi has no incoming"))?;
+                    unsafe { LLVMReplaceAllUsesWith(inst.as_value_ref(), v.as_value_ref()) };
+                    inst.erase_from_basic_block();
+                }
+                inst => break inst,
+            }
+        };
+        branch.erase_from_basic_block();
+        let builder = func.get_type().get_context().create_builder();
+        match next_first_non_phi {
+            Some(inst) => builder.position_before(&inst),
+            None => builder.position_at_end(next_bb),
+        }
+        while let Some(inst) = first_bb.get_first_instruction() {
+            inst.remove_from_basic_block();
+            builder.insert_instruction(&inst, None);
+        }
+        next_bb
+            .move_before(first_bb)
+            .map_err(|_| anyhow!("Failed to move basic block"))?;
+        unsafe { first_bb.delete() }.map_err(|_| anyhow!("Failed to delete basic block"))?;
+        first_bb = next_bb;
+    }
+}
+
+fn profile_error(hugr: &impl HugrView, node: Node, message: impl Into<String>) -> anyhow::Error {
+    EmitError {
+        context: ErrorContext::new(hugr, node, None),
+        kind: EmitErrorKind::QirBaseProfile(message.into()),
+    }
+    .into()
+}
+
+/// Checks that the descendants of `parent` emit straight-line code, i.e.
+/// that there are no loops, branches or calls.
+///
+/// Extension ops which branch, e.g. to panic, are only found once the entry
+/// point has been emitted, by [QirBaseProfile::finish_entry_point].
+fn check_straight_line(hugr: &impl HugrView, parent: Node) -> Result<()> {
+    let mut worklist = hugr.children(parent).collect_vec();
+    while let Some(node) = worklist.pop() {
+        let unsupported = match hugr.get_optype(node) {
+            OpType::TailLoop(_) => "Loops",
+            OpType::Conditional(_) => "Conditionals",
+            // The children of a CFG are its entry block, its exit block, and
+            // any other blocks.
+            OpType::CFG(_) if hugr.children(node).count() > 2 => {
+                "Control flow graphs with more than one basic block"
+            }
+            OpType::Call(_) | OpType::CallIndirect(_) | OpType::LoadFunction(_) => "Function calls",
+            OpType::FuncDefn(_) => "Nested functions",
+            OpType::ExtensionOp(op)
+                if op.def().extension() == &PRELUDE_ID && op.def().name() == &PANIC_OP_ID =>
+            {
+                "Panics"
+            }
+            _ => {
+                worklist.extend(hugr.children(node));
+                continue;
+            }
+        };
+        Err(profile_error(
+            hugr,
+            node,
+            format!("{unsupported} are not supported"),
+        ))?
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::{
+        builder::{
+            Container as _, Dataflow, DataflowSubContainer as _, HugrBuilder as _, ModuleBuilder,
+            SubContainer as _,
+        },
+        extension::{
+            prelude::{ConstError, ConstUsize, BOOL_T, USIZE_T},
+            PRELUDE, PRELUDE_REGISTRY,
+        },
+        ops::Value,
+        type_row,
+        types::{Signature, TypeArg},
+        Hugr,
+    };
+    use rstest::rstest;
+
+    use crate::{
+        emit::test::{Emission, SimpleHugrConfig},
+        test::{llvm_ctx, TestContext},
+        utils::{fat::FatExt as _, ArrayOpBuilder as _},
+    };
+
+    use super::*;
+
+    fn emit_base_profile(llvm_ctx: &TestContext, hugr: &Hugr) -> Result<String> {
+        let emit_hugr = llvm_ctx
+            .get_emit_hugr()
+            .with_qir_base_profile(&QirBaseProfileOptions::default());
+        let emission = Emission::emit_hugr(hugr.fat_root().unwrap(), emit_hugr)?;
+        emission.verify()?;
+        Ok(emission.module().print_to_string().to_string())
+    }
+
+    fn profile_error_message(err: anyhow::Error) -> String {
+        match err.downcast::<EmitError>() {
+            Ok(EmitError {
+                kind: EmitErrorKind::QirBaseProfile(message),
+                ..
+            }) => message,
+            err => panic!("Expected a QIR base profile error, got {err:?}"),
+        }
+    }
+
+    #[rstest]
+    fn emit_empty_entry_point(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| cge.add_default_prelude_extensions());
+        let hugr = SimpleHugrConfig::new()
+            .with_extensions(PRELUDE_REGISTRY.to_owned())
+            .finish(|builder| builder.finish_with_outputs([]).unwrap());
+        let module = emit_base_profile(&llvm_ctx, &hugr).unwrap();
+        for s in [
+            "call void @__quantum__rt__initialize(i8* null)",
+            "\"EntryPoint\"",
+            "\"qir_profiles\"=\"base_profile\"",
+            "\"required_num_qubits\"=\"0\"",
+            "\"required_num_results\"=\"0\"",
+            "!\"qir_major_version\", i32 1",
+            "!\"dynamic_qubit_management\", i1 false",
+        ] {
+            assert!(module.contains(s), "{s} not in {module}");
+        }
+        assert!(!module.contains("__quantum__rt__result_record_output"));
+    }
+
+    #[rstest]
+    fn reject_missing_entry_point(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| cge.add_default_prelude_extensions());
+        let hugr = ModuleBuilder::new().finish_hugr(&PRELUDE_REGISTRY).unwrap();
+        let message = profile_error_message(emit_base_profile(&llvm_ctx, &hugr).unwrap_err());
+        assert!(
+            message.contains("no entry point function 'main'"),
+            "{message}"
+        );
+    }
+
+    #[rstest]
+    fn reject_entry_point_with_outputs(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| cge.add_default_prelude_extensions());
+        let hugr = SimpleHugrConfig::new()
+            .with_ins(BOOL_T)
+            .with_outs(BOOL_T)
+            .with_extensions(PRELUDE_REGISTRY.to_owned())
+            .finish(|builder| {
+                let [b] = builder.input_wires_arr();
+                builder.finish_with_outputs([b]).unwrap()
+            });
+        let message = profile_error_message(emit_base_profile(&llvm_ctx, &hugr).unwrap_err());
+        assert!(
+            message.contains("take no inputs and return no outputs"),
+            "{message}"
+        );
+    }
+
+    #[rstest]
+    fn reject_other_functions(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| cge.add_default_prelude_extensions());
+        let mut mod_b = ModuleBuilder::new();
+        for name in ["main", "helper"] {
+            let func_b = mod_b
+                .define_function(name, Signature::new_endo(type_row![]))
+                .unwrap();
+            func_b.finish_with_outputs([]).unwrap();
+        }
+        let hugr = mod_b.finish_hugr(&PRELUDE_REGISTRY).unwrap();
+        let message = profile_error_message(emit_base_profile(&llvm_ctx, &hugr).unwrap_err());
+        assert!(message.contains("'helper'"), "{message}");
+    }
+
+    #[rstest]
+    fn reject_loops(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| cge.add_default_prelude_extensions());
+        let hugr = SimpleHugrConfig::new()
+            .with_extensions(PRELUDE_REGISTRY.to_owned())
+            .finish(|mut builder| {
+                let mut loop_b = builder.tail_loop_builder([], [], type_row![]).unwrap();
+                let done = loop_b.add_load_value(Value::true_val());
+                loop_b.finish_with_outputs(done, []).unwrap();
+                builder.finish_with_outputs([]).unwrap()
+            });
+        let message = profile_error_message(emit_base_profile(&llvm_ctx, &hugr).unwrap_err());
+        assert_eq!("Loops are not supported", message);
+    }
+
+    #[rstest]
+    fn reject_panics(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| cge.add_default_prelude_extensions());
+        let panic_op = PRELUDE
+            .instantiate_extension_op(
+                &PANIC_OP_ID,
+                [
+                    TypeArg::Sequence { elems: vec![] },
+                    TypeArg::Sequence { elems: vec![] },
+                ],
+                &PRELUDE_REGISTRY,
+            )
+            .unwrap();
+        let hugr = SimpleHugrConfig::new()
+            .with_extensions(PRELUDE_REGISTRY.to_owned())
+            .finish(|mut builder| {
+                let err = builder.add_load_value(ConstError::new(1, "PANIC"));
+                builder.add_dataflow_op(panic_op, [err]).unwrap();
+                builder.finish_with_outputs([]).unwrap()
+            });
+        let message = profile_error_message(emit_base_profile(&llvm_ctx, &hugr).unwrap_err());
+        assert_eq!("Panics are not supported", message);
+    }
+
+    /// A bounds-checked array `get` branches to panic, which is only found once
+    /// the entry point has been emitted.
+    #[rstest]
+    fn reject_array_get(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| cge.add_default_prelude_extensions());
+        let mut get_node = None;
+        let hugr = SimpleHugrConfig::new()
+            .with_extensions(PRELUDE_REGISTRY.to_owned())
+            .finish(|mut builder| {
+                let elems = [1, 2].map(|i| builder.add_load_value(ConstUsize::new(i)));
+                let array = builder.add_new_array(USIZE_T, elems).unwrap();
+                let index = builder.add_load_value(ConstUsize::new(0));
+                let elem = builder.add_array_get(USIZE_T, 2, array, index).unwrap();
+                get_node = Some(elem.node());
+                builder.finish_with_outputs([]).unwrap()
+            });
+        let err = emit_base_profile(&llvm_ctx, &hugr)
+            .unwrap_err()
+            .downcast::<EmitError>()
+            .unwrap();
+        assert_eq!(get_node, Some(err.node()));
+        assert!(
+            matches!(&err.kind, EmitErrorKind::QirBaseProfile(m) if m == "Ops which branch are not supported"),
+            "{err}"
+        );
+    }
+
+    /// The blocks emitted for straight-line code are merged into one.
+    #[rstest]
+    fn merge_single_block_cfg(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| cge.add_default_prelude_extensions());
+        let hugr = SimpleHugrConfig::new()
+            .with_extensions(PRELUDE_REGISTRY.to_owned())
+            .finish(|mut builder| {
+                let mut cfg_b = builder.cfg_builder([], type_row![]).unwrap();
+                let mut entry_b = cfg_b.simple_entry_builder(type_row![], 1).unwrap();
+                let unit = entry_b.add_load_value(Value::unary_unit_sum());
+                let entry = entry_b.finish_with_outputs(unit, []).unwrap();
+                let exit = cfg_b.exit_block();
+                cfg_b.branch(&entry, 0, &exit).unwrap();
+                cfg_b.finish_sub_container().unwrap();
+                builder.finish_with_outputs([]).unwrap()
+            });
+        let module = emit_base_profile(&llvm_ctx, &hugr).unwrap();
+        assert_eq!(1, module.matches("ret void").count(), "{module}");
+        assert!(!module.contains("br "), "{module}");
+    }
+}
@@ -4,6 +4,7 @@ use crate::custom::CodegenExtsBuilder;
 use crate::extension::int::add_int_extensions;
 use crate::types::HugrFuncType;
 use crate::utils::fat::FatNode;
+use crate::utils::IntOpBuilder as _;
 use anyhow::{anyhow, Result};
 use hugr::builder::DataflowSubContainer;
 use hugr::builder::{
@@ -15,7 +16,7 @@ use hugr::ops::constant::CustomConst;
 use hugr::ops::handle::FuncID;
 use hugr::ops::{CallIndirect, Tag, Value};
 use hugr::std_extensions::arithmetic::int_ops::{self, INT_OPS_REGISTRY};
-use hugr::std_extensions::arithmetic::int_types::ConstInt;
+use hugr::std_extensions::arithmetic::int_types::{ConstInt, INT_TYPES};
 use hugr::types::{Signature, Type, TypeRow};
 use hugr::{type_row, Hugr, HugrView};
 use inkwell::module::Module;
@@ -26,7 +27,7 @@ use rstest::rstest;
 
 use crate::test::*;
 
-use super::EmitHugr;
+use super::{ConstFunctionView, EmitHugr};
 
 #[allow(clippy::upper_case_acronyms)]
 pub type DFGW<'a> = DFGWrapper<&'a mut Hugr, BuildHandle<FuncID<true>>>;
@@ -40,21 +41,32 @@ pub struct SimpleHugrConfig {
 /// A wrapper for a module into which our tests will emit hugr.
 pub struct Emission<'c> {
     module: Module<'c>,
+    global_mappings: Vec<(String, usize)>,
 }
 
 impl<'c> Emission<'c> {
     /// Create an `Emission` from a HUGR.
-    pub fn emit_hugr<'a: 'c, H: HugrView>(
+    pub fn emit_hugr<'a: 'c, H: ConstFunctionView>(
         hugr: FatNode<'c, hugr::ops::Module, H>,
         eh: EmitHugr<'c, 'a, H>,
     ) -> Result<Self> where {
         let module = eh.emit_module(hugr)?.finish();
-        Ok(Self { module })
+        Ok(Self::new(module))
     }
 
     /// Create an `Emission` from an LLVM Module.
     pub fn new(module: Module<'c>) -> Self {
-        Self { module }
+        Self {
+            module,
+            global_mappings: Vec::new(),
+        }
+    }
+
+    /// Map the function named `symbol` to the native function at `addr` when
+    /// executing the inner module. This is ignored if the module does not
+    /// contain such a function.
+    pub fn add_global_mapping(&mut self, symbol: impl Into<String>, addr: usize) {
+        self.global_mappings.push((symbol.into(), addr));
     }
 
     // Verify the inner Module.
@@ -95,6 +107,18 @@ impl<'c> Emission<'c> {
         Ok(gv.as_float(&self.module.get_context().f64_type()))
     }
 
+    /// JIT and execute the function named `entry` in the inner module.
+    ///
+    /// That function must take no arguments and return an `i8*` pointing to a
+    /// null-terminated string.
+    pub fn exec_string(&self, entry: impl AsRef<str>) -> Result<String> {
+        let gv = self.exec_impl(entry)?;
+        let ptr = unsafe { gv.into_pointer::<std::ffi::c_char>() };
+        Ok(unsafe { std::ffi::CStr::from_ptr(ptr) }
+            .to_str()?
+            .to_owned())
+    }
+
     pub(crate) fn exec_impl(&self, entry: impl AsRef<str>) -> Result<GenericValue<'c>> {
         let entry_fv = self
             .module
@@ -107,6 +131,11 @@ impl<'c> Emission<'c> {
             .module
             .create_jit_execution_engine(inkwell::OptimizationLevel::None)
             .map_err(|err| anyhow!("Failed to create execution engine: {err}"))?;
+        for (symbol, addr) in &self.global_mappings {
+            if let Some(func) = self.module.get_function(symbol) {
+                ee.add_global_mapping(&func, *addr);
+            }
+        }
         let fv = ee.get_function_value(entry.as_ref())?;
         Ok(unsafe { ee.run_function(fv, &[]) })
     }
@@ -519,3 +548,664 @@ fn test_exec(mut exec_ctx: TestContext) {
     exec_ctx.add_extensions(CodegenExtsBuilder::add_default_prelude_extensions);
     assert_eq!(42, exec_ctx.exec_hugr_u64(hugr, "main"));
 }
+
+/// Builds a HUGR that sums the integers from `n` down to 1 in a `TailLoop`,
+/// breaking out of the loop when the counter reaches 0.
+fn tail_loop_sum_hugr(n: u64) -> Hugr {
+    let int_ty = INT_TYPES[6].clone();
+    SimpleHugrConfig::new()
+        .with_outs(int_ty.clone())
+        .with_extensions(INT_OPS_REGISTRY.to_owned())
+        .finish(|mut builder: DFGW| {
+            let counter = builder.add_load_value(ConstInt::new_u(6, n).unwrap());
+            let acc = builder.add_load_value(ConstInt::new_u(6, 0).unwrap());
+            let [_, acc] = {
+                let mut loop_b = builder
+                    .tail_loop_builder([], [(int_ty.clone(), counter), (int_ty, acc)], type_row![])
+                    .unwrap();
+                let [counter, acc] = loop_b.input_wires_arr();
+                let acc = loop_b.add_iadd(6, acc, counter).unwrap();
+                let minus_one = loop_b.add_load_value(ConstInt::new_s(6, -1).unwrap());
+                let counter = loop_b.add_iadd(6, counter, minus_one).unwrap();
+                let zero = loop_b.add_load_value(ConstInt::new_u(6, 0).unwrap());
+                // The control type of a TailLoop with empty "just inputs" and
+                // "just outputs" is a unit sum with two variants, i.e. a bool,
+                // where `true` means break.
+                let done = loop_b.add_ieq(6, counter, zero).unwrap();
+                loop_b
+                    .finish_with_outputs(done, [counter, acc])
+                    .unwrap()
+                    .outputs_arr()
+            };
+            builder.finish_with_outputs([acc]).unwrap()
+        })
+}
+
+/// Builds a HUGR that sums the integers from `n` down to 1 in a `CFG`. The
+/// loop block chooses its successor with a `Conditional`.
+fn cfg_sum_hugr(n: u64) -> Hugr {
+    let int_ty = INT_TYPES[6].clone();
+    SimpleHugrConfig::new()
+        .with_outs(int_ty.clone())
+        .with_extensions(INT_OPS_REGISTRY.to_owned())
+        .finish(|mut builder: DFGW| {
+            let counter = builder.add_load_value(ConstInt::new_u(6, n).unwrap());
+            let acc = builder.add_load_value(ConstInt::new_u(6, 0).unwrap());
+            let mut cfg_b = builder
+                .cfg_builder(
+                    [(int_ty.clone(), counter), (int_ty.clone(), acc)],
+                    vec![int_ty.clone()].into(),
+                )
+                .unwrap();
+            let entry = {
+                let mut entry_b = cfg_b
+                    .entry_builder([type_row![]], vec![int_ty.clone(), int_ty.clone()].into())
+                    .unwrap();
+                let [counter, acc] = entry_b.input_wires_arr();
+                let unit = entry_b.add_load_value(Value::unit());
+                entry_b.finish_with_outputs(unit, [counter, acc]).unwrap()
+            };
+            // The loop block branches back to itself with the new counter, or
+            // to the exit block. In either case it passes on the accumulator.
+            let loop_block = {
+                let sum_rows: Vec<TypeRow> = vec![vec![int_ty.clone()].into(), type_row![]];
+                let mut block_b = cfg_b
+                    .block_builder(
+                        vec![int_ty.clone(), int_ty.clone()].into(),
+                        sum_rows.clone(),
+                        vec![int_ty.clone()].into(),
+                    )
+                    .unwrap();
+                let [counter, acc] = block_b.input_wires_arr();
+                let acc = block_b.add_iadd(6, acc, counter).unwrap();
+                let minus_one = block_b.add_load_value(ConstInt::new_s(6, -1).unwrap());
+                let counter = block_b.add_iadd(6, counter, minus_one).unwrap();
+                let zero = block_b.add_load_value(ConstInt::new_u(6, 0).unwrap());
+                let done = block_b.add_ieq(6, counter, zero).unwrap();
+                let [branch] = {
+                    let mut cond_b = block_b
+                        .conditional_builder(
+                            ([type_row![], type_row![]], done),
+                            [(int_ty.clone(), counter)],
+                            vec![Type::new_sum(sum_rows.clone())].into(),
+                        )
+                        .unwrap();
+                    for tag in 0..2 {
+                        let mut case_b = cond_b.case_builder(tag).unwrap();
+                        let [counter] = case_b.input_wires_arr();
+                        let values = if tag == 0 { vec![counter] } else { vec![] };
+                        let branch = case_b.make_sum(tag, sum_rows.clone(), values).unwrap();
+                        case_b.finish_with_outputs([branch]).unwrap();
+                    }
+                    cond_b.finish_sub_container().unwrap().outputs_arr()
+                };
+                block_b.finish_with_outputs(branch, [acc]).unwrap()
+            };
+            let exit = cfg_b.exit_block();
+            cfg_b.branch(&entry, 0, &loop_block).unwrap();
+            cfg_b.branch(&loop_block, 0, &loop_block).unwrap();
+            cfg_b.branch(&loop_block, 1, &exit).unwrap();
+            let [r] = cfg_b.finish_sub_container().unwrap().outputs_arr();
+            builder.finish_with_outputs([r]).unwrap()
+        })
+}
+
+#[rstest]
+#[case(1, 1)]
+#[case(3, 6)]
+#[case(10, 55)]
+fn exec_tail_loop(mut exec_ctx: TestContext, #[case] n: u64, #[case] expected: u64) {
+    let hugr = tail_loop_sum_hugr(n);
+    exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions().add_int_extensions());
+    assert_eq!(expected, exec_ctx.exec_hugr_u64(hugr, "main"));
+}
+
+#[rstest]
+#[case::tail_loop(tail_loop_sum_hugr(10))]
+#[case::cfg(cfg_sum_hugr(10))]
+fn exec_ssa_mode(mut exec_ctx: TestContext, #[case] hugr: Hugr) {
+    use crate::utils::fat::FatExt as _;
+    exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions().add_int_extensions());
+    let emit_hugr = exec_ctx
+        .get_emit_hugr()
+        .with_mailbox_mode(super::MailBoxMode::Ssa);
+    let emission = Emission::emit_hugr(hugr.fat_root().unwrap(), emit_hugr).unwrap();
+    emission.verify().unwrap();
+    // No `mem2reg` is required.
+    let module_text = emission.module().print_to_string().to_string();
+    assert!(!module_text.contains("= alloca"), "{module_text}");
+    assert_eq!(55, emission.exec_u64("main").unwrap());
+}
+
+#[rstest]
+fn exec_poly_call(mut exec_ctx: TestContext) {
+    use crate::utils::fat::FatExt as _;
+    use hugr::types::{PolyFuncType, TypeBound};
+    let hugr = {
+        let mut mod_b = ModuleBuilder::new();
+        let tv = Type::new_var_use(0, TypeBound::Copyable);
+        let poly_sig = PolyFuncType::new(
+            [TypeBound::Copyable.into()],
+            HugrFuncType::new_endo(tv.clone()),
+        );
+        let id = {
+            let func_b = mod_b.define_function("id", poly_sig.clone()).unwrap();
+            let inputs = func_b.input_wires();
+            func_b.finish_with_outputs(inputs).unwrap()
+        };
+        // `wrap<T>` calls `id<T>`, so the type args of the inner call must be
+        // substituted when `wrap` is instantiated.
+        let wrap = {
+            let mut func_b = mod_b.define_function("wrap", poly_sig).unwrap();
+            let call = func_b
+                .call(id.handle(), &[tv.into()], func_b.input_wires(), &EMPTY_REG)
+                .unwrap();
+            func_b.finish_with_outputs(call.outputs()).unwrap()
+        };
+        let mut func_b = mod_b
+            .define_function("main", HugrFuncType::new(type_row![], USIZE_T))
+            .unwrap();
+        let k = func_b.add_load_value(ConstUsize::new(42));
+        let [r] = func_b
+            .call(wrap.handle(), &[USIZE_T.into()], [k], &PRELUDE_REGISTRY)
+            .unwrap()
+            .outputs_arr();
+        let b = func_b.add_load_value(Value::true_val());
+        let _ = func_b
+            .call(id.handle(), &[BOOL_T.into()], [b], &PRELUDE_REGISTRY)
+            .unwrap();
+        func_b.finish_with_outputs([r]).unwrap();
+        mod_b.finish_hugr(&PRELUDE_REGISTRY).unwrap()
+    };
+    exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions());
+
+    let emission = Emission::emit_hugr(hugr.fat_root().unwrap(), exec_ctx.get_emit_hugr()).unwrap();
+    emission.verify().unwrap();
+    let module = emission.module();
+    assert!(module.get_function("id<tE7:prelude5:usize0:>").is_some());
+    assert!(module.get_function("wrap<tE7:prelude5:usize0:>").is_some());
+    // No instantiation of `wrap` other than the one with `usize`, and the
+    // uninstantiated polymorphic functions are not emitted.
+    assert_eq!(
+        4,
+        module.get_functions().count(),
+        "{}",
+        module.print_to_string()
+    );
+    assert!(module.get_function("id").is_none());
+    assert_eq!(42, emission.exec_u64("main").unwrap());
+}
+
+#[rstest]
+fn emit_poly_recursion(llvm_ctx: TestContext) {
+    use crate::utils::fat::FatExt as _;
+    use hugr::types::{PolyFuncType, TypeBound};
+    // `rec<T>` calls `rec<(T)>`, so it has infinitely many instantiations.
+    let hugr = {
+        let mut mod_b = ModuleBuilder::new();
+        let tv = Type::new_var_use(0, TypeBound::Any);
+        let rec_sig = PolyFuncType::new([TypeBound::Any.into()], HugrFuncType::new_endo(vec![]));
+        let rec = mod_b.declare("rec", rec_sig).unwrap();
+        let mut rec_b = mod_b.define_declaration(&rec).unwrap();
+        let tuple_ty = Type::new_tuple(vec![tv]);
+        rec_b
+            .call(&rec, &[tuple_ty.into()], [], &PRELUDE_REGISTRY)
+            .unwrap();
+        rec_b.finish_with_outputs([]).unwrap();
+        let mut main_b = mod_b
+            .define_function("main", HugrFuncType::new_endo(vec![]))
+            .unwrap();
+        main_b
+            .call(&rec, &[USIZE_T.into()], [], &PRELUDE_REGISTRY)
+            .unwrap();
+        main_b.finish_with_outputs([]).unwrap();
+        mod_b.finish_hugr(&PRELUDE_REGISTRY).unwrap()
+    };
+    let emit_hugr = llvm_ctx.get_emit_hugr().with_max_instances(8);
+    let Err(err) = Emission::emit_hugr(hugr.fat_root().unwrap(), emit_hugr) else {
+        panic!("emission of a polymorphically recursive function should fail");
+    };
+    assert!(
+        format!("{err:#}").contains("more than 8 instantiations"),
+        "{err:#}"
+    );
+}
+
+#[rstest]
+fn exec_const_function(mut exec_ctx: TestContext) {
+    use hugr::builder::{DFGBuilder, DataflowHugr as _};
+    use hugr::ops::Const;
+    let int_ty = INT_TYPES[6].clone();
+    let add_one: Const = Value::function({
+        let mut builder = DFGBuilder::new(HugrFuncType::new_endo(int_ty.clone())).unwrap();
+        let [i] = builder.input_wires_arr();
+        let one = builder.add_load_value(ConstInt::new_u(6, 1).unwrap());
+        let r = builder.add_iadd(6, i, one).unwrap();
+        builder
+            .finish_hugr_with_outputs([r], &INT_OPS_REGISTRY)
+            .unwrap()
+    })
+    .unwrap()
+    .into();
+    let hugr = SimpleHugrConfig::new()
+        .with_outs(int_ty.clone())
+        .with_extensions(INT_OPS_REGISTRY.to_owned())
+        .finish(|mut builder: DFGW| {
+            // Both loads of the constant share a single emitted function.
+            let add_one = builder.add_constant(add_one);
+            let mut acc = builder.add_load_value(ConstInt::new_u(6, 40).unwrap());
+            for _ in 0..2 {
+                let func = builder.load_const(&add_one);
+                [acc] = builder
+                    .add_dataflow_op(
+                        CallIndirect {
+                            signature: HugrFuncType::new_endo(int_ty.clone()),
+                        },
+                        [func, acc],
+                    )
+                    .unwrap()
+                    .outputs_arr();
+            }
+            builder.finish_with_outputs([acc]).unwrap()
+        });
+    exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions().add_int_extensions());
+    assert_eq!(42, exec_ctx.exec_hugr_u64(hugr, "main"));
+}
+
+#[test]
+fn const_function_view() {
+    use hugr::builder::{DFGBuilder, DataflowHugr as _};
+    use hugr::ops::{handle::NodeHandle as _, Value};
+    let id = {
+        let builder = DFGBuilder::new(HugrFuncType::new_endo(BOOL_T)).unwrap();
+        let inputs = builder.input_wires();
+        builder
+            .finish_hugr_with_outputs(inputs, &PRELUDE_REGISTRY)
+            .unwrap()
+    };
+    let mut const_node = None;
+    let hugr = SimpleHugrConfig::new().finish(|mut builder: DFGW| {
+        const_node = Some(builder.add_constant(Value::function(id.clone()).unwrap()));
+        builder.finish_with_outputs([]).unwrap()
+    });
+    let const_node = const_node.unwrap().node();
+    let nested = |view: &Hugr| -> *const Hugr { view };
+    // Neither view copies the nested HUGR.
+    let by_value = hugr.with_const_function(const_node, nested).unwrap();
+    let by_ref =
+        <&Hugr>::with_const_function(&&hugr, const_node, |view: &&Hugr| nested(view)).unwrap();
+    assert_eq!(by_value, by_ref);
+    let Value::Function { hugr: expected } =
+        hugr.get_optype(const_node).as_const().unwrap().value()
+    else {
+        unreachable!()
+    };
+    assert_eq!(by_value, expected.as_ref() as *const Hugr);
+    assert!(hugr.with_const_function(hugr.root(), nested).is_none());
+}
+
+#[rstest]
+fn emit_nested_function_value(llvm_ctx: TestContext) {
+    use crate::emit::{EmitError, EmitErrorKind};
+    use crate::utils::fat::FatExt as _;
+    use hugr::builder::{DFGBuilder, DataflowHugr as _};
+    let id = Value::function({
+        let builder = DFGBuilder::new(HugrFuncType::new_endo(BOOL_T)).unwrap();
+        let inputs = builder.input_wires();
+        builder
+            .finish_hugr_with_outputs(inputs, &PRELUDE_REGISTRY)
+            .unwrap()
+    })
+    .unwrap();
+    let tuple = Value::tuple([id]);
+    let tuple_ty = tuple.get_type();
+    let hugr = SimpleHugrConfig::new()
+        .with_outs(tuple_ty)
+        .finish(|mut builder: DFGW| {
+            let t = builder.add_load_value(tuple);
+            builder.finish_with_outputs([t]).unwrap()
+        });
+    let err = Emission::emit_hugr(hugr.fat_root().unwrap(), llvm_ctx.get_emit_hugr())
+        .err()
+        .unwrap();
+    let err = err.downcast_ref::<EmitError>().unwrap();
+    assert!(
+        matches!(err.kind, EmitErrorKind::UnsupportedConst(_)),
+        "{err}"
+    );
+}
+
+#[rstest]
+fn exec_nested_const_function(mut exec_ctx: TestContext) {
+    use hugr::builder::{DFGBuilder, DataflowHugr as _};
+    use hugr::ops::{handle::NodeHandle as _, Const};
+    let int_ty = INT_TYPES[6].clone();
+    let call_indirect = CallIndirect {
+        signature: HugrFuncType::new_endo(int_ty.clone()),
+    };
+    let add_one: Const = Value::function({
+        let mut builder = DFGBuilder::new(HugrFuncType::new_endo(int_ty.clone())).unwrap();
+        let [i] = builder.input_wires_arr();
+        let one = builder.add_load_value(ConstInt::new_u(6, 1).unwrap());
+        let r = builder.add_iadd(6, i, one).unwrap();
+        builder
+            .finish_hugr_with_outputs([r], &INT_OPS_REGISTRY)
+            .unwrap()
+    })
+    .unwrap()
+    .into();
+    // A constant function which calls `add_one` twice, where `add_one` is
+    // itself a constant in the nested HUGR.
+    let inner_node;
+    let add_two: Const = Value::function({
+        let mut builder = DFGBuilder::new(HugrFuncType::new_endo(int_ty.clone())).unwrap();
+        let [mut acc] = builder.input_wires_arr();
+        // An unused constant, so that `add_one` has the same index as
+        // `add_two` has in the outer HUGR.
+        builder.add_constant(Value::from(ConstInt::new_u(6, 0).unwrap()));
+        let add_one = builder.add_constant(add_one);
+        inner_node = Some(add_one.node());
+        for _ in 0..2 {
+            let func = builder.load_const(&add_one);
+            [acc] = builder
+                .add_dataflow_op(call_indirect.clone(), [func, acc])
+                .unwrap()
+                .outputs_arr();
+        }
+        builder
+            .finish_hugr_with_outputs([acc], &INT_OPS_REGISTRY)
+            .unwrap()
+    })
+    .unwrap()
+    .into();
+    let mut outer_node = None;
+    let hugr = SimpleHugrConfig::new()
+        .with_outs(int_ty.clone())
+        .with_extensions(INT_OPS_REGISTRY.to_owned())
+        .finish(|mut builder: DFGW| {
+            let add_two = builder.add_constant(add_two);
+            outer_node = Some(add_two.node());
+            let func = builder.load_const(&add_two);
+            let acc = builder.add_load_value(ConstInt::new_u(6, 40).unwrap());
+            let [r] = builder
+                .add_dataflow_op(call_indirect, [func, acc])
+                .unwrap()
+                .outputs_arr();
+            builder.finish_with_outputs([r]).unwrap()
+        });
+    // The functions emitted for the constants must not collide, even though
+    // their nodes are the same in their respective HUGRs.
+    assert_eq!(inner_node, outer_node);
+    exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions().add_int_extensions());
+    assert_eq!(42, exec_ctx.exec_hugr_u64(hugr, "main"));
+}
+
+#[rstest]
+fn emission_order_is_discovery_order(llvm_ctx: TestContext) {
+    use crate::utils::fat::FatExt as _;
+    // Many scoped definitions, each with a scoped definition of its own. None
+    // are called, so each is declared in the module when it is emitted.
+    let hugr = SimpleHugrConfig::new()
+        .with_outs(BOOL_T)
+        .finish(|mut builder: DFGW| {
+            for i in 0..8 {
+                let mut func_b = builder
+                    .define_function(format!("f{i}"), HugrFuncType::new(type_row![], BOOL_T))
+                    .unwrap();
+                let mut inner_b = func_b
+                    .define_function(format!("g{i}"), HugrFuncType::new(type_row![], BOOL_T))
+                    .unwrap();
+                let w = inner_b.add_load_value(Value::true_val());
+                inner_b.finish_with_outputs([w]).unwrap();
+                let w = func_b.add_load_value(Value::true_val());
+                func_b.finish_with_outputs([w]).unwrap();
+            }
+            let w = builder.add_load_value(Value::false_val());
+            builder.finish_with_outputs([w]).unwrap()
+        });
+
+    let emit = || {
+        Emission::emit_hugr(hugr.fat_root().unwrap(), llvm_ctx.get_emit_hugr())
+            .unwrap()
+            .module()
+            .print_to_string()
+            .to_string()
+    };
+    let module = emit();
+    assert_eq!(module, emit());
+    // Functions are emitted breadth first: every `f{i}`, discovered while
+    // emitting `main`, before any `g{i}`, discovered while emitting `f{i}`.
+    let defined = module
+        .lines()
+        .filter_map(|l| l.strip_prefix("define "))
+        .map(|l| l.split('@').nth(1).unwrap().split('.').nth(1).unwrap())
+        .collect_vec();
+    let first_char = defined.iter().map(|f| &f[..1]).collect_vec();
+    let expected = std::iter::once("m")
+        .chain(["f"; 8])
+        .chain(["g"; 8])
+        .collect_vec();
+    assert_eq!(expected, first_char, "{defined:?}");
+}
+
+#[rstest]
+fn exec_debug_info(mut exec_ctx: TestContext) {
+    use crate::utils::fat::FatExt as _;
+    use hugr::hugr::hugrmut::HugrMut as _;
+    use hugr::ops::OpType;
+    exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions().add_int_extensions());
+    let mut hugr = tail_loop_sum_hugr(10);
+    let tail_loop = hugr
+        .nodes()
+        .find(|&n| matches!(hugr.get_optype(n), OpType::TailLoop(_)))
+        .unwrap();
+    hugr.set_metadata(
+        tail_loop,
+        super::debug_info::SPAN_METADATA_KEY,
+        serde_json::json!({"line": 42, "column": 7}),
+    );
+    let emit_hugr = exec_ctx
+        .get_emit_hugr()
+        .with_debug_info(&Default::default());
+    let emission = Emission::emit_hugr(hugr.fat_root().unwrap(), emit_hugr).unwrap();
+    emission.verify().unwrap();
+    let module_text = emission.module().print_to_string().to_string();
+    assert!(
+        module_text.contains("distinct !DISubprogram(name: \"main\""),
+        "{module_text}"
+    );
+    assert!(module_text.contains("line: 42, column: 7"), "{module_text}");
+    assert_eq!(55, emission.exec_u64("main").unwrap());
+}
+
+#[rstest]
+fn exec_node_metadata(mut exec_ctx: TestContext) {
+    use crate::utils::fat::FatExt as _;
+    use inkwell::values::InstructionOpcode;
+    exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions().add_int_extensions());
+    let hugr = tail_loop_sum_hugr(10);
+    let emit_hugr = exec_ctx.get_emit_hugr().with_node_metadata(true);
+    let emission = Emission::emit_hugr(hugr.fat_root().unwrap(), emit_hugr).unwrap();
+    emission.verify().unwrap();
+
+    let kind_id = exec_ctx
+        .iw_context()
+        .get_kind_id(super::debug_info::NODE_METADATA_KIND);
+    let main = emission.module().get_function("main").unwrap();
+    let instructions = main
+        .get_basic_blocks()
+        .into_iter()
+        .flat_map(|bb| {
+            std::iter::successors(bb.get_first_instruction(), |i| i.get_next_instruction())
+        })
+        .collect_vec();
+    // Only the instructions emitted for the function itself, outside of any
+    // node, are untagged: the alloca of its output, the branch out of the
+    // prologue, and the load and return of its output.
+    let untagged = instructions
+        .iter()
+        .filter(|i| i.get_metadata(kind_id).is_none())
+        .map(|i| i.get_opcode())
+        .collect_vec();
+    assert_eq!(
+        vec![
+            InstructionOpcode::Alloca,
+            InstructionOpcode::Br,
+            InstructionOpcode::Load,
+            InstructionOpcode::Return
+        ],
+        untagged
+    );
+    let tagged_ops = instructions
+        .iter()
+        .filter_map(|i| {
+            let md = i.get_metadata(kind_id)?;
+            let [_, op_name] = md.get_node_values()[..] else {
+                panic!("malformed node metadata: {md:?}")
+            };
+            Some(
+                op_name
+                    .into_metadata_value()
+                    .get_string_value()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_owned(),
+            )
+        })
+        .collect_vec();
+    // Instructions emitted by extensions are tagged too.
+    assert!(
+        tagged_ops.iter().any(|x| x == "arithmetic.int.iadd"),
+        "{tagged_ops:?}"
+    );
+    assert!(tagged_ops.iter().any(|x| x == "TailLoop"), "{tagged_ops:?}");
+    assert_eq!(55, emission.exec_u64("main").unwrap());
+}
+
+#[rstest]
+#[case::tail_loop(tail_loop_sum_hugr(10), "loop_exit_")]
+#[case::conditional(cfg_sum_hugr(10), "cond_exit_")]
+fn exec_node_metadata_exit_blocks(
+    mut exec_ctx: TestContext,
+    #[case] hugr: Hugr,
+    #[case] exit_prefix: &str,
+) {
+    use crate::utils::fat::FatExt as _;
+    use hugr::NodeIndex as _;
+    exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions().add_int_extensions());
+    let emit_hugr = exec_ctx.get_emit_hugr().with_node_metadata(true);
+    let emission = Emission::emit_hugr(hugr.fat_root().unwrap(), emit_hugr).unwrap();
+    emission.verify().unwrap();
+
+    let kind_id = exec_ctx
+        .iw_context()
+        .get_kind_id(super::debug_info::NODE_METADATA_KIND);
+    let main = emission.module().get_function("main").unwrap();
+    let exit_blocks = main
+        .get_basic_blocks()
+        .into_iter()
+        .filter_map(|bb| {
+            let name = bb.get_name().to_str().unwrap().to_owned();
+            let node: u64 = name.strip_prefix(exit_prefix)?.parse().unwrap();
+            Some((bb, node))
+        })
+        .collect_vec();
+    assert!(!exit_blocks.is_empty());
+    // The exit block of a Conditional or TailLoop is filled before the blocks
+    // of its children are emitted. It begins with the instructions of the
+    // parent, followed by those of the nodes after it, and has none from the
+    // children.
+    for (bb, node) in exit_blocks {
+        let tags = std::iter::successors(bb.get_first_instruction(), |i| i.get_next_instruction())
+            // The epilogue of the function itself is untagged.
+            .filter_map(|i| {
+                let md = i.get_metadata(kind_id)?;
+                let index = md.get_node_values()[0].into_int_value();
+                index.get_zero_extended_constant()
+            })
+            .collect_vec();
+        assert_eq!(Some(&node), tags.first(), "{tags:?}");
+        let parent = hugr.nodes().find(|n| n.index() as u64 == node).unwrap();
+        let mut descendants = vec![];
+        let mut stack = hugr.children(parent).collect_vec();
+        while let Some(n) = stack.pop() {
+            descendants.push(n.index() as u64);
+            stack.extend(hugr.children(n));
+        }
+        assert!(
+            tags.iter().all(|tag| !descendants.contains(tag)),
+            "{tags:?}"
+        );
+    }
+    assert_eq!(55, emission.exec_u64("main").unwrap());
+}
+
+#[rstest]
+fn emit_error_context(mut exec_ctx: TestContext) {
+    use crate::emit::EmitErrorKind;
+    use crate::utils::fat::FatExt as _;
+    use hugr::std_extensions::logic::{self, LogicOp};
+    let hugr = SimpleHugrConfig::new()
+        .with_ins(vec![BOOL_T; 2])
+        .with_outs(BOOL_T)
+        .with_extensions(ExtensionRegistry::try_new(vec![logic::EXTENSION.to_owned()]).unwrap())
+        .finish(|mut builder| {
+            let outputs = builder
+                .add_dataflow_op(LogicOp::And, builder.input_wires())
+                .unwrap()
+                .outputs();
+            builder.finish_with_outputs(outputs).unwrap()
+        });
+    let and_node = hugr
+        .nodes()
+        .find(|&n| matches!(hugr.get_optype(n), hugr::ops::OpType::ExtensionOp(_)))
+        .unwrap();
+
+    // No codegen extension for the logic ops is registered.
+    exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions());
+    let Err(err) = exec_ctx
+        .get_emit_hugr()
+        .emit_module(hugr.fat_root().unwrap())
+    else {
+        panic!("emission should fail without a codegen extension for logic.And");
+    };
+    assert!(matches!(err.kind, EmitErrorKind::UnsupportedOp(_)), "{err}");
+    let context = &err.context;
+    assert_eq!(context.node, and_node);
+    assert_eq!(context.op_name, "logic.And");
+    assert_eq!(context.func.as_deref(), Some("main"));
+}
+
+#[rstest]
+fn emit_extension_op_fallback(mut llvm_ctx: TestContext) {
+    use crate::custom::extension_op::{emit_extern_call_stub, extern_call_stub_symbol};
+    use crate::utils::fat::FatExt as _;
+    use hugr::ops::NamedOp as _;
+    use hugr::std_extensions::logic::{self, LogicOp};
+    let hugr = SimpleHugrConfig::new()
+        .with_ins(vec![BOOL_T; 2])
+        .with_outs(BOOL_T)
+        .with_extensions(ExtensionRegistry::try_new(vec![logic::EXTENSION.to_owned()]).unwrap())
+        .finish(|mut builder| {
+            let outputs = builder
+                .add_dataflow_op(LogicOp::And, builder.input_wires())
+                .unwrap()
+                .outputs();
+            builder.finish_with_outputs(outputs).unwrap()
+        });
+
+    // No codegen extension for the logic ops is registered, so `And` is
+    // emitted as a call to an external function.
+    llvm_ctx.add_extensions(|cge| {
+        cge.add_default_prelude_extensions()
+            .extension_op_fallback(emit_extern_call_stub)
+    });
+    let emission = Emission::emit_hugr(hugr.fat_root().unwrap(), llvm_ctx.get_emit_hugr()).unwrap();
+    emission.verify().unwrap();
+    let symbol = extern_call_stub_symbol(&logic::EXTENSION_ID, &LogicOp::And.name(), &[]);
+    assert_eq!(symbol, "logic__And");
+    let stub = emission.module().get_function(&symbol).unwrap();
+    assert!(stub.get_first_basic_block().is_none());
+    assert_eq!(stub.count_params(), 2);
+}
@@ -1,9 +1,12 @@
 pub mod conversions;
 pub mod float;
 pub mod int;
+pub mod list;
 pub mod logic;
 pub mod prelude;
 
+#[cfg(feature = "tket2")]
+pub mod quantum;
 #[cfg(feature = "tket2")]
 pub mod rotation;
 
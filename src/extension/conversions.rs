@@ -20,6 +20,7 @@ use crate::{
         ops::{emit_custom_unary_op, emit_value},
         EmitOpArgs,
     },
+    extension::int::build_int_normalize,
     sum::LLVMSumValue,
     types::HugrType,
 };
@@ -109,8 +110,10 @@ fn build_trunc_op<'c, H: HugrView>(
                 int_ty,
                 "trunc_result",
             )
-        }?
-        .as_basic_value_enum();
+        }?;
+        // Clear the high bits of negative sub-byte ints.
+        let trunc_result = build_int_normalize(ctx.builder(), trunc_result, log_width, false)?
+            .as_basic_value_enum();
 
         let err_msg = Value::extension(ConstError::new(
             2,
@@ -158,13 +161,21 @@ fn emit_conversion_op<'c, H: HugrView>(
                 .as_basic_value_enum()])
         }),
 
-        ConvertOpDef::convert_s => emit_custom_unary_op(context, args, |ctx, arg, out_tys| {
-            let out_ty = out_tys.last().unwrap();
-            Ok(vec![ctx
-                .builder()
-                .build_signed_int_to_float(arg.into_int_value(), out_ty.into_float_type(), "")?
-                .as_basic_value_enum()])
-        }),
+        ConvertOpDef::convert_s => {
+            let Some(TypeArg::BoundedNat { n: log_width }) = args.node().args().first().cloned()
+            else {
+                bail!("convert_s should have one type arg: the log-width of the int we're converting from")
+            };
+            emit_custom_unary_op(context, args, |ctx, arg, out_tys| {
+                let out_ty = out_tys.last().unwrap();
+                let arg =
+                    build_int_normalize(ctx.builder(), arg.into_int_value(), log_width, true)?;
+                Ok(vec![ctx
+                    .builder()
+                    .build_signed_int_to_float(arg, out_ty.into_float_type(), "")?
+                    .as_basic_value_enum()])
+            })
+        }
         // These ops convert between hugr's `USIZE` and u64. The former is
         // implementation-dependent and we define them to be the same.
         // Hence our implementation is a noop.
@@ -7,15 +7,75 @@ use hugr::{
     HugrView,
 };
 use inkwell::{
+    attributes::AttributeLoc,
     types::{BasicType, FloatType},
     values::{BasicValue, BasicValueEnum},
+    FloatPredicate,
 };
+use itertools::Itertools as _;
 
-use crate::emit::emit_value;
+use crate::emit::libc::{emit_libc_malloc, emit_libc_snprintf};
 use crate::emit::ops::{emit_custom_binary_op, emit_custom_unary_op};
+use crate::emit::{emit_value, get_intrinsic};
 use crate::emit::{func::EmitFuncContext, EmitOpArgs};
 
-use crate::custom::CodegenExtsBuilder;
+use crate::custom::{CodegenExtension, CodegenExtsBuilder};
+
+/// The size of the buffer into which `ftostring` formats a float. This is
+/// enough for any `f64` formatted with `%.17g`.
+const FTOSTRING_BUFFER_SIZE: u64 = 32;
+
+/// Emit a float operation as a call to an LLVM intrinsic, e.g. `llvm.fabs`,
+/// overloaded on the type of its arguments.
+fn emit_float_intrinsic<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    name: &str,
+) -> Result<()> {
+    let float_ty = context.iw_context().f64_type();
+    let intrinsic = get_intrinsic(context.get_current_module(), name, [float_ty.into()])?;
+    let inputs = args.inputs.iter().map(|&v| v.into()).collect_vec();
+    let r = context
+        .builder()
+        .build_call(intrinsic, &inputs, "")?
+        .try_as_basic_value()
+        .left()
+        .ok_or(anyhow!("{name} returned void"))?;
+    args.outputs.finish(context.builder(), [r])
+}
+
+/// Emit `ftostring` as a call to `snprintf`, formatting with `%.17g` into a
+/// buffer allocated with `malloc`. Seventeen significant digits are enough for
+/// the string to parse back to the same `f64`.
+///
+/// Strings have no drop op, so the buffer is only freed when the string is
+/// passed straight to a `print`, which then owns it and releases it with
+/// [PreludeCodegen::emit_free_string] (see [crate::utils::ownership]).
+/// Otherwise the buffer is owned by whoever receives the string, e.g. the
+/// caller of the entry point, which may release it with `free`.
+///
+/// [PreludeCodegen::emit_free_string]: crate::extension::prelude::PreludeCodegen::emit_free_string
+fn emit_ftostring<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+) -> Result<()> {
+    emit_custom_unary_op(context, args, |ctx, v, _| {
+        let size = ctx
+            .iw_context()
+            .i64_type()
+            .const_int(FTOSTRING_BUFFER_SIZE, false);
+        let buffer = emit_libc_malloc(ctx, size)?;
+        let format_str = ctx
+            .builder()
+            .build_global_string_ptr("%.17g", "float.ftostring_template")?
+            .as_pointer_value();
+        emit_libc_snprintf(
+            ctx,
+            &[buffer.into(), size.into(), format_str.into(), v.into()],
+        )?;
+        Ok(vec![buffer.into()])
+    })
+}
 
 /// Emit a float comparison operation.
 fn emit_fcmp<'c, H: HugrView>(
@@ -43,19 +103,46 @@ fn emit_fcmp<'c, H: HugrView>(
 
 fn emit_float_op<'c, H: HugrView>(
     context: &mut EmitFuncContext<'c, '_, H>,
+    config: &FloatCodegenExtension,
     args: EmitOpArgs<'c, '_, ExtensionOp, H>,
     op: FloatOps,
 ) -> Result<()> {
-    // We emit the float comparison variants where NaN is an absorbing value.
-    // Any comparison with NaN is always false.
-    #[allow(clippy::wildcard_in_or_patterns)]
+    config.add_function_attributes(context)?;
+    let cmp = config.comparisons;
     match op {
-        FloatOps::feq => emit_fcmp(context, args, inkwell::FloatPredicate::OEQ),
-        FloatOps::fne => emit_fcmp(context, args, inkwell::FloatPredicate::ONE),
-        FloatOps::flt => emit_fcmp(context, args, inkwell::FloatPredicate::OLT),
-        FloatOps::fgt => emit_fcmp(context, args, inkwell::FloatPredicate::OGT),
-        FloatOps::fle => emit_fcmp(context, args, inkwell::FloatPredicate::OLE),
-        FloatOps::fge => emit_fcmp(context, args, inkwell::FloatPredicate::OGE),
+        FloatOps::feq => emit_fcmp(
+            context,
+            args,
+            cmp.predicate(FloatPredicate::OEQ, FloatPredicate::UEQ),
+        ),
+        FloatOps::fne => emit_fcmp(
+            context,
+            args,
+            match cmp {
+                FloatComparisons::Ordered => FloatPredicate::ONE,
+                FloatComparisons::Unordered | FloatComparisons::Ieee => FloatPredicate::UNE,
+            },
+        ),
+        FloatOps::flt => emit_fcmp(
+            context,
+            args,
+            cmp.predicate(FloatPredicate::OLT, FloatPredicate::ULT),
+        ),
+        FloatOps::fgt => emit_fcmp(
+            context,
+            args,
+            cmp.predicate(FloatPredicate::OGT, FloatPredicate::UGT),
+        ),
+        FloatOps::fle => emit_fcmp(
+            context,
+            args,
+            cmp.predicate(FloatPredicate::OLE, FloatPredicate::ULE),
+        ),
+        FloatOps::fge => emit_fcmp(
+            context,
+            args,
+            cmp.predicate(FloatPredicate::OGE, FloatPredicate::UGE),
+        ),
         FloatOps::fadd => emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
             Ok(vec![ctx
                 .builder()
@@ -86,14 +173,15 @@ fn emit_float_op<'c, H: HugrView>(
                 .build_float_div(lhs.into_float_value(), rhs.into_float_value(), "")?
                 .as_basic_value_enum()])
         }),
-        // Missing ops, not supported by inkwell
-        FloatOps::fmax
-        | FloatOps::fmin
-        | FloatOps::fabs
-        | FloatOps::ffloor
-        | FloatOps::fceil
-        | FloatOps::ftostring
-        | _ => {
+        // `llvm.maxnum` and `llvm.minnum` return the other operand if one is
+        // NaN.
+        FloatOps::fmax => emit_float_intrinsic(context, args, "llvm.maxnum"),
+        FloatOps::fmin => emit_float_intrinsic(context, args, "llvm.minnum"),
+        FloatOps::fabs => emit_float_intrinsic(context, args, "llvm.fabs"),
+        FloatOps::ffloor => emit_float_intrinsic(context, args, "llvm.floor"),
+        FloatOps::fceil => emit_float_intrinsic(context, args, "llvm.ceil"),
+        FloatOps::ftostring => emit_ftostring(context, args),
+        _ => {
             let name: &str = op.into();
             Err(anyhow!("FloatOpEmitter: unimplemented op: {name}"))
         }
@@ -108,18 +196,240 @@ fn emit_constf64<'c, H: HugrView>(
     Ok(ty.const_float(k.value()).as_basic_value_enum())
 }
 
+/// Which LLVM fast-math assumptions may be made about float ops.
+///
+/// The LLVM 14 C API cannot set fast-math flags on individual instructions,
+/// so each flag is emitted as the corresponding function attribute, e.g.
+/// `"no-nans-fp-math"="true"`, on functions containing float ops. These are
+/// respected by LLVM's code generators and by some optimisation passes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FastMathFlags {
+    /// Assume that arguments and results are never NaN.
+    pub no_nans: bool,
+    /// Assume that arguments and results are never infinite.
+    pub no_infs: bool,
+    /// Treat the sign of a zero argument or result as insignificant.
+    pub no_signed_zeros: bool,
+    /// Allow transformations which are algebraically but not numerically
+    /// equivalent, e.g. reassociation.
+    pub unsafe_algebra: bool,
+    /// Allow library functions and intrinsics to be replaced with
+    /// approximations.
+    pub approx_func: bool,
+}
+
+impl FastMathFlags {
+    /// No fast-math assumptions. This is the default.
+    pub const NONE: Self = Self {
+        no_nans: false,
+        no_infs: false,
+        no_signed_zeros: false,
+        unsafe_algebra: false,
+        approx_func: false,
+    };
+
+    /// All fast-math assumptions.
+    pub const ALL: Self = Self {
+        no_nans: true,
+        no_infs: true,
+        no_signed_zeros: true,
+        unsafe_algebra: true,
+        approx_func: true,
+    };
+
+    /// The names of the function attributes of the flags which are set.
+    fn attribute_names(&self) -> impl Iterator<Item = &'static str> {
+        [
+            (self.no_nans, "no-nans-fp-math"),
+            (self.no_infs, "no-infs-fp-math"),
+            (self.no_signed_zeros, "no-signed-zeros-fp-math"),
+            (self.unsafe_algebra, "unsafe-fp-math"),
+            (self.approx_func, "approx-func-fp-math"),
+        ]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+    }
+}
+
+/// Which LLVM predicates float comparisons are lowered to, and so how they
+/// treat NaN operands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FloatComparisons {
+    /// Ordered predicates, e.g. `fcmp one`. Any comparison with NaN is false,
+    /// including `fne`.
+    #[default]
+    Ordered,
+    /// Unordered predicates, e.g. `fcmp une`. Any comparison with NaN is true.
+    Unordered,
+    /// The comparisons of IEEE 754: `fne` is lowered to `fcmp une`, and so is
+    /// true when either operand is NaN. All other comparisons are ordered.
+    Ieee,
+}
+
+impl FloatComparisons {
+    /// Selects between the `ordered` and `unordered` variants of a predicate
+    /// other than that of `fne`.
+    fn predicate(self, ordered: FloatPredicate, unordered: FloatPredicate) -> FloatPredicate {
+        match self {
+            FloatComparisons::Ordered | FloatComparisons::Ieee => ordered,
+            FloatComparisons::Unordered => unordered,
+        }
+    }
+}
+
+/// How denormal floats are handled. This is emitted as the
+/// `"denormal-fp-math"` attribute of functions containing float ops.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DenormalMode {
+    /// Denormals are supported as specified by IEEE 754.
+    Ieee,
+    /// Denormal inputs and outputs may be flushed to zero, preserving their
+    /// sign.
+    PreserveSign,
+    /// Denormal inputs and outputs may be flushed to positive zero.
+    PositiveZero,
+}
+
+impl DenormalMode {
+    fn attribute_value(self) -> &'static str {
+        match self {
+            DenormalMode::Ieee => "ieee,ieee",
+            DenormalMode::PreserveSign => "preserve-sign,preserve-sign",
+            DenormalMode::PositiveZero => "positive-zero,positive-zero",
+        }
+    }
+}
+
+/// A codegen extension for the `arithmetic.float` and `arithmetic.float.types`
+/// extensions.
+///
+/// By default no fast-math assumptions are made, comparisons are
+/// [FloatComparisons::Ordered], and no denormal handling is specified, which
+/// LLVM takes to mean [DenormalMode::Ieee].
+///
+/// The [FastMathFlags] and [DenormalMode] are emitted as function attributes,
+/// so they apply to the whole of any function containing a float op: to float
+/// code emitted by other extensions, e.g. conversions or rotations, and to
+/// the bodies of callees after LLVM inlines them into that function.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FloatCodegenExtension {
+    fast_math: FastMathFlags,
+    comparisons: FloatComparisons,
+    denormals: Option<DenormalMode>,
+}
+
+impl FloatCodegenExtension {
+    /// Returns a new `FloatCodegenExtension` with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a new `FloatCodegenExtension` for bit-exact, reproducible
+    /// results: no fast-math assumptions, [FloatComparisons::Ieee] and
+    /// [DenormalMode::Ieee].
+    pub fn strict() -> Self {
+        Self::new()
+            .with_comparisons(FloatComparisons::Ieee)
+            .with_denormals(DenormalMode::Ieee)
+    }
+
+    /// Returns a new `FloatCodegenExtension` making all fast-math assumptions
+    /// and flushing denormals to zero.
+    pub fn fast() -> Self {
+        Self::new()
+            .with_fast_math(FastMathFlags::ALL)
+            .with_denormals(DenormalMode::PreserveSign)
+    }
+
+    /// Returns a new `FloatCodegenExtension` making the fast-math assumptions
+    /// of `fast_math`.
+    pub fn with_fast_math(mut self, fast_math: FastMathFlags) -> Self {
+        self.fast_math = fast_math;
+        self
+    }
+
+    /// Returns a new `FloatCodegenExtension` lowering comparisons according to
+    /// `comparisons`.
+    pub fn with_comparisons(mut self, comparisons: FloatComparisons) -> Self {
+        self.comparisons = comparisons;
+        self
+    }
+
+    /// Returns a new `FloatCodegenExtension` handling denormals according to
+    /// `denormals`.
+    pub fn with_denormals(mut self, denormals: DenormalMode) -> Self {
+        self.denormals = Some(denormals);
+        self
+    }
+
+    /// Adds the function attributes of this configuration to the function
+    /// into which `context` is emitting.
+    ///
+    /// This is called for every float op, but each attribute is only set by
+    /// the first op emitted into a function.
+    fn add_function_attributes<H: HugrView>(
+        &self,
+        context: &EmitFuncContext<'_, '_, H>,
+    ) -> Result<()> {
+        let func = context
+            .builder()
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .ok_or(anyhow!("FloatCodegenExtension: builder is not positioned"))?;
+        let iw_ctx = context.iw_context();
+        let attributes = self
+            .fast_math
+            .attribute_names()
+            .map(|name| (name, "true"))
+            .chain(
+                self.denormals
+                    .map(|d| ("denormal-fp-math", d.attribute_value())),
+            );
+        for (key, value) in attributes {
+            if func
+                .get_string_attribute(AttributeLoc::Function, key)
+                .is_some()
+            {
+                continue;
+            }
+            func.add_attribute(
+                AttributeLoc::Function,
+                iw_ctx.create_string_attribute(key, value),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl CodegenExtension for FloatCodegenExtension {
+    fn add_extension<'a, H: HugrView + 'a>(
+        self,
+        builder: CodegenExtsBuilder<'a, H>,
+    ) -> CodegenExtsBuilder<'a, H>
+    where
+        Self: 'a,
+    {
+        builder
+            .custom_type(
+                (
+                    float_types::EXTENSION_ID,
+                    float_types::FLOAT64_CUSTOM_TYPE.name().clone(),
+                ),
+                |ts, _custom_type| Ok(ts.iw_context().f64_type().as_basic_type_enum()),
+            )
+            .custom_const(emit_constf64)
+            .simple_extension_op::<FloatOps>(move |context, args, op| {
+                emit_float_op(context, &self, args, op)
+            })
+    }
+}
+
+/// Populates a [CodegenExtsBuilder] with all extensions needed to lower float
+/// ops, types, and constants, with the default [FloatCodegenExtension].
 pub fn add_float_extensions<'a, H: HugrView + 'a>(
     cem: CodegenExtsBuilder<'a, H>,
 ) -> CodegenExtsBuilder<'a, H> {
-    cem.custom_type(
-        (
-            float_types::EXTENSION_ID,
-            float_types::FLOAT64_CUSTOM_TYPE.name().clone(),
-        ),
-        |ts, _custom_type| Ok(ts.iw_context().f64_type().as_basic_type_enum()),
-    )
-    .custom_const(emit_constf64)
-    .simple_extension_op::<FloatOps>(emit_float_op)
+    cem.add_extension(FloatCodegenExtension::new())
 }
 
 impl<'a, H: HugrView + 'a> CodegenExtsBuilder<'a, H> {
@@ -134,21 +444,25 @@ mod test {
     use hugr::extension::SignatureFunc;
     use hugr::std_extensions::arithmetic::float_ops::{self, FloatOps};
     use hugr::types::TypeRow;
-    use hugr::Hugr;
     use hugr::{
-        builder::{Dataflow, DataflowSubContainer},
+        builder::{Dataflow, DataflowSubContainer, SubContainer},
+        extension::prelude::{ConstUsize, PRELUDE, PRELUDE_REGISTRY, PRINT_OP_ID, USIZE_T},
         std_extensions::arithmetic::{
             float_ops::FLOAT_OPS_REGISTRY,
             float_types::{ConstF64, FLOAT64_TYPE},
         },
     };
+    use hugr::{type_row, Hugr, Wire};
     use rstest::rstest;
 
-    use super::add_float_extensions;
+    use super::{
+        add_float_extensions, DenormalMode, FastMathFlags, FloatCodegenExtension, FloatComparisons,
+    };
     use crate::{
         check_emission,
-        emit::test::SimpleHugrConfig,
-        test::{llvm_ctx, TestContext},
+        emit::test::{Emission, SimpleHugrConfig, DFGW},
+        test::{exec_ctx, llvm_ctx, TestContext},
+        utils::fat::FatExt as _,
     };
 
     fn test_float_op(op: FloatOps) -> Hugr {
@@ -203,4 +517,250 @@ mod test {
         llvm_ctx.add_extensions(add_float_extensions);
         check_emission!(name, hugr, llvm_ctx);
     }
+
+    /// Loads the float `x`. [ConstF64] must be finite, so infinities and NaNs
+    /// are built by dividing by zero.
+    fn load_float(builder: &mut DFGW, x: f64) -> Wire {
+        if x.is_finite() {
+            return builder.add_load_value(ConstF64::new(x));
+        }
+        let numerator = if x.is_nan() { 0.0 } else { x.signum() };
+        let [n, d] = [numerator, 0.0].map(|v| builder.add_load_value(ConstF64::new(v)));
+        builder
+            .add_dataflow_op(FloatOps::fdiv, [n, d])
+            .unwrap()
+            .out_wire(0)
+    }
+
+    /// A HUGR applying `op` to constant `inputs`.
+    fn const_float_op_hugr(op: FloatOps, inputs: &[f64]) -> Hugr {
+        let SignatureFunc::PolyFuncType(poly_sig) = op.signature() else {
+            panic!("Expected PolyFuncType");
+        };
+        let out: TypeRow = poly_sig.body().output.clone().try_into().unwrap();
+        SimpleHugrConfig::new()
+            .with_outs(out)
+            .with_extensions(FLOAT_OPS_REGISTRY.to_owned())
+            .finish(|mut builder| {
+                let ins = inputs
+                    .iter()
+                    .map(|&x| load_float(&mut builder, x))
+                    .collect::<Vec<_>>();
+                let outputs = builder.add_dataflow_op(op, ins).unwrap().outputs();
+                builder.finish_with_outputs(outputs).unwrap()
+            })
+    }
+
+    #[rstest]
+    #[case::fmax(FloatOps::fmax, &[1.0, 2.0], 2.0)]
+    #[case::fmax_nan_lhs(FloatOps::fmax, &[f64::NAN, 1.0], 1.0)]
+    #[case::fmax_nan_rhs(FloatOps::fmax, &[-1.0, f64::NAN], -1.0)]
+    #[case::fmax_nan_both(FloatOps::fmax, &[f64::NAN, f64::NAN], f64::NAN)]
+    #[case::fmin(FloatOps::fmin, &[1.0, 2.0], 1.0)]
+    #[case::fmin_nan_lhs(FloatOps::fmin, &[f64::NAN, 1.0], 1.0)]
+    #[case::fmin_nan_rhs(FloatOps::fmin, &[-1.0, f64::NAN], -1.0)]
+    #[case::fmin_nan_both(FloatOps::fmin, &[f64::NAN, f64::NAN], f64::NAN)]
+    #[case::fabs(FloatOps::fabs, &[-1.5], 1.5)]
+    #[case::fabs_neg_zero(FloatOps::fabs, &[-0.0], 0.0)]
+    #[case::fabs_nan(FloatOps::fabs, &[f64::NAN], f64::NAN)]
+    #[case::fabs_neg_inf(FloatOps::fabs, &[f64::NEG_INFINITY], f64::INFINITY)]
+    #[case::ffloor(FloatOps::ffloor, &[-1.5], -2.0)]
+    #[case::ffloor_pos(FloatOps::ffloor, &[0.5], 0.0)]
+    #[case::ffloor_neg_zero(FloatOps::ffloor, &[-0.0], -0.0)]
+    #[case::ffloor_nan(FloatOps::ffloor, &[f64::NAN], f64::NAN)]
+    #[case::fceil(FloatOps::fceil, &[1.5], 2.0)]
+    #[case::fceil_neg(FloatOps::fceil, &[-0.5], -0.0)]
+    #[case::fceil_nan(FloatOps::fceil, &[f64::NAN], f64::NAN)]
+    fn exec_float_op(
+        mut exec_ctx: TestContext,
+        #[case] op: FloatOps,
+        #[case] inputs: &[f64],
+        #[case] expected: f64,
+    ) {
+        exec_ctx.add_extensions(add_float_extensions);
+        let hugr = const_float_op_hugr(op, inputs);
+        let r = exec_ctx.exec_hugr_f64(hugr, "main");
+        if expected.is_nan() {
+            assert!(r.is_nan(), "expected NaN, got {r}");
+        } else {
+            // Compare bits, so that the sign of zero is checked.
+            assert_eq!(
+                expected.to_bits(),
+                r.to_bits(),
+                "expected {expected}, got {r}"
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(1.5, "1.5")]
+    #[case(-2.0, "-2")]
+    #[case(0.0, "0")]
+    #[case(-0.0, "-0")]
+    #[case(1e100, "1e+100")]
+    #[case(0.1, "0.10000000000000001")]
+    #[case(1.0 / 3.0, "0.33333333333333331")]
+    #[case(f64::INFINITY, "inf")]
+    #[case(f64::NEG_INFINITY, "-inf")]
+    #[case(f64::NAN, "nan")]
+    fn exec_ftostring(mut exec_ctx: TestContext, #[case] x: f64, #[case] expected: &str) {
+        exec_ctx.add_extensions(|cge| cge.add_float_extensions().add_default_prelude_extensions());
+        let hugr = const_float_op_hugr(FloatOps::ftostring, &[x]);
+        let r = exec_ctx.exec_hugr_string(hugr, "main");
+        // The sign of a NaN built at runtime depends on the platform.
+        let r = if x.is_nan() {
+            r.trim_start_matches('-')
+        } else {
+            &r
+        };
+        assert_eq!(expected, r);
+    }
+
+    #[rstest]
+    #[case::print_once(1, true)]
+    #[case::print_twice(2, false)]
+    fn ftostring_print_frees_owned_string(
+        mut llvm_ctx: TestContext,
+        #[case] num_prints: usize,
+        #[case] expect_free: bool,
+    ) {
+        llvm_ctx.add_extensions(|cge| cge.add_float_extensions().add_default_prelude_extensions());
+        let print_op = PRELUDE
+            .instantiate_extension_op(&PRINT_OP_ID, [], &PRELUDE_REGISTRY)
+            .unwrap();
+        let hugr = SimpleHugrConfig::new()
+            .with_extensions(FLOAT_OPS_REGISTRY.to_owned())
+            .finish(|mut builder| {
+                let x = load_float(&mut builder, 1.5);
+                let [s] = builder
+                    .add_dataflow_op(FloatOps::ftostring, [x])
+                    .unwrap()
+                    .outputs_arr();
+                for _ in 0..num_prints {
+                    builder.add_dataflow_op(print_op.clone(), [s]).unwrap();
+                }
+                builder.finish_with_outputs([]).unwrap()
+            });
+        let emission =
+            Emission::emit_hugr(hugr.fat_root().unwrap(), llvm_ctx.get_emit_hugr()).unwrap();
+        emission.verify().unwrap();
+        let module = emission.module().print_to_string().to_string();
+        assert_eq!(expect_free, module.contains("call void @free("), "{module}");
+    }
+
+    /// With the default configuration, comparisons with NaN are ordered, as
+    /// they were before comparisons were configurable, so all are false,
+    /// including `fne`.
+    #[rstest]
+    fn exec_default_nan_comparisons(
+        mut exec_ctx: TestContext,
+        #[values(
+            FloatOps::feq,
+            FloatOps::fne,
+            FloatOps::flt,
+            FloatOps::fgt,
+            FloatOps::fle,
+            FloatOps::fge
+        )]
+        op: FloatOps,
+        #[values([f64::NAN, 1.0], [1.0, f64::NAN], [f64::NAN, f64::NAN])] inputs: [f64; 2],
+    ) {
+        let hugr = SimpleHugrConfig::new()
+            .with_outs(vec![USIZE_T])
+            .with_extensions(FLOAT_OPS_REGISTRY.to_owned())
+            .finish(|mut builder| {
+                let ins = inputs.map(|x| load_float(&mut builder, x));
+                let [b] = builder.add_dataflow_op(op, ins).unwrap().outputs_arr();
+                let mut cond = builder
+                    .conditional_builder(([type_row![], type_row![]], b), [], type_row![USIZE_T])
+                    .unwrap();
+                for (tag, r) in [0, 1].into_iter().enumerate() {
+                    let mut case = cond.case_builder(tag).unwrap();
+                    let r = case.add_load_value(ConstUsize::new(r));
+                    case.finish_with_outputs([r]).unwrap();
+                }
+                let [r] = cond.finish_sub_container().unwrap().outputs_arr();
+                builder.finish_with_outputs([r]).unwrap()
+            });
+        exec_ctx.add_extensions(|cge| cge.add_float_extensions().add_default_prelude_extensions());
+        assert_eq!(0, exec_ctx.exec_hugr_u64(hugr, "main"));
+    }
+
+    fn emit_with_config(
+        mut llvm_ctx: TestContext,
+        config: FloatCodegenExtension,
+        op: FloatOps,
+    ) -> String {
+        llvm_ctx.add_extensions(move |cge| cge.add_extension(config));
+        let hugr = const_float_op_hugr(op, &[1.0, 2.0]);
+        let emission =
+            Emission::emit_hugr(hugr.fat_root().unwrap(), llvm_ctx.get_emit_hugr()).unwrap();
+        emission.verify().unwrap();
+        emission.module().print_to_string().to_string()
+    }
+
+    #[rstest]
+    #[case::feq_ordered(FloatComparisons::Ordered, FloatOps::feq, "fcmp oeq")]
+    #[case::fne_ordered(FloatComparisons::Ordered, FloatOps::fne, "fcmp one")]
+    #[case::flt_ordered(FloatComparisons::Ordered, FloatOps::flt, "fcmp olt")]
+    #[case::feq_unordered(FloatComparisons::Unordered, FloatOps::feq, "fcmp ueq")]
+    #[case::fne_unordered(FloatComparisons::Unordered, FloatOps::fne, "fcmp une")]
+    #[case::fge_unordered(FloatComparisons::Unordered, FloatOps::fge, "fcmp uge")]
+    #[case::feq_ieee(FloatComparisons::Ieee, FloatOps::feq, "fcmp oeq")]
+    #[case::fne_ieee(FloatComparisons::Ieee, FloatOps::fne, "fcmp une")]
+    #[case::fle_ieee(FloatComparisons::Ieee, FloatOps::fle, "fcmp ole")]
+    fn float_comparisons(
+        llvm_ctx: TestContext,
+        #[case] comparisons: FloatComparisons,
+        #[case] op: FloatOps,
+        #[case] expected: &str,
+    ) {
+        let config = FloatCodegenExtension::new().with_comparisons(comparisons);
+        let module = emit_with_config(llvm_ctx, config, op);
+        assert!(module.contains(expected), "{module}");
+    }
+
+    #[rstest]
+    #[case::default(FloatCodegenExtension::new(), &[], &["-fp-math"])]
+    #[case::strict(
+        FloatCodegenExtension::strict(),
+        &["\"denormal-fp-math\"=\"ieee,ieee\""],
+        &["\"true\""]
+    )]
+    #[case::fast(
+        FloatCodegenExtension::fast(),
+        &[
+            "\"no-nans-fp-math\"=\"true\"",
+            "\"no-infs-fp-math\"=\"true\"",
+            "\"no-signed-zeros-fp-math\"=\"true\"",
+            "\"unsafe-fp-math\"=\"true\"",
+            "\"approx-func-fp-math\"=\"true\"",
+            "\"denormal-fp-math\"=\"preserve-sign,preserve-sign\"",
+        ],
+        &[]
+    )]
+    #[case::no_nans(
+        FloatCodegenExtension::new()
+            .with_fast_math(FastMathFlags { no_nans: true, ..FastMathFlags::NONE })
+            .with_denormals(DenormalMode::PositiveZero),
+        &[
+            "\"no-nans-fp-math\"=\"true\"",
+            "\"denormal-fp-math\"=\"positive-zero,positive-zero\"",
+        ],
+        &["no-infs-fp-math", "unsafe-fp-math"]
+    )]
+    fn float_function_attributes(
+        llvm_ctx: TestContext,
+        #[case] config: FloatCodegenExtension,
+        #[case] expected: &[&str],
+        #[case] unexpected: &[&str],
+    ) {
+        let module = emit_with_config(llvm_ctx, config, FloatOps::fadd);
+        for attr in expected {
+            assert!(module.contains(attr), "{attr} not in {module}");
+        }
+        for attr in unexpected {
+            assert!(!module.contains(attr), "{attr} in {module}");
+        }
+    }
 }
@@ -1,27 +1,32 @@
 use hugr::{
-    ops::{constant::CustomConst, ExtensionOp, NamedOp, Value},
+    extension::prelude::ConstError,
+    ops::{constant::CustomConst, DataflowOpTrait as _, ExtensionOp, NamedOp, Value},
     std_extensions::arithmetic::{
         int_ops::IntOpDef,
         int_types::{self, ConstInt},
     },
-    types::{CustomType, TypeArg},
+    types::{CustomType, SumType, TypeArg, TypeEnum},
     HugrView,
 };
 use inkwell::{
+    builder::Builder,
     types::{BasicTypeEnum, IntType},
-    values::{BasicValue, BasicValueEnum},
+    values::{BasicValue, BasicValueEnum, IntValue},
 };
+use itertools::Itertools as _;
 
 use crate::{
-    custom::CodegenExtsBuilder,
+    custom::{CodegenExtension, CodegenExtsBuilder},
     emit::{
-        emit_value, func::EmitFuncContext, ops::emit_custom_binary_op, ops::emit_custom_unary_op,
-        EmitOpArgs,
+        emit_value, func::EmitFuncContext, get_intrinsic, ops::emit_custom_binary_op,
+        ops::emit_custom_unary_op, EmitErrorKind, EmitOpArgs,
     },
+    extension::prelude::{DefaultPreludeCodegen, PreludeCodegen},
+    sum::LLVMSumType,
     types::TypingSession,
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 
 /// Emit an integer comparison operation.
 fn emit_icmp<'c, H: HugrView>(
@@ -47,55 +52,692 @@ fn emit_icmp<'c, H: HugrView>(
     })
 }
 
-fn emit_int_op<'c, H: HugrView>(
+/// Emit a logical shift. The result is zero when the shift amount, the
+/// unsigned interpretation of the second input, is at least the bit width.
+fn emit_ishift<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    log_width: u64,
+    left: bool,
+) -> Result<()> {
+    emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
+        let (lhs, rhs) = (lhs.into_int_value(), rhs.into_int_value());
+        let ty = lhs.get_type();
+        let builder = ctx.builder();
+        // In LLVM a shift by at least the bit width is poison, so we select
+        // zero in that case.
+        let width = rhs.get_type().const_int(ty.get_bit_width() as u64, false);
+        let in_range =
+            builder.build_int_compare(inkwell::IntPredicate::ULT, rhs, width, "in_range")?;
+        let rhs = builder.build_int_cast_sign_flag(rhs, ty, false, "")?;
+        let shifted = if left {
+            let shifted = builder.build_left_shift(lhs, rhs, "")?;
+            build_int_normalize(builder, shifted, log_width, false)?
+        } else {
+            builder.build_right_shift(lhs, rhs, false, "")?
+        };
+        Ok(vec![builder.build_select(
+            in_range,
+            shifted,
+            ty.const_zero(),
+            "",
+        )?])
+    })
+}
+
+/// Emit a rotation, by the unsigned interpretation of the second input modulo
+/// the bit width, using the `llvm.fshl` or `llvm.fshr` intrinsics.
+fn emit_irot<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    log_width: u64,
+    left: bool,
+) -> Result<()> {
+    emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
+        let (lhs, rhs) = (lhs.into_int_value(), rhs.into_int_value());
+        let ty = lhs.get_type();
+        if ty.get_bit_width() != 1 << log_width {
+            return Ok(vec![build_masked_rot(
+                ctx.builder(),
+                lhs,
+                rhs,
+                log_width,
+                left,
+            )?
+            .into()]);
+        }
+        let intrinsic = get_intrinsic(
+            ctx.get_current_module(),
+            if left { "llvm.fshl" } else { "llvm.fshr" },
+            [ty.into()],
+        )?;
+        let builder = ctx.builder();
+        // The bit width is a power of two, so truncating the shift amount
+        // preserves its value modulo the bit width.
+        let rhs = builder.build_int_cast_sign_flag(rhs, ty, false, "")?;
+        let r = builder
+            .build_call(intrinsic, &[lhs.into(), lhs.into(), rhs.into()], "")?
+            .try_as_basic_value()
+            .left()
+            .ok_or(anyhow!("funnel shift intrinsic returned void"))?;
+        Ok(vec![r])
+    })
+}
+
+/// Builds a rotation of an int of log width `log_width` which is lowered to a
+/// wider LLVM type, for which the funnel shift intrinsics can't be used.
+fn build_masked_rot<'c>(
+    builder: &Builder<'c>,
+    v: IntValue<'c>,
+    k: IntValue<'c>,
+    log_width: u64,
+    left: bool,
+) -> Result<IntValue<'c>> {
+    let ty = v.get_type();
+    let width = 1u64 << log_width;
+    let k = builder.build_and(k, ty.const_int(width - 1, false), "")?;
+    let k_rev = builder.build_int_sub(ty.const_int(width, false), k, "")?;
+    let (k_left, k_right) = if left { (k, k_rev) } else { (k_rev, k) };
+    let shl = builder.build_left_shift(v, k_left, "")?;
+    let shr = builder.build_right_shift(v, k_right, false, "")?;
+    let r = builder.build_or(shl, shr, "")?;
+    build_int_normalize(builder, r, log_width, false)
+}
+
+/// Emit the maximum or minimum of two integers.
+fn emit_iminmax<'c, H: HugrView>(
     context: &mut EmitFuncContext<'c, '_, H>,
     args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    log_width: u64,
+    pred: inkwell::IntPredicate,
+) -> Result<()> {
+    emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
+        let (lhs, rhs) = (lhs.into_int_value(), rhs.into_int_value());
+        let builder = ctx.builder();
+        let lhs_wins = builder.build_int_compare(pred, lhs, rhs, "")?;
+        let r = builder
+            .build_select(lhs_wins, lhs, rhs, "")?
+            .into_int_value();
+        // Signed inputs have been sign extended.
+        Ok(vec![
+            build_int_normalize(builder, r, log_width, false)?.into()
+        ])
+    })
+}
+
+/// Returns the [LLVMSumType] of the single output of the op of `args`, which
+/// must be a sum, e.g. the sum with an error of a checked op.
+fn llvm_output_sum_type<'c, H: HugrView>(
+    context: &EmitFuncContext<'c, '_, H>,
+    args: &EmitOpArgs<'c, '_, ExtensionOp, H>,
+) -> Result<LLVMSumType<'c>> {
+    let out_tys = args.node().signature().output;
+    let Some(TypeEnum::Sum(sum_ty)) = out_tys.iter().exactly_one().ok().map(|t| t.as_type_enum())
+    else {
+        bail!("Expected op to output a single sum: {out_tys}")
+    };
+    context.llvm_sum_type(sum_ty.clone())
+}
+
+/// Returns the log width of an int op, which is its first type arg.
+fn log_width_arg<H: HugrView>(args: &EmitOpArgs<'_, '_, ExtensionOp, H>) -> Result<u64> {
+    let node = args.node();
+    let Some(TypeArg::BoundedNat { n }) = node.args().first() else {
+        bail!("Expected a log width type arg: {:?}", node.args())
+    };
+    Ok(*n)
+}
+
+/// Returns the number of leading inputs of `op` which are interpreted as
+/// signed.
+fn num_signed_inputs(op: IntOpDef, overflow: IntOverflow) -> usize {
+    match op {
+        IntOpDef::iadd | IntOpDef::isub | IntOpDef::imul => match overflow {
+            IntOverflow::Undefined { signed: true } | IntOverflow::Trap { signed: true } => 2,
+            _ => 0,
+        },
+        IntOpDef::ilt_s
+        | IntOpDef::igt_s
+        | IntOpDef::ile_s
+        | IntOpDef::ige_s
+        | IntOpDef::imax_s
+        | IntOpDef::imin_s => 2,
+        // The divisor of signed division is unsigned.
+        IntOpDef::iabs
+        | IntOpDef::idivmod_s
+        | IntOpDef::idivmod_checked_s
+        | IntOpDef::idiv_s
+        | IntOpDef::idiv_checked_s
+        | IntOpDef::imod_s
+        | IntOpDef::imod_checked_s => 1,
+        // `iwiden_s` and `inarrow_s` have inputs of a different log width,
+        // which they extend themselves.
+        _ => 0,
+    }
+}
+
+/// Returns the log widths of the input and output of a widening or narrowing
+/// op.
+fn resize_log_widths<H: HugrView>(args: &EmitOpArgs<'_, '_, ExtensionOp, H>) -> Result<(u64, u64)> {
+    let node = args.node();
+    let [TypeArg::BoundedNat { n: from }, TypeArg::BoundedNat { n: to }] = node.args() else {
+        bail!("Expected two log width type args: {:?}", node.args())
+    };
+    Ok((*from, *to))
+}
+
+/// Sign or zero extends the low `2^log_width` bits of `v` to the width of its
+/// LLVM type. This is a no-op unless `v` is a sub-byte int lowered according
+/// to [SubByteInts::Masked].
+///
+/// Zero extension gives the representation in which sub-byte ints are passed
+/// between nodes, so it must be applied to the results of ops which may set
+/// the high bits. Sign extension must be applied to inputs which are
+/// interpreted as signed.
+pub(crate) fn build_int_normalize<'c>(
+    builder: &Builder<'c>,
+    v: IntValue<'c>,
+    log_width: u64,
+    signed: bool,
+) -> Result<IntValue<'c>> {
+    let ty = v.get_type();
+    let width = 1u32 << log_width;
+    ensure!(
+        width <= ty.get_bit_width(),
+        "Int of log width {log_width} does not fit in {ty}"
+    );
+    let spare = ty.get_bit_width() - width;
+    if spare == 0 {
+        return Ok(v);
+    }
+    Ok(if signed {
+        let spare = ty.const_int(spare as u64, false);
+        let shifted = builder.build_left_shift(v, spare, "")?;
+        builder.build_right_shift(shifted, spare, true, "")?
+    } else {
+        builder.build_and(v, ty.const_int(u64::MAX >> (64 - width), false), "")?
+    })
+}
+
+/// Emits `iwiden_u` or `iwiden_s` as a zero or sign extension.
+fn emit_iwiden<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    signed: bool,
+) -> Result<()> {
+    let (from, to) = resize_log_widths(&args)?;
+    emit_custom_unary_op(context, args, |ctx, arg, out_tys| {
+        let [out_ty] = out_tys else {
+            bail!("Expected widening to have a single output")
+        };
+        let builder = ctx.builder();
+        let arg = build_int_normalize(builder, arg.into_int_value(), from, signed)?;
+        // The LLVM types of `from` and `to` may be the same, in which case
+        // this is a no-op.
+        let r = builder.build_int_cast_sign_flag(arg, out_ty.into_int_type(), signed, "")?;
+        Ok(vec![build_int_normalize(builder, r, to, false)?.into()])
+    })
+}
+
+/// Emits `inarrow_u` or `inarrow_s`, returning an error if the input is not
+/// representable at the narrower width.
+fn emit_inarrow<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    signed: bool,
+) -> Result<()> {
+    let (from, to) = resize_log_widths(&args)?;
+    let sum_ty = llvm_output_sum_type(context, &args)?;
+    let out_ty = context
+        .llvm_type(&int_types::INT_TYPES[to as usize])?
+        .into_int_type();
+    emit_custom_unary_op(context, args, |ctx, arg, _| {
+        let builder = ctx.builder();
+        let arg = build_int_normalize(builder, arg.into_int_value(), from, signed)?;
+        let ty = arg.get_type();
+        let width = 1 << to;
+        // `arg` is at least as wide as the output, so these constants are
+        // representable in its type.
+        let in_range = if signed {
+            let max = ty.const_int((i64::MAX >> (64 - width)) as u64, true);
+            let min = ty.const_int((i64::MIN >> (64 - width)) as u64, true);
+            let le_max = builder.build_int_compare(inkwell::IntPredicate::SLE, arg, max, "")?;
+            let ge_min = builder.build_int_compare(inkwell::IntPredicate::SGE, arg, min, "")?;
+            builder.build_and(le_max, ge_min, "in_range")?
+        } else {
+            let max = ty.const_int(u64::MAX >> (64 - width), false);
+            builder.build_int_compare(inkwell::IntPredicate::ULE, arg, max, "in_range")?
+        };
+        let r = builder.build_int_cast_sign_flag(arg, out_ty, signed, "")?;
+        let r = build_int_normalize(builder, r, to, false)?;
+
+        let err_msg = Value::extension(ConstError::new(
+            2,
+            format!("Integer value out of range of int of given width ({width})"),
+        ));
+        let err_val = emit_value(ctx, &err_msg)?;
+        let failure = sum_ty.build_tag(ctx.builder(), 0, vec![err_val])?;
+        let success = sum_ty.build_tag(ctx.builder(), 1, vec![r.into()])?;
+        Ok(vec![ctx
+            .builder()
+            .build_select(in_range, success, failure, "")?])
+    })
+}
+
+/// Which results of a division are output by an op.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DivMod {
+    Div,
+    Mod,
+    Both,
+}
+
+/// Builds the quotient and remainder of `n` by `m`, which must be nonzero.
+///
+/// In the signed case `n` is signed and `m` is unsigned, as specified by
+/// [IntOpDef::idivmod_s]. The quotient is then rounded towards negative
+/// infinity, so that the remainder is in `[0, m)`.
+fn build_divmod<'c>(
+    builder: &Builder<'c>,
+    signed: bool,
+    n: IntValue<'c>,
+    m: IntValue<'c>,
+) -> Result<(IntValue<'c>, IntValue<'c>)> {
+    if !signed {
+        return Ok((
+            builder.build_int_unsigned_div(n, m, "")?,
+            builder.build_int_unsigned_rem(n, m, "")?,
+        ));
+    }
+    let ty = n.get_type();
+    let zero = ty.const_zero();
+    let n_neg = builder.build_int_compare(inkwell::IntPredicate::SLT, n, zero, "")?;
+    // If the top bit of `m` is set then `m >= |n|`, so the quotient is 0 or
+    // -1.
+    let m_large = builder.build_int_compare(inkwell::IntPredicate::SLT, m, zero, "")?;
+    let large_q = builder.build_select(n_neg, ty.const_all_ones(), zero, "")?;
+    let n_plus_m = builder.build_int_add(n, m, "")?;
+    let large_r = builder.build_select(n_neg, n_plus_m, n, "")?;
+
+    // Otherwise `m` is positive when interpreted as signed. `sdiv` rounds
+    // towards zero, so we correct negative remainders. We divide by one in the
+    // case above to avoid the undefined behaviour of `sdiv` overflow.
+    let safe_m = builder
+        .build_select(m_large, ty.const_int(1, false), m, "")?
+        .into_int_value();
+    let q = builder.build_int_signed_div(n, safe_m, "")?;
+    let r = builder.build_int_signed_rem(n, safe_m, "")?;
+    let r_neg = builder.build_int_compare(inkwell::IntPredicate::SLT, r, zero, "")?;
+    let q_minus_one = builder.build_int_sub(q, ty.const_int(1, false), "")?;
+    let q = builder.build_select(r_neg, q_minus_one, q, "")?;
+    let r_plus_m = builder.build_int_add(r, m, "")?;
+    let r = builder.build_select(r_neg, r_plus_m, r, "")?;
+
+    Ok((
+        builder
+            .build_select(m_large, large_q, q, "")?
+            .into_int_value(),
+        builder
+            .build_select(m_large, large_r, r, "")?
+            .into_int_value(),
+    ))
+}
+
+/// Emits a branch to a new block which halts with the error `err` via
+/// [PreludeCodegen::emit_panic] when `cond` is true. The builder is left
+/// positioned in the block executed otherwise.
+fn emit_panic_if<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    pcg: &impl PreludeCodegen,
+    cond: IntValue<'c>,
+    err: &ConstError,
+) -> Result<()> {
+    let current = context
+        .builder()
+        .get_insert_block()
+        .ok_or(anyhow!("emit_panic_if: builder is not positioned"))?;
+    let next = current.get_next_basic_block();
+    let panic_bb = context.new_basic_block("panic", next);
+    let continue_bb = context.new_basic_block("", next);
+    context
+        .builder()
+        .build_conditional_branch(cond, panic_bb, continue_bb)?;
+    context.build_positioned(panic_bb, |context| {
+        let err = pcg.emit_const_error(context, err)?;
+        pcg.emit_panic(context, err)?;
+        context.builder().build_unreachable()?;
+        anyhow::Ok(())
+    })?;
+    context.builder().position_at_end(continue_bb);
+    Ok(())
+}
+
+/// Emits the division ops. When `checked` is true the results are returned in
+/// a sum with an error, otherwise a zero divisor causes a panic.
+fn emit_divmod<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    pcg: &impl PreludeCodegen,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    signed: bool,
+    outputs: DivMod,
+    checked: bool,
+) -> Result<()> {
+    let log_width = log_width_arg(&args)?;
+    let sum_ty = if checked {
+        Some(llvm_output_sum_type(context, &args)?)
+    } else {
+        None
+    };
+    // The success variant of `idivmod_checked_*` holds a tuple of both results.
+    let tuple_ty = if checked && outputs == DivMod::Both {
+        let int_ty = int_types::int_type(log_width);
+        Some(context.llvm_sum_type(SumType::new_tuple(vec![int_ty; 2]))?)
+    } else {
+        None
+    };
+    emit_custom_binary_op(context, args, |ctx, (n, m), _| {
+        let (n, m) = (n.into_int_value(), m.into_int_value());
+        let ty = m.get_type();
+        let err = ConstError::new(2, "Attempted division by zero");
+        let is_zero = ctx.builder().build_int_compare(
+            inkwell::IntPredicate::EQ,
+            m,
+            ty.const_zero(),
+            "is_zero",
+        )?;
+        let m = if checked {
+            // Division by zero is undefined behaviour in LLVM, so we divide by
+            // one instead and discard the results.
+            ctx.builder()
+                .build_select(is_zero, ty.const_int(1, false), m, "")?
+                .into_int_value()
+        } else {
+            emit_panic_if(ctx, pcg, is_zero, &err)?;
+            m
+        };
+        let (q, r) = build_divmod(ctx.builder(), signed, n, m)?;
+        let q = build_int_normalize(ctx.builder(), q, log_width, false)?;
+        let results = match outputs {
+            DivMod::Div => vec![q.into()],
+            DivMod::Mod => vec![r.into()],
+            DivMod::Both => vec![q.into(), r.into()],
+        };
+        let Some(sum_ty) = sum_ty else {
+            return Ok(results);
+        };
+        let results = match tuple_ty {
+            Some(tuple_ty) => vec![tuple_ty.build_tag(ctx.builder(), 0, results)?],
+            None => results,
+        };
+        let err_val = emit_value(ctx, &Value::extension(err))?;
+        let failure = sum_ty.build_tag(ctx.builder(), 0, vec![err_val])?;
+        let success = sum_ty.build_tag(ctx.builder(), 1, results)?;
+        Ok(vec![ctx
+            .builder()
+            .build_select(is_zero, failure, success, "")?])
+    })
+}
+
+/// Emits `ipow` by exponentiation by squaring, in a loop over the bits of the
+/// exponent. As specified, the multiplications wrap.
+fn emit_ipow<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    log_width: u64,
+) -> Result<()> {
+    emit_custom_binary_op(context, args, |ctx, (base, exp), _| {
+        let (base, exp) = (base.into_int_value(), exp.into_int_value());
+        let ty = base.get_type();
+        let (zero, one) = (ty.const_zero(), ty.const_int(1, false));
+        let entry_bb = ctx
+            .builder()
+            .get_insert_block()
+            .ok_or(anyhow!("emit_ipow: builder is not positioned"))?;
+        let next = entry_bb.get_next_basic_block();
+        let loop_bb = ctx.new_basic_block("ipow_loop", next);
+        let exit_bb = ctx.new_basic_block("", next);
+        let builder = ctx.builder();
+        let exp_is_zero = builder.build_int_compare(inkwell::IntPredicate::EQ, exp, zero, "")?;
+        builder.build_conditional_branch(exp_is_zero, exit_bb, loop_bb)?;
+
+        builder.position_at_end(loop_bb);
+        let acc = builder.build_phi(ty, "acc")?;
+        let square = builder.build_phi(ty, "square")?;
+        let bits = builder.build_phi(ty, "bits")?;
+        let [acc_in, square_in, bits_in] =
+            [acc, square, bits].map(|phi| phi.as_basic_value().into_int_value());
+        let odd = builder.build_int_truncate(bits_in, ctx.iw_context().bool_type(), "")?;
+        let product = builder.build_int_mul(acc_in, square_in, "")?;
+        let acc_out = builder
+            .build_select(odd, product, acc_in, "")?
+            .into_int_value();
+        let square_out = builder.build_int_mul(square_in, square_in, "")?;
+        let bits_out = builder.build_right_shift(bits_in, one, false, "")?;
+        acc.add_incoming(&[(&one, entry_bb), (&acc_out, loop_bb)]);
+        square.add_incoming(&[(&base, entry_bb), (&square_out, loop_bb)]);
+        bits.add_incoming(&[(&exp, entry_bb), (&bits_out, loop_bb)]);
+        let done = builder.build_int_compare(inkwell::IntPredicate::EQ, bits_out, zero, "")?;
+        builder.build_conditional_branch(done, exit_bb, loop_bb)?;
+
+        builder.position_at_end(exit_bb);
+        let r = builder.build_phi(ty, "")?;
+        r.add_incoming(&[(&one, entry_bb), (&acc_out, loop_bb)]);
+        Ok(vec![build_int_normalize(
+            builder,
+            r.as_basic_value().into_int_value(),
+            log_width,
+            false,
+        )?
+        .as_basic_value_enum()])
+    })
+}
+
+/// Emits `is_to_u` or `iu_to_s`, which panic via [PreludeCodegen::emit_panic]
+/// if the top bit of the input is set, and otherwise return it unchanged.
+fn emit_sign_conversion<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    pcg: &impl PreludeCodegen,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    log_width: u64,
+    to_unsigned: bool,
+) -> Result<()> {
+    emit_custom_unary_op(context, args, |ctx, arg, _| {
+        let arg = arg.into_int_value();
+        let signed = build_int_normalize(ctx.builder(), arg, log_width, true)?;
+        let top_bit_set = ctx.builder().build_int_compare(
+            inkwell::IntPredicate::SLT,
+            signed,
+            arg.get_type().const_zero(),
+            "",
+        )?;
+        let message = if to_unsigned {
+            "Cannot convert negative integer to unsigned"
+        } else {
+            "Unsigned integer is too large to be converted to signed"
+        };
+        emit_panic_if(ctx, pcg, top_bit_set, &ConstError::new(2, message))?;
+        Ok(vec![arg.as_basic_value_enum()])
+    })
+}
+
+/// Which arithmetic op is emitted by [emit_arith].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Arith {
+    Add,
+    Sub,
+    Mul,
+}
+
+/// Emits `iadd`, `isub` or `imul`, handling overflow according to `overflow`.
+fn emit_arith<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    pcg: &impl PreludeCodegen,
+    overflow: IntOverflow,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    log_width: u64,
+    arith: Arith,
+) -> Result<()> {
+    emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
+        let (lhs, rhs) = (lhs.into_int_value(), rhs.into_int_value());
+        let ty = lhs.get_type();
+        let builder = ctx.builder();
+        let r = match (overflow, arith) {
+            (IntOverflow::Wrap, Arith::Add) => builder.build_int_add(lhs, rhs, "")?,
+            (IntOverflow::Wrap, Arith::Sub) => builder.build_int_sub(lhs, rhs, "")?,
+            (IntOverflow::Wrap, Arith::Mul) => builder.build_int_mul(lhs, rhs, "")?,
+            (IntOverflow::Undefined { signed: true }, Arith::Add) => {
+                builder.build_int_nsw_add(lhs, rhs, "")?
+            }
+            (IntOverflow::Undefined { signed: true }, Arith::Sub) => {
+                builder.build_int_nsw_sub(lhs, rhs, "")?
+            }
+            (IntOverflow::Undefined { signed: true }, Arith::Mul) => {
+                builder.build_int_nsw_mul(lhs, rhs, "")?
+            }
+            (IntOverflow::Undefined { signed: false }, Arith::Add) => {
+                builder.build_int_nuw_add(lhs, rhs, "")?
+            }
+            (IntOverflow::Undefined { signed: false }, Arith::Sub) => {
+                builder.build_int_nuw_sub(lhs, rhs, "")?
+            }
+            (IntOverflow::Undefined { signed: false }, Arith::Mul) => {
+                builder.build_int_nuw_mul(lhs, rhs, "")?
+            }
+            (IntOverflow::Trap { signed }, arith) => {
+                let name = format!(
+                    "llvm.{}{}.with.overflow",
+                    if signed { "s" } else { "u" },
+                    match arith {
+                        Arith::Add => "add",
+                        Arith::Sub => "sub",
+                        Arith::Mul => "mul",
+                    }
+                );
+                let intrinsic = get_intrinsic(ctx.get_current_module(), &name, [ty.into()])?;
+                let builder = ctx.builder();
+                let res = builder
+                    .build_call(intrinsic, &[lhs.into(), rhs.into()], "")?
+                    .try_as_basic_value()
+                    .left()
+                    .ok_or(anyhow!("{name} returned void"))?
+                    .into_struct_value();
+                let r = builder.build_extract_value(res, 0, "")?.into_int_value();
+                let mut overflowed = builder
+                    .build_extract_value(res, 1, "overflow")?
+                    .into_int_value();
+                if ty.get_bit_width() != 1 << log_width {
+                    // A sub-byte int lowered to `i8` can't overflow the `i8`,
+                    // so we also check that the result fits in the int.
+                    let fits = build_int_normalize(builder, r, log_width, signed)?;
+                    let truncated =
+                        builder.build_int_compare(inkwell::IntPredicate::NE, r, fits, "")?;
+                    overflowed = builder.build_or(overflowed, truncated, "overflow")?;
+                }
+                let err = ConstError::new(2, "Integer overflow");
+                emit_panic_if(ctx, pcg, overflowed, &err)?;
+                r
+            }
+        };
+        Ok(vec![build_int_normalize(
+            ctx.builder(),
+            r,
+            log_width,
+            false,
+        )?
+        .as_basic_value_enum()])
+    })
+}
+
+fn emit_int_op<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    pcg: &impl PreludeCodegen,
+    overflow: IntOverflow,
+    mut args: EmitOpArgs<'c, '_, ExtensionOp, H>,
     op: IntOpDef,
 ) -> Result<()> {
+    let log_width = log_width_arg(&args)?;
+    for input in args.inputs.iter_mut().take(num_signed_inputs(op, overflow)) {
+        *input = build_int_normalize(context.builder(), input.into_int_value(), log_width, true)?
+            .as_basic_value_enum();
+    }
+    // Results which may set the high bits of a sub-byte int are normalized
+    // with `mask`.
+    let mask = |builder: &Builder<'c>, v: IntValue<'c>| {
+        Ok::<_, anyhow::Error>(vec![
+            build_int_normalize(builder, v, log_width, false)?.as_basic_value_enum()
+        ])
+    };
     match op {
-        IntOpDef::iadd => emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
-            Ok(vec![ctx
-                .builder()
-                .build_int_add(lhs.into_int_value(), rhs.into_int_value(), "")?
-                .as_basic_value_enum()])
+        IntOpDef::iadd => emit_arith(context, pcg, overflow, args, log_width, Arith::Add),
+        IntOpDef::imul => emit_arith(context, pcg, overflow, args, log_width, Arith::Mul),
+        IntOpDef::isub => emit_arith(context, pcg, overflow, args, log_width, Arith::Sub),
+        IntOpDef::iwiden_u => emit_iwiden(context, args, false),
+        IntOpDef::iwiden_s => emit_iwiden(context, args, true),
+        IntOpDef::inarrow_u => emit_inarrow(context, args, false),
+        IntOpDef::inarrow_s => emit_inarrow(context, args, true),
+        IntOpDef::idivmod_u => emit_divmod(context, pcg, args, false, DivMod::Both, false),
+        IntOpDef::idivmod_s => emit_divmod(context, pcg, args, true, DivMod::Both, false),
+        IntOpDef::idiv_u => emit_divmod(context, pcg, args, false, DivMod::Div, false),
+        IntOpDef::idiv_s => emit_divmod(context, pcg, args, true, DivMod::Div, false),
+        IntOpDef::imod_u => emit_divmod(context, pcg, args, false, DivMod::Mod, false),
+        IntOpDef::imod_s => emit_divmod(context, pcg, args, true, DivMod::Mod, false),
+        IntOpDef::idivmod_checked_u => emit_divmod(context, pcg, args, false, DivMod::Both, true),
+        IntOpDef::idivmod_checked_s => emit_divmod(context, pcg, args, true, DivMod::Both, true),
+        IntOpDef::idiv_checked_u => emit_divmod(context, pcg, args, false, DivMod::Div, true),
+        IntOpDef::idiv_checked_s => emit_divmod(context, pcg, args, true, DivMod::Div, true),
+        IntOpDef::imod_checked_u => emit_divmod(context, pcg, args, false, DivMod::Mod, true),
+        IntOpDef::imod_checked_s => emit_divmod(context, pcg, args, true, DivMod::Mod, true),
+        IntOpDef::ineg => emit_custom_unary_op(context, args, |ctx, arg, _| {
+            let r = ctx.builder().build_int_neg(arg.into_int_value(), "")?;
+            mask(ctx.builder(), r)
         }),
-        IntOpDef::imul => emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
+        IntOpDef::iand => emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
             Ok(vec![ctx
                 .builder()
-                .build_int_mul(lhs.into_int_value(), rhs.into_int_value(), "")?
+                .build_and(lhs.into_int_value(), rhs.into_int_value(), "")?
                 .as_basic_value_enum()])
         }),
-        IntOpDef::isub => emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
+        IntOpDef::ior => emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
             Ok(vec![ctx
                 .builder()
-                .build_int_sub(lhs.into_int_value(), rhs.into_int_value(), "")?
+                .build_or(lhs.into_int_value(), rhs.into_int_value(), "")?
                 .as_basic_value_enum()])
         }),
-        IntOpDef::idiv_s => emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
+        IntOpDef::ixor => emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
             Ok(vec![ctx
                 .builder()
-                .build_int_signed_div(lhs.into_int_value(), rhs.into_int_value(), "")?
+                .build_xor(lhs.into_int_value(), rhs.into_int_value(), "")?
                 .as_basic_value_enum()])
         }),
-        IntOpDef::idiv_u => emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
-            Ok(vec![ctx
-                .builder()
-                .build_int_unsigned_div(lhs.into_int_value(), rhs.into_int_value(), "")?
-                .as_basic_value_enum()])
+        IntOpDef::inot => emit_custom_unary_op(context, args, |ctx, arg, _| {
+            let r = ctx.builder().build_not(arg.into_int_value(), "")?;
+            mask(ctx.builder(), r)
         }),
-        IntOpDef::imod_s => emit_custom_binary_op(context, args, |ctx, (lhs, rhs), _| {
-            Ok(vec![ctx
-                .builder()
-                .build_int_signed_rem(lhs.into_int_value(), rhs.into_int_value(), "")?
-                .as_basic_value_enum()])
-        }),
-        IntOpDef::ineg => emit_custom_unary_op(context, args, |ctx, arg, _| {
-            Ok(vec![ctx
-                .builder()
-                .build_int_neg(arg.into_int_value(), "")?
-                .as_basic_value_enum()])
+        IntOpDef::iabs => emit_custom_unary_op(context, args, |ctx, arg, _| {
+            // The absolute value of the minimum signed integer is its own
+            // unsigned interpretation, so no special case is required.
+            let arg = arg.into_int_value();
+            let builder = ctx.builder();
+            let is_negative = builder.build_int_compare(
+                inkwell::IntPredicate::SLT,
+                arg,
+                arg.get_type().const_zero(),
+                "",
+            )?;
+            let neg = builder.build_int_neg(arg, "")?;
+            let r = builder.build_select(is_negative, neg, arg, "")?;
+            mask(builder, r.into_int_value())
         }),
+        IntOpDef::ishl => emit_ishift(context, args, log_width, true),
+        IntOpDef::ishr => emit_ishift(context, args, log_width, false),
+        IntOpDef::irotl => emit_irot(context, args, log_width, true),
+        IntOpDef::irotr => emit_irot(context, args, log_width, false),
+        IntOpDef::imax_s => emit_iminmax(context, args, log_width, inkwell::IntPredicate::SGT),
+        IntOpDef::imax_u => emit_iminmax(context, args, log_width, inkwell::IntPredicate::UGT),
+        IntOpDef::imin_s => emit_iminmax(context, args, log_width, inkwell::IntPredicate::SLT),
+        IntOpDef::imin_u => emit_iminmax(context, args, log_width, inkwell::IntPredicate::ULT),
         IntOpDef::ieq => emit_icmp(context, args, inkwell::IntPredicate::EQ),
+        IntOpDef::ine => emit_icmp(context, args, inkwell::IntPredicate::NE),
         IntOpDef::ilt_s => emit_icmp(context, args, inkwell::IntPredicate::SLT),
         IntOpDef::igt_s => emit_icmp(context, args, inkwell::IntPredicate::SGT),
         IntOpDef::ile_s => emit_icmp(context, args, inkwell::IntPredicate::SLE),
@@ -104,18 +746,27 @@ fn emit_int_op<'c, H: HugrView>(
         IntOpDef::igt_u => emit_icmp(context, args, inkwell::IntPredicate::UGT),
         IntOpDef::ile_u => emit_icmp(context, args, inkwell::IntPredicate::ULE),
         IntOpDef::ige_u => emit_icmp(context, args, inkwell::IntPredicate::UGE),
-        _ => Err(anyhow!("IntOpEmitter: unimplemented op: {}", op.name())),
+        IntOpDef::ipow => emit_ipow(context, args, log_width),
+        IntOpDef::is_to_u => emit_sign_conversion(context, pcg, args, log_width, true),
+        IntOpDef::iu_to_s => emit_sign_conversion(context, pcg, args, log_width, false),
+        _ => Err(EmitErrorKind::UnsupportedOp(op.name().to_string()))?,
     }
 }
 
+/// Lowers `int<n>` types. Note that `hugr` bounds `n` by
+/// [int_types::LOG_WIDTH_BOUND], so ints wider than 64 bits can not occur.
 fn llvm_type<'c>(
     context: TypingSession<'c, '_>,
     hugr_type: &CustomType,
+    sub_byte_ints: SubByteInts,
 ) -> Result<BasicTypeEnum<'c>> {
     if let [TypeArg::BoundedNat { n }] = hugr_type.args() {
         let m = *n as usize;
         if m < int_types::INT_TYPES.len() && int_types::INT_TYPES[m] == hugr_type.clone().into() {
             return Ok(match m {
+                0..=2 if sub_byte_ints == SubByteInts::Exact => {
+                    context.iw_context().custom_width_int_type(1 << m)
+                }
                 0..=3 => context.iw_context().i8_type(),
                 4 => context.iw_context().i16_type(),
                 5 => context.iw_context().i32_type(),
@@ -145,14 +796,102 @@ fn emit_const_int<'c, H: HugrView>(
     Ok(ty.const_int(k.value_u(), false).as_basic_value_enum())
 }
 
+/// How ints of log width less than 3, i.e. narrower than a byte, are lowered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SubByteInts {
+    /// Sub-byte ints are lowered to `i8`, with the value in the low bits and
+    /// the high bits zero. The results of ops are masked to preserve this, so
+    /// that they wrap at the width of the int.
+    #[default]
+    Masked,
+    /// Sub-byte ints are lowered to `i1`, `i2` and `i4`.
+    Exact,
+}
+
+/// How overflow of `iadd`, `isub` and `imul` is handled.
+///
+/// HUGR specifies that these ops wrap, and that they are the same op for
+/// signed and unsigned ints. Modes other than [IntOverflow::Wrap] must pick an
+/// interpretation, and change the behaviour of programs which overflow in it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum IntOverflow {
+    /// Results wrap modulo `2^N`.
+    #[default]
+    Wrap,
+    /// Overflow is undefined behaviour, which allows LLVM to optimise more
+    /// aggressively. Instructions are flagged `nsw` when `signed`, and `nuw`
+    /// otherwise.
+    Undefined { signed: bool },
+    /// Overflow panics via [PreludeCodegen::emit_panic]. It is detected with
+    /// the `llvm.sadd.with.overflow` family of intrinsics when `signed`, and
+    /// with `llvm.uadd.with.overflow` etc. otherwise.
+    Trap { signed: bool },
+}
+
+/// A codegen extension for the `arithmetic.int` and `arithmetic.int.types`
+/// extensions.
+///
+/// An `IntCodegenExtension` carries a `PCG`, which should impl
+/// [PreludeCodegen]. This is used to [PreludeCodegen::emit_panic] when lowering
+/// panicking ops.
+#[derive(Clone, Debug, Default)]
+pub struct IntCodegenExtension<PCG> {
+    prelude_codegen: PCG,
+    sub_byte_ints: SubByteInts,
+    overflow: IntOverflow,
+}
+
+impl<PCG: PreludeCodegen> IntCodegenExtension<PCG> {
+    /// Returns a new `IntCodegenExtension` with the given [PreludeCodegen].
+    pub fn new(prelude_codegen: PCG) -> Self {
+        Self {
+            prelude_codegen,
+            sub_byte_ints: SubByteInts::default(),
+            overflow: IntOverflow::default(),
+        }
+    }
+
+    /// Returns a new `IntCodegenExtension` lowering sub-byte ints according to
+    /// `sub_byte_ints`.
+    pub fn with_sub_byte_ints(mut self, sub_byte_ints: SubByteInts) -> Self {
+        self.sub_byte_ints = sub_byte_ints;
+        self
+    }
+
+    /// Returns a new `IntCodegenExtension` handling overflow of `iadd`, `isub`
+    /// and `imul` according to `overflow`.
+    pub fn with_overflow(mut self, overflow: IntOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+}
+
+impl<PCG: PreludeCodegen> CodegenExtension for IntCodegenExtension<PCG> {
+    fn add_extension<'a, H: HugrView + 'a>(
+        self,
+        builder: CodegenExtsBuilder<'a, H>,
+    ) -> CodegenExtsBuilder<'a, H>
+    where
+        Self: 'a,
+    {
+        let sub_byte_ints = self.sub_byte_ints;
+        builder
+            .custom_const(emit_const_int)
+            .custom_type((int_types::EXTENSION_ID, "int".into()), move |ts, t| {
+                llvm_type(ts, t, sub_byte_ints)
+            })
+            .simple_extension_op::<IntOpDef>(move |context, args, op| {
+                emit_int_op(context, &self.prelude_codegen, self.overflow, args, op)
+            })
+    }
+}
+
 /// Populates a [CodegenExtsBuilder] with all extensions needed to lower int
 /// ops, types, and constants.
 pub fn add_int_extensions<'a, H: HugrView + 'a>(
     cem: CodegenExtsBuilder<'a, H>,
 ) -> CodegenExtsBuilder<'a, H> {
-    cem.custom_const(emit_const_int)
-        .custom_type((int_types::EXTENSION_ID, "int".into()), llvm_type)
-        .simple_extension_op::<IntOpDef>(emit_int_op)
+    cem.add_extension(IntCodegenExtension::new(DefaultPreludeCodegen))
 }
 
 impl<'a, H: HugrView + 'a> CodegenExtsBuilder<'a, H> {
@@ -166,19 +905,29 @@ impl<'a, H: HugrView + 'a> CodegenExtsBuilder<'a, H> {
 #[cfg(test)]
 mod test {
     use hugr::{
-        builder::{Dataflow, DataflowSubContainer},
-        extension::prelude::BOOL_T,
-        std_extensions::arithmetic::{int_ops, int_types::INT_TYPES},
-        types::TypeRow,
+        builder::{Dataflow, DataflowSubContainer, SubContainer},
+        extension::prelude::{ConstUsize, UnpackTuple, BOOL_T, ERROR_TYPE, USIZE_T},
+        ops::{DataflowOpTrait as _, ExtensionOp},
+        std_extensions::arithmetic::{
+            int_ops,
+            int_types::{ConstInt, INT_TYPES},
+        },
+        type_row,
+        types::{TypeEnum, TypeRow},
         Hugr,
     };
+    use itertools::Itertools as _;
     use rstest::rstest;
 
     use crate::{
         check_emission,
-        emit::test::SimpleHugrConfig,
-        extension::int::add_int_extensions,
-        test::{llvm_ctx, TestContext},
+        emit::test::{Emission, SimpleHugrConfig},
+        extension::{
+            int::{add_int_extensions, IntCodegenExtension, IntOverflow, SubByteInts},
+            DefaultPreludeCodegen,
+        },
+        test::{exec_ctx, llvm_ctx, TestContext},
+        utils::fat::FatExt as _,
     };
 
     fn test_binary_int_op(name: impl AsRef<str>, log_width: u8) -> Hugr {
@@ -249,6 +998,7 @@ mod test {
     #[rstest]
     #[case::iadd("iadd", 3)]
     #[case::isub("isub", 6)]
+    #[case::ipow("ipow", 6)]
     fn test_binop_emission(mut llvm_ctx: TestContext, #[case] op: String, #[case] width: u8) {
         llvm_ctx.add_extensions(add_int_extensions);
         let hugr = test_binary_int_op(op.clone(), width);
@@ -263,4 +1013,567 @@ mod test {
         let hugr = test_binary_icmp_op(op.clone(), width);
         check_emission!(op.clone(), hugr, llvm_ctx);
     }
+
+    /// Builds a HUGR applying the op `name` at `log_width` to constant
+    /// `inputs`, which are truncated to the width. If the op returns a bool
+    /// the HUGR returns it as a `usize` 0 or 1.
+    fn exec_int_op_hugr(name: &str, log_width: u8, inputs: &[u64]) -> Hugr {
+        let ext_op = int_ops::EXTENSION
+            .instantiate_extension_op(
+                name,
+                [(log_width as u64).into()],
+                &int_ops::INT_OPS_REGISTRY,
+            )
+            .unwrap();
+        let returns_bool = ext_op.signature().output() == &type_row![BOOL_T];
+        let out_ty = if returns_bool {
+            USIZE_T
+        } else {
+            INT_TYPES[log_width as usize].clone()
+        };
+        SimpleHugrConfig::new()
+            .with_outs(vec![out_ty])
+            .with_extensions(int_ops::INT_OPS_REGISTRY.clone())
+            .finish(|mut builder| {
+                let ins = inputs
+                    .iter()
+                    .map(|&v| {
+                        builder.add_load_value(
+                            ConstInt::new_u(log_width, v & mask(log_width)).unwrap(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let [r] = builder.add_dataflow_op(ext_op, ins).unwrap().outputs_arr();
+                if !returns_bool {
+                    return builder.finish_with_outputs([r]).unwrap();
+                }
+                let mut cond = builder
+                    .conditional_builder(([type_row![], type_row![]], r), [], type_row![USIZE_T])
+                    .unwrap();
+                for i in 0..2 {
+                    let mut case = cond.case_builder(i).unwrap();
+                    let result = case.add_load_value(ConstUsize::new(i as u64));
+                    case.finish_with_outputs([result]).unwrap();
+                }
+                let res = cond.finish_sub_container().unwrap();
+                builder.finish_with_outputs(res.outputs()).unwrap()
+            })
+    }
+
+    fn mask(log_width: u8) -> u64 {
+        u64::MAX >> (64 - (1 << log_width))
+    }
+
+    fn exec_int_op(exec_ctx: &mut TestContext, name: &str, log_width: u8, inputs: &[u64]) -> u64 {
+        let hugr = exec_int_op_hugr(name, log_width, inputs);
+        exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions().add_int_extensions());
+        exec_ctx.exec_hugr_u64(hugr, "main") & mask(log_width)
+    }
+
+    #[rstest]
+    #[case::iand("iand", 6, &[0b1100, 0b1010], 0b1000)]
+    #[case::ior("ior", 6, &[0b1100, 0b1010], 0b1110)]
+    #[case::ixor("ixor", 6, &[0b1100, 0b1010], 0b0110)]
+    #[case::inot("inot", 6, &[0b1100], !0b1100)]
+    #[case::inot_3("inot", 3, &[0b1100], 0xf3)]
+    #[case::ine_eq("ine", 6, &[3, 3], 0)]
+    #[case::ine_ne("ine", 6, &[3, 4], 1)]
+    #[case::imax_u("imax_u", 6, &[u64::MAX, 1], u64::MAX)]
+    #[case::imax_s("imax_s", 6, &[-1i64 as u64, 1], 1)]
+    #[case::imin_u("imin_u", 6, &[u64::MAX, 1], 1)]
+    #[case::imin_s("imin_s", 6, &[-1i64 as u64, 1], -1i64 as u64)]
+    #[case::imax_s_4("imax_s", 4, &[-3i64 as u64, -7i64 as u64], 0xfffd)]
+    #[case::imin_u_4("imin_u", 4, &[-3i64 as u64, 7], 7)]
+    #[case::iabs_pos("iabs", 6, &[5], 5)]
+    #[case::iabs_neg("iabs", 6, &[-5i64 as u64], 5)]
+    #[case::iabs_min("iabs", 6, &[i64::MIN as u64], i64::MIN as u64)]
+    #[case::iabs_5("iabs", 5, &[-5i64 as u64], 5)]
+    fn exec_bitwise_minmax(
+        mut exec_ctx: TestContext,
+        #[case] op: &str,
+        #[case] log_width: u8,
+        #[case] inputs: &[u64],
+        #[case] expected: u64,
+    ) {
+        assert_eq!(
+            expected & mask(log_width),
+            exec_int_op(&mut exec_ctx, op, log_width, inputs)
+        );
+    }
+
+    #[rstest]
+    #[case::ishl("ishl", 6, 1, 3, 8)]
+    #[case::ishl_out("ishl", 6, 1, 63, 1 << 63)]
+    #[case::ishl_width("ishl", 6, 1, 64, 0)]
+    #[case::ishl_large("ishl", 6, 1, u64::MAX, 0)]
+    #[case::ishl_3("ishl", 3, 0x81, 1, 0x02)]
+    #[case::ishl_3_width("ishl", 3, 0x81, 8, 0)]
+    #[case::ishr("ishr", 6, 8, 3, 1)]
+    #[case::ishr_logical("ishr", 6, 1 << 63, 63, 1)]
+    #[case::ishr_width("ishr", 6, u64::MAX, 64, 0)]
+    #[case::ishr_4_width("ishr", 4, 0xffff, 16, 0)]
+    #[case::irotl("irotl", 6, 1 << 63 | 1, 1, 0b11)]
+    #[case::irotl_width("irotl", 6, 0x1234, 64, 0x1234)]
+    #[case::irotl_wrap("irotl", 6, 0x1234, 68, 0x12340)]
+    #[case::irotl_3("irotl", 3, 0x81, 1, 0x03)]
+    #[case::irotr("irotr", 6, 0b11, 1, 1 << 63 | 1)]
+    #[case::irotr_wrap("irotr", 6, 0x1234, 68, 0x4000_0000_0000_0123)]
+    #[case::irotr_5("irotr", 5, 0x8000_0001, 1, 0xc000_0000)]
+    fn exec_shift(
+        mut exec_ctx: TestContext,
+        #[case] op: &str,
+        #[case] log_width: u8,
+        #[case] lhs: u64,
+        #[case] rhs: u64,
+        #[case] expected: u64,
+    ) {
+        assert_eq!(
+            expected,
+            exec_int_op(&mut exec_ctx, op, log_width, &[lhs, rhs])
+        );
+    }
+
+    /// Builds a HUGR applying the division op `name` at `log_width` to `n` and
+    /// `m`, and returning its result at `output_index`. If the op returns a
+    /// sum with an error the HUGR returns 999 in the error case.
+    fn divmod_hugr(name: &str, log_width: u8, n: u64, m: u64, output_index: usize) -> Hugr {
+        let ext_op = int_ops::EXTENSION
+            .instantiate_extension_op(
+                name,
+                [(log_width as u64).into()],
+                &int_ops::INT_OPS_REGISTRY,
+            )
+            .unwrap();
+        let int_ty = INT_TYPES[log_width as usize].clone();
+        let out_row = ext_op.signature().output;
+        SimpleHugrConfig::new()
+            .with_outs(vec![int_ty.clone()])
+            .with_extensions(int_ops::INT_OPS_REGISTRY.clone())
+            .finish(|mut builder| {
+                let [n, m] = [n, m].map(|v| {
+                    builder.add_load_value(ConstInt::new_u(log_width, v & mask(log_width)).unwrap())
+                });
+                let outs = builder
+                    .add_dataflow_op(ext_op, [n, m])
+                    .unwrap()
+                    .outputs()
+                    .collect_vec();
+                let TypeEnum::Sum(sum_ty) = out_row[0].as_type_enum() else {
+                    return builder.finish_with_outputs([outs[output_index]]).unwrap();
+                };
+                let variants = (0..2)
+                    .map(|i| TypeRow::try_from(sum_ty.get_variant(i).unwrap().clone()).unwrap())
+                    .collect_vec();
+                let mut cond = builder
+                    .conditional_builder((variants, outs[0]), [], vec![int_ty.clone()].into())
+                    .unwrap();
+                let mut case_err = cond.case_builder(0).unwrap();
+                let magic = case_err.add_load_value(ConstInt::new_u(log_width, 999).unwrap());
+                case_err.finish_with_outputs([magic]).unwrap();
+                let mut case_ok = cond.case_builder(1).unwrap();
+                let mut results = case_ok.input_wires().collect_vec();
+                if name.starts_with("idivmod") {
+                    // Both results are in a tuple.
+                    let unpack = UnpackTuple::new(vec![int_ty.clone(); 2].into());
+                    results = case_ok
+                        .add_dataflow_op(unpack, results)
+                        .unwrap()
+                        .outputs()
+                        .collect_vec();
+                }
+                case_ok
+                    .finish_with_outputs([results[output_index]])
+                    .unwrap();
+                let res = cond.finish_sub_container().unwrap();
+                builder.finish_with_outputs(res.outputs()).unwrap()
+            })
+    }
+
+    #[rstest]
+    #[case::idivmod_u_q("idivmod_u", 6, 17, 5, 0, 3)]
+    #[case::idivmod_u_r("idivmod_u", 6, 17, 5, 1, 2)]
+    #[case::idiv_u("idiv_u", 6, u64::MAX, 2, 0, u64::MAX >> 1)]
+    #[case::imod_u("imod_u", 6, u64::MAX, 10, 0, 5)]
+    #[case::idivmod_s_q("idivmod_s", 6, -17i64 as u64, 5, 0, -4i64 as u64)]
+    #[case::idivmod_s_r("idivmod_s", 6, -17i64 as u64, 5, 1, 3)]
+    #[case::idivmod_s_pos_q("idivmod_s", 6, 17, 5, 0, 3)]
+    #[case::idivmod_s_pos_r("idivmod_s", 6, 17, 5, 1, 2)]
+    #[case::idiv_s_exact("idiv_s", 6, -16i64 as u64, 4, 0, -4i64 as u64)]
+    #[case::idiv_s_floor("idiv_s", 6, -1i64 as u64, 2, 0, -1i64 as u64)]
+    #[case::imod_s("imod_s", 6, -1i64 as u64, 2, 0, 1)]
+    #[case::idivmod_s_min_q("idivmod_s", 6, i64::MIN as u64, 1, 0, i64::MIN as u64)]
+    #[case::idivmod_s_min_r("idivmod_s", 6, i64::MIN as u64, 1, 1, 0)]
+    #[case::idivmod_s_large_q("idivmod_s", 6, -17i64 as u64, u64::MAX, 0, -1i64 as u64)]
+    #[case::idivmod_s_large_r("idivmod_s", 6, -17i64 as u64, u64::MAX, 1, u64::MAX - 17)]
+    #[case::idivmod_s_large_pos_q("idivmod_s", 6, 17, u64::MAX, 0, 0)]
+    #[case::idivmod_s_large_pos_r("idivmod_s", 6, 17, u64::MAX, 1, 17)]
+    #[case::idivmod_s_half_q("idivmod_s", 6, i64::MIN as u64, 1 << 63, 0, -1i64 as u64)]
+    #[case::idivmod_s_half_r("idivmod_s", 6, i64::MIN as u64, 1 << 63, 1, 0)]
+    #[case::idivmod_s_3_q("idivmod_s", 3, -17i64 as u64, 5, 0, -4i64 as u64)]
+    #[case::idivmod_s_3_r("idivmod_s", 3, -17i64 as u64, 5, 1, 3)]
+    #[case::idivmod_s_3_large("idivmod_s", 3, -17i64 as u64, 200, 1, 183)]
+    #[case::idivmod_checked_u_q("idivmod_checked_u", 6, 17, 5, 0, 3)]
+    #[case::idivmod_checked_u_r("idivmod_checked_u", 6, 17, 5, 1, 2)]
+    #[case::idivmod_checked_u_zero("idivmod_checked_u", 6, 17, 0, 0, 999)]
+    #[case::idivmod_checked_s_q("idivmod_checked_s", 6, -17i64 as u64, 5, 0, -4i64 as u64)]
+    #[case::idivmod_checked_s_r("idivmod_checked_s", 6, -17i64 as u64, 5, 1, 3)]
+    #[case::idivmod_checked_s_zero("idivmod_checked_s", 6, -17i64 as u64, 0, 1, 999)]
+    #[case::idiv_checked_u("idiv_checked_u", 6, 17, 5, 0, 3)]
+    #[case::idiv_checked_u_zero("idiv_checked_u", 6, 17, 0, 0, 999)]
+    #[case::idiv_checked_s("idiv_checked_s", 6, -17i64 as u64, 5, 0, -4i64 as u64)]
+    #[case::idiv_checked_s_zero("idiv_checked_s", 6, 0, 0, 0, 999)]
+    #[case::imod_checked_u("imod_checked_u", 6, 17, 5, 0, 2)]
+    #[case::imod_checked_u_zero("imod_checked_u", 6, 17, 0, 0, 999)]
+    #[case::imod_checked_s("imod_checked_s", 6, -17i64 as u64, 5, 0, 3)]
+    #[case::imod_checked_s_zero("imod_checked_s", 6, -17i64 as u64, 0, 0, 999)]
+    fn exec_divmod(
+        mut exec_ctx: TestContext,
+        #[case] op: &str,
+        #[case] log_width: u8,
+        #[case] n: u64,
+        #[case] m: u64,
+        #[case] output_index: usize,
+        #[case] expected: u64,
+    ) {
+        let hugr = divmod_hugr(op, log_width, n, m, output_index);
+        exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions().add_int_extensions());
+        assert_eq!(
+            expected & mask(log_width),
+            exec_ctx.exec_hugr_u64(hugr, "main") & mask(log_width)
+        );
+    }
+
+    /// Checks that executing the HUGR built by `hugr` panics. The panic aborts
+    /// the process, so the HUGR is executed in a child process which runs only
+    /// the test `test` with `case`, and we check how that exits.
+    #[cfg(unix)]
+    fn assert_exec_panics(
+        mut exec_ctx: TestContext,
+        test: &str,
+        case: &str,
+        hugr: impl FnOnce() -> Hugr,
+    ) {
+        use std::os::unix::process::ExitStatusExt as _;
+        const CHILD_VAR: &str = "HUGR_LLVM_TEST_PANIC_CASE";
+        match std::env::var(CHILD_VAR) {
+            Ok(child_case) if child_case == case => {
+                exec_ctx.add_extensions(|cge| {
+                    cge.add_default_prelude_extensions().add_int_extensions()
+                });
+                exec_ctx.exec_hugr_u64(hugr(), "main");
+                unreachable!("{case} did not panic");
+            }
+            Ok(_) => return,
+            Err(_) => (),
+        }
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([test, "--test-threads=1"])
+            .env(CHILD_VAR, case)
+            .output()
+            .unwrap();
+        // The panic prints with libc's buffered `printf`, and `abort` does
+        // not flush, so only the signal is observable. A hardware exception
+        // from a division would raise SIGFPE instead.
+        assert_eq!(
+            Some(6),
+            output.status.signal(),
+            "{}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    #[case::idivmod_u("idivmod_u")]
+    #[case::idivmod_s("idivmod_s")]
+    #[case::idiv_u("idiv_u")]
+    #[case::imod_s("imod_s")]
+    fn exec_divmod_panics_on_zero(exec_ctx: TestContext, #[case] op: &str) {
+        assert_exec_panics(exec_ctx, "exec_divmod_panics_on_zero", op, || {
+            divmod_hugr(op, 6, 17, 0, 0)
+        });
+    }
+
+    #[rstest]
+    #[case::ipow("ipow", 6, &[3, 4], 81)]
+    #[case::ipow_zero_exp("ipow", 6, &[3, 0], 1)]
+    #[case::ipow_zero_zero("ipow", 6, &[0, 0], 1)]
+    #[case::ipow_wrap("ipow", 6, &[3, 41], 3u64.wrapping_pow(41))]
+    #[case::ipow_overflow("ipow", 6, &[2, 64], 0)]
+    #[case::ipow_large_exp("ipow", 6, &[1, u64::MAX], 1)]
+    #[case::ipow_3("ipow", 3, &[3, 5], 243)]
+    #[case::ipow_3_wrap("ipow", 3, &[3, 6], 729 & 0xff)]
+    #[case::ipow_2("ipow", 2, &[3, 3], 27 & 0xf)]
+    #[case::is_to_u("is_to_u", 6, &[42], 42)]
+    #[case::iu_to_s("iu_to_s", 6, &[i64::MAX as u64], i64::MAX as u64)]
+    #[case::iu_to_s_3("iu_to_s", 3, &[0x7f], 0x7f)]
+    fn exec_pow_and_sign_conversions(
+        mut exec_ctx: TestContext,
+        #[case] op: &str,
+        #[case] log_width: u8,
+        #[case] inputs: &[u64],
+        #[case] expected: u64,
+    ) {
+        assert_eq!(
+            expected & mask(log_width),
+            exec_int_op(&mut exec_ctx, op, log_width, inputs)
+        );
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    #[case::is_to_u("is_to_u", 6, -42i64 as u64)]
+    #[case::iu_to_s("iu_to_s", 6, 1 << 63)]
+    #[case::is_to_u_2("is_to_u", 2, 0x8)]
+    #[case::iu_to_s_3("iu_to_s", 3, 0x80)]
+    fn exec_sign_conversion_panics(
+        exec_ctx: TestContext,
+        #[case] op: &str,
+        #[case] log_width: u8,
+        #[case] input: u64,
+    ) {
+        let case = format!("{op}_{log_width}");
+        assert_exec_panics(exec_ctx, "exec_sign_conversion_panics", &case, || {
+            exec_int_op_hugr(op, log_width, &[input])
+        });
+    }
+
+    fn resize_op(name: &str, from: u8, to: u8) -> ExtensionOp {
+        int_ops::EXTENSION
+            .instantiate_extension_op(
+                name,
+                [(from as u64).into(), (to as u64).into()],
+                &int_ops::INT_OPS_REGISTRY,
+            )
+            .unwrap()
+    }
+
+    /// Builds a HUGR which applies `iwiden_u` or `iwiden_s` to `value`.
+    fn iwiden_hugr(signed: bool, from: u8, to: u8, value: u64) -> Hugr {
+        let name = if signed { "iwiden_s" } else { "iwiden_u" };
+        SimpleHugrConfig::new()
+            .with_outs(vec![INT_TYPES[to as usize].clone()])
+            .with_extensions(int_ops::INT_OPS_REGISTRY.clone())
+            .finish(|mut builder| {
+                let v = builder.add_load_value(ConstInt::new_u(from, value).unwrap());
+                let outputs = builder
+                    .add_dataflow_op(resize_op(name, from, to), [v])
+                    .unwrap()
+                    .outputs();
+                builder.finish_with_outputs(outputs).unwrap()
+            })
+    }
+
+    /// Builds a HUGR which applies `inarrow_u` or `inarrow_s` to `value`, and
+    /// returns the result widened to 64 bits, or 999 on error.
+    fn inarrow_hugr(signed: bool, from: u8, to: u8, value: u64) -> Hugr {
+        let name = if signed { "inarrow_s" } else { "inarrow_u" };
+        SimpleHugrConfig::new()
+            .with_outs(vec![INT_TYPES[6].clone()])
+            .with_extensions(int_ops::INT_OPS_REGISTRY.clone())
+            .finish(|mut builder| {
+                let v = builder.add_load_value(ConstInt::new_u(from, value).unwrap());
+                let [r] = builder
+                    .add_dataflow_op(resize_op(name, from, to), [v])
+                    .unwrap()
+                    .outputs_arr();
+                let variants = [
+                    type_row![ERROR_TYPE],
+                    vec![INT_TYPES[to as usize].clone()].into(),
+                ];
+                let mut cond = builder
+                    .conditional_builder((variants, r), [], vec![INT_TYPES[6].clone()].into())
+                    .unwrap();
+                let mut case_err = cond.case_builder(0).unwrap();
+                let magic = case_err.add_load_value(ConstInt::new_u(6, 999).unwrap());
+                case_err.finish_with_outputs([magic]).unwrap();
+                let mut case_ok = cond.case_builder(1).unwrap();
+                let [r] = case_ok.input_wires_arr();
+                let outputs = case_ok
+                    .add_dataflow_op(resize_op("iwiden_u", to, 6), [r])
+                    .unwrap()
+                    .outputs();
+                case_ok.finish_with_outputs(outputs).unwrap();
+                let res = cond.finish_sub_container().unwrap();
+                builder.finish_with_outputs(res.outputs()).unwrap()
+            })
+    }
+
+    #[rstest]
+    fn exec_iwiden(
+        mut exec_ctx: TestContext,
+        #[values(0, 1, 2, 3, 4, 5, 6)] from: u8,
+        #[values(0, 1, 2, 3, 4, 5, 6)] to: u8,
+    ) {
+        if from > to {
+            return;
+        }
+        exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions().add_int_extensions());
+        let all_ones = mask(from);
+        let max_s = mask(from) >> 1;
+        for (signed, value, expected) in [
+            (false, all_ones, all_ones),
+            (false, max_s, max_s),
+            (false, 0, 0),
+            (true, all_ones, mask(to)),
+            (true, max_s, max_s),
+            (true, 0, 0),
+        ] {
+            let hugr = iwiden_hugr(signed, from, to, value);
+            assert_eq!(
+                expected,
+                exec_ctx.exec_hugr_u64(hugr, "main") & mask(to),
+                "signed: {signed}, value: {value}"
+            );
+        }
+    }
+
+    #[rstest]
+    fn exec_inarrow(
+        mut exec_ctx: TestContext,
+        #[values(0, 1, 2, 3, 4, 5, 6)] from: u8,
+        #[values(0, 1, 2, 3, 4, 5, 6)] to: u8,
+    ) {
+        if from < to {
+            return;
+        }
+        exec_ctx.add_extensions(|cge| cge.add_default_prelude_extensions().add_int_extensions());
+        let narrowing = from > to;
+        let max_u = mask(to);
+        let max_s = mask(to) >> 1;
+        // The bit pattern of the minimum signed value, at widths `from` and
+        // `to`.
+        let min_s_from = (max_s + 1).wrapping_neg() & mask(from);
+        let min_s_to = (max_s + 1) & mask(to);
+        let mut cases = vec![
+            (false, 0, Some(0)),
+            (false, max_u, Some(max_u)),
+            (true, 0, Some(0)),
+            (true, max_s, Some(max_s)),
+            (true, min_s_from, Some(min_s_to)),
+        ];
+        if narrowing {
+            cases.extend([
+                (false, max_u + 1, None),
+                (false, mask(from), None),
+                (true, max_s + 1, None),
+                (true, (min_s_from - 1) & mask(from), None),
+            ]);
+        } else {
+            // At the same width every value is representable.
+            cases.push((false, mask(from), Some(mask(from))));
+        }
+        for (signed, value, expected) in cases {
+            let hugr = inarrow_hugr(signed, from, to, value);
+            assert_eq!(
+                expected.unwrap_or(999),
+                exec_ctx.exec_hugr_u64(hugr, "main"),
+                "signed: {signed}, value: {value}"
+            );
+        }
+    }
+
+    #[rstest]
+    #[case::iadd("iadd", 2, &[15, 1], 0)]
+    #[case::isub("isub", 1, &[0, 1], 3)]
+    #[case::imul("imul", 2, &[5, 5], 9)]
+    #[case::ineg("ineg", 0, &[1], 1)]
+    #[case::inot("inot", 2, &[0b1010], 0b0101)]
+    #[case::iabs("iabs", 2, &[0b1001], 7)]
+    #[case::ishl("ishl", 2, &[0b0110, 2], 0b1000)]
+    #[case::ishr("ishr", 2, &[0b1000, 3], 1)]
+    #[case::irotl("irotl", 2, &[0b0110, 2], 0b1001)]
+    #[case::irotl_wrap("irotl", 2, &[0b0001, 5], 0b0010)]
+    #[case::irotr("irotr", 1, &[0b01, 1], 0b10)]
+    #[case::ilt_s("ilt_s", 2, &[15, 1], 1)]
+    #[case::ilt_u("ilt_u", 2, &[15, 1], 0)]
+    #[case::imax_s("imax_s", 2, &[15, 1], 1)]
+    #[case::imin_s("imin_s", 2, &[15, 1], 15)]
+    fn exec_sub_byte(
+        mut exec_ctx: TestContext,
+        #[values(SubByteInts::Masked, SubByteInts::Exact)] sub_byte_ints: SubByteInts,
+        #[case] op: &str,
+        #[case] log_width: u8,
+        #[case] inputs: &[u64],
+        #[case] expected: u64,
+    ) {
+        exec_ctx.add_extensions(move |cge| {
+            cge.add_default_prelude_extensions().add_extension(
+                IntCodegenExtension::new(DefaultPreludeCodegen).with_sub_byte_ints(sub_byte_ints),
+            )
+        });
+        let hugr = exec_int_op_hugr(op, log_width, inputs);
+        // Results must not have any high bits set, so we do not mask them.
+        assert_eq!(expected, exec_ctx.exec_hugr_u64(hugr, "main"));
+    }
+
+    #[rstest]
+    #[case::q(0, 0b1100)]
+    #[case::r(1, 1)]
+    fn exec_sub_byte_divmod(
+        mut exec_ctx: TestContext,
+        #[values(SubByteInts::Masked, SubByteInts::Exact)] sub_byte_ints: SubByteInts,
+        #[case] output_index: usize,
+        #[case] expected: u64,
+    ) {
+        exec_ctx.add_extensions(move |cge| {
+            cge.add_default_prelude_extensions().add_extension(
+                IntCodegenExtension::new(DefaultPreludeCodegen).with_sub_byte_ints(sub_byte_ints),
+            )
+        });
+        // -7 divided by 2 is -4 remainder 1.
+        let hugr = divmod_hugr("idivmod_s", 2, 0b1001, 2, output_index);
+        assert_eq!(expected, exec_ctx.exec_hugr_u64(hugr, "main"));
+    }
+
+    #[rstest]
+    #[case::wrap(IntOverflow::Wrap)]
+    #[case::undefined_s(IntOverflow::Undefined { signed: true })]
+    #[case::undefined_u(IntOverflow::Undefined { signed: false })]
+    #[case::trap_s(IntOverflow::Trap { signed: true })]
+    #[case::trap_u(IntOverflow::Trap { signed: false })]
+    fn exec_overflow_modes(
+        mut exec_ctx: TestContext,
+        #[case] overflow: IntOverflow,
+        #[values(2, 3, 6)] log_width: u8,
+        #[values(("iadd", 5), ("isub", 1), ("imul", 6))] op_expected: (&str, u64),
+    ) {
+        let (op, expected) = op_expected;
+        // Nothing overflows, so all modes agree.
+        exec_ctx.add_extensions(move |cge| {
+            cge.add_default_prelude_extensions().add_extension(
+                IntCodegenExtension::new(DefaultPreludeCodegen).with_overflow(overflow),
+            )
+        });
+        let hugr = exec_int_op_hugr(op, log_width, &[3, 2]);
+        assert_eq!(expected, exec_ctx.exec_hugr_u64(hugr, "main"));
+    }
+
+    #[rstest]
+    #[case::wrap(IntOverflow::Wrap, "add i64")]
+    #[case::undefined_s(IntOverflow::Undefined { signed: true }, "add nsw i64")]
+    #[case::undefined_u(IntOverflow::Undefined { signed: false }, "add nuw i64")]
+    #[case::trap_s(IntOverflow::Trap { signed: true }, "@llvm.sadd.with.overflow.i64")]
+    #[case::trap_u(IntOverflow::Trap { signed: false }, "@llvm.uadd.with.overflow.i64")]
+    fn emit_overflow_modes(
+        mut llvm_ctx: TestContext,
+        #[case] overflow: IntOverflow,
+        #[case] expected: &str,
+    ) {
+        // Overflow would abort the test process, so we only check that the
+        // expected instructions are emitted.
+        llvm_ctx.add_extensions(move |cge| {
+            cge.add_default_prelude_extensions().add_extension(
+                IntCodegenExtension::new(DefaultPreludeCodegen).with_overflow(overflow),
+            )
+        });
+        let hugr = exec_int_op_hugr("iadd", 6, &[u64::MAX, 1]);
+        let emission =
+            Emission::emit_hugr(hugr.fat_root().unwrap(), llvm_ctx.get_emit_hugr()).unwrap();
+        emission.verify().unwrap();
+        let module = emission.module().print_to_string().to_string();
+        assert!(module.contains(expected), "{module}");
+        let traps = matches!(overflow, IntOverflow::Trap { .. });
+        assert_eq!(traps, emission.module().get_function("abort").is_some());
+    }
 }
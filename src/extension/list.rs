@@ -0,0 +1,1056 @@
+//! Codegen for the `collections` extension, whose [ListOp]s operate on
+//! dynamically sized lists.
+//!
+//! A list is lowered to a struct of a pointer to its elements, its length, and
+//! its capacity. Its elements are managed by a small runtime with a C ABI, and
+//! ops which may need to access or reallocate them are lowered to calls into
+//! that runtime. Lists are passed to the runtime by pointer, and elements by
+//! pointer together with their size in bytes. With the default
+//! [ListRtCodegen::list_type], the runtime must provide:
+//!
+//! ```c
+//! typedef struct { void *ptr; size_t len; size_t cap; } hugr_list;
+//!
+//! // Initialise `out` with a copy of the `len` elements at `data`.
+//! void __hugr_list_from_array(hugr_list *out, const void *data, size_t len, size_t elem_size);
+//! // Initialise `out` with a copy of the elements of `list`.
+//! void __hugr_list_copy(hugr_list *out, const hugr_list *list, size_t elem_size);
+//! // Append the element at `elem`.
+//! void __hugr_list_push(hugr_list *list, const void *elem, size_t elem_size);
+//! // Remove the last element, copying it to `out`. Returns false if `list` is empty.
+//! bool __hugr_list_pop(hugr_list *list, void *out, size_t elem_size);
+//! // Copy the element at `index` to `out`. Returns false if `index` is out of bounds.
+//! bool __hugr_list_get(const hugr_list *list, size_t index, void *out, size_t elem_size);
+//! // Swap the element at `index` with that at `elem`. Returns false if
+//! // `index` is out of bounds.
+//! bool __hugr_list_set(hugr_list *list, size_t index, void *elem, size_t elem_size);
+//! // Insert the element at `elem` before `index`. Returns false if `index` is
+//! // greater than the length of `list`.
+//! bool __hugr_list_insert(hugr_list *list, size_t index, const void *elem, size_t elem_size);
+//! // Release the elements of `list`, which is not used again.
+//! void __hugr_list_free(hugr_list *list, size_t elem_size);
+//! ```
+//!
+//! The runtime mutates elements in place, so a list must not be used after it
+//! has been passed to an op which returns a new list. A list of copyable
+//! elements may itself be copied, and copies never share their elements. Ops
+//! therefore only mutate or free a list which they own, as described in
+//! [crate::utils::ownership]:
+//! - Lists of linear elements cannot be copied, so are always owned.
+//! - A list of copyable elements is owned if it was loaded from a constant or
+//!   output by `push`, `pop`, `set` or `insert`, possibly via `length`,
+//!   through wires with no other uses.
+//!
+//! `push`, `pop`, `set` and `insert` modify an owned list in place, and
+//! otherwise first copy it with `__hugr_list_copy`. `get` frees a list which
+//! it owns after reading from it. Lists which are not owned by a `get` are
+//! leaked.
+use anyhow::{anyhow, bail, Result};
+use hugr::{
+    extension::{prelude::ConstUsize, simple_op::MakeExtensionOp as _},
+    ops::{constant::Sum, DataflowOpTrait as _, ExtensionOp, NamedOp as _, OpType, Value},
+    std_extensions::collections::{self, ListOp, ListValue},
+    types::{TypeArg, TypeEnum},
+    HugrView, Node, OutgoingPort, PortIndex as _,
+};
+use inkwell::{
+    llvm_sys::core::LLVMIsConstant,
+    module::Linkage,
+    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType},
+    values::{
+        ArrayValue, AsValueRef as _, BasicValue as _, BasicValueEnum, FunctionValue, IntValue,
+        PointerValue,
+    },
+    AddressSpace,
+};
+use itertools::Itertools as _;
+
+use crate::{
+    custom::{CodegenExtension, CodegenExtsBuilder},
+    emit::{emit_value, func::EmitFuncContext, EmitOpArgs},
+    types::{HugrType, TypingSession},
+    utils::ownership::is_owned_wire,
+};
+
+/// The functions of the list runtime. See the [module docs](self) for their
+/// semantics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ListRtFunc {
+    FromArray,
+    Copy,
+    Push,
+    Pop,
+    Get,
+    Set,
+    Insert,
+    Free,
+}
+
+impl ListRtFunc {
+    /// All functions of the list runtime.
+    pub const ALL: [ListRtFunc; 8] = [
+        ListRtFunc::FromArray,
+        ListRtFunc::Copy,
+        ListRtFunc::Push,
+        ListRtFunc::Pop,
+        ListRtFunc::Get,
+        ListRtFunc::Set,
+        ListRtFunc::Insert,
+        ListRtFunc::Free,
+    ];
+
+    /// The symbol of the function in the default runtime.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            ListRtFunc::FromArray => "__hugr_list_from_array",
+            ListRtFunc::Copy => "__hugr_list_copy",
+            ListRtFunc::Push => "__hugr_list_push",
+            ListRtFunc::Pop => "__hugr_list_pop",
+            ListRtFunc::Get => "__hugr_list_get",
+            ListRtFunc::Set => "__hugr_list_set",
+            ListRtFunc::Insert => "__hugr_list_insert",
+            ListRtFunc::Free => "__hugr_list_free",
+        }
+    }
+
+    /// The type of the function, for lists of type `list_ty`. `size_t` is
+    /// assumed to be 64 bits wide.
+    pub fn fn_type(self, list_ty: StructType<'_>) -> FunctionType<'_> {
+        let ctx = list_ty.get_context();
+        let list_ptr: BasicMetadataTypeEnum = list_ty.ptr_type(AddressSpace::default()).into();
+        let elem_ptr = ctx.i8_type().ptr_type(AddressSpace::default()).into();
+        let size = ctx.i64_type().into();
+        match self {
+            ListRtFunc::FromArray => ctx
+                .void_type()
+                .fn_type(&[list_ptr, elem_ptr, size, size], false),
+            ListRtFunc::Copy => ctx.void_type().fn_type(&[list_ptr, list_ptr, size], false),
+            ListRtFunc::Free => ctx.void_type().fn_type(&[list_ptr, size], false),
+            ListRtFunc::Push => ctx.void_type().fn_type(&[list_ptr, elem_ptr, size], false),
+            ListRtFunc::Pop => ctx.bool_type().fn_type(&[list_ptr, elem_ptr, size], false),
+            ListRtFunc::Get | ListRtFunc::Set | ListRtFunc::Insert => ctx
+                .bool_type()
+                .fn_type(&[list_ptr, size, elem_ptr, size], false),
+        }
+    }
+}
+
+/// A helper trait for customising the lowering of the `collections`
+/// extension, and in particular the runtime to which [ListOp]s are lowered.
+///
+/// All methods have defaults, which expect the runtime described in the
+/// [module docs](self). [DefaultListRtCodegen] is a trivial implementation of
+/// this trait which uses those defaults.
+pub trait ListRtCodegen: Clone {
+    /// Return the llvm type of [collections::LIST_TYPENAME]. This is the same
+    /// for all element types.
+    ///
+    /// The default implementation is a struct type with an i8* field and two
+    /// i64 fields for the elements, length, and capacity.
+    fn list_type<'c>(&self, session: &TypingSession<'c, '_>) -> StructType<'c> {
+        let ctx = session.iw_context();
+        ctx.struct_type(
+            &[
+                ctx.i8_type().ptr_type(AddressSpace::default()).into(),
+                ctx.i64_type().into(),
+                ctx.i64_type().into(),
+            ],
+            false,
+        )
+    }
+
+    /// Return the runtime function `func`.
+    ///
+    /// The default implementation declares an external function with symbol
+    /// [ListRtFunc::symbol].
+    fn get_list_rt_func<'c, H: HugrView>(
+        &self,
+        ctx: &EmitFuncContext<'c, '_, H>,
+        func: ListRtFunc,
+    ) -> Result<FunctionValue<'c>> {
+        let list_ty = self.list_type(&ctx.typing_session());
+        ctx.get_extern_func(func.symbol(), func.fn_type(list_ty))
+    }
+
+    /// Emit instructions to read the length of `list` as an `i64`.
+    ///
+    /// The default implementation reads the second field of `list`.
+    fn emit_list_length<'c, H: HugrView>(
+        &self,
+        ctx: &mut EmitFuncContext<'c, '_, H>,
+        list: BasicValueEnum<'c>,
+    ) -> Result<IntValue<'c>> {
+        Ok(ctx
+            .builder()
+            .build_extract_value(list.into_struct_value(), 1, "")?
+            .into_int_value())
+    }
+
+    /// Emit instructions to release `list`, whose elements are of
+    /// `elem_size` bytes, after its last use. This is only called for a list
+    /// which is owned by the op consuming it, as described in the
+    /// [module docs](self).
+    ///
+    /// The default implementation passes `list` to [ListRtFunc::Free].
+    fn emit_list_free<'c, H: HugrView>(
+        &self,
+        ctx: &mut EmitFuncContext<'c, '_, H>,
+        list: BasicValueEnum<'c>,
+        elem_size: IntValue<'c>,
+    ) -> Result<()> {
+        let list_ptr = build_alloca_store(ctx, list)?;
+        build_rt_call(
+            ctx,
+            self,
+            ListRtFunc::Free,
+            &[list_ptr.into(), elem_size.into()],
+        )?;
+        Ok(())
+    }
+}
+
+/// A trivial implementation of [ListRtCodegen] which uses the default
+/// implementation of all methods.
+#[derive(Clone, Debug, Default)]
+pub struct DefaultListRtCodegen;
+
+impl ListRtCodegen for DefaultListRtCodegen {}
+
+/// Stores `v` into a new `alloca`, returning a pointer to it.
+fn build_alloca_store<'c, H: HugrView>(
+    ctx: &EmitFuncContext<'c, '_, H>,
+    v: BasicValueEnum<'c>,
+) -> Result<PointerValue<'c>> {
+    let ptr = ctx.builder().build_alloca(v.get_type(), "")?;
+    ctx.builder().build_store(ptr, v)?;
+    Ok(ptr)
+}
+
+/// Casts `ptr` to an `i8*`, as the runtime expects for elements.
+fn build_i8_ptr<'c, H: HugrView>(
+    ctx: &EmitFuncContext<'c, '_, H>,
+    ptr: PointerValue<'c>,
+) -> Result<BasicValueEnum<'c>> {
+    let i8_ptr_ty = ctx.iw_context().i8_type().ptr_type(AddressSpace::default());
+    Ok(ctx.builder().build_bitcast(ptr, i8_ptr_ty, "")?)
+}
+
+/// Emit a call to the runtime function `func`, returning its result if any.
+fn build_rt_call<'c, H: HugrView>(
+    ctx: &EmitFuncContext<'c, '_, H>,
+    rt: &impl ListRtCodegen,
+    func: ListRtFunc,
+    args: &[BasicValueEnum<'c>],
+) -> Result<Option<BasicValueEnum<'c>>> {
+    let func = rt.get_list_rt_func(ctx, func)?;
+    let args = args.iter().map(|&a| a.into()).collect_vec();
+    Ok(ctx
+        .builder()
+        .build_call(func, &args, "")?
+        .try_as_basic_value()
+        .left())
+}
+
+/// Emit instructions to build a value of the two-variant sum type `sum_ty`,
+/// of variant 1 with fields `ok_vals` if `ok` is true, and otherwise of
+/// variant 0 with fields `err_vals`.
+fn build_result<'c, H: HugrView>(
+    ctx: &EmitFuncContext<'c, '_, H>,
+    sum_ty: &HugrType,
+    ok: IntValue<'c>,
+    ok_vals: Vec<BasicValueEnum<'c>>,
+    err_vals: Vec<BasicValueEnum<'c>>,
+) -> Result<BasicValueEnum<'c>> {
+    let TypeEnum::Sum(sum_ty) = sum_ty.as_type_enum() else {
+        bail!("Expected a sum type: {sum_ty}")
+    };
+    let llvm_sum_ty = ctx.llvm_sum_type(sum_ty.clone())?;
+    let ok_val = llvm_sum_ty.build_tag(ctx.builder(), 1, ok_vals)?;
+    let err_val = llvm_sum_ty.build_tag(ctx.builder(), 0, err_vals)?;
+    Ok(ctx.builder().build_select(ok, ok_val, err_val, "")?)
+}
+
+fn emit_list_op<'c, H: HugrView>(
+    ctx: &mut EmitFuncContext<'c, '_, H>,
+    rt: &impl ListRtCodegen,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    op: ListOp,
+) -> Result<()> {
+    let node = args.node();
+    let [TypeArg::Type { ty: hugr_elem_ty }] = node.args() else {
+        bail!("Expected a single type arg: {:?}", node.args())
+    };
+    let out_tys = node.signature().output;
+    if op == ListOp::length {
+        // The length is read directly, without calling the runtime.
+        let len = rt.emit_list_length(ctx, args.inputs[0])?;
+        let out_ty = ctx.llvm_type(&out_tys[1])?.into_int_type();
+        let len = ctx
+            .builder()
+            .build_int_cast_sign_flag(len, out_ty, false, "")?;
+        return args
+            .outputs
+            .finish(ctx.builder(), [args.inputs[0], len.into()]);
+    }
+
+    let elem_ty = ctx.llvm_type(hugr_elem_ty)?;
+    let elem_size: BasicValueEnum = elem_ty
+        .size_of()
+        .ok_or(anyhow!("ListOp: element type is unsized: {hugr_elem_ty}"))?
+        .into();
+    let usize_ty = ctx.iw_context().i64_type();
+    let index = |ctx: &EmitFuncContext<'c, '_, H>, i: usize| -> Result<BasicValueEnum<'c>> {
+        Ok(ctx
+            .builder()
+            .build_int_cast_sign_flag(args.inputs[i].into_int_value(), usize_ty, false, "")?
+            .into())
+    };
+
+    let owns_list = !hugr_elem_ty.copyable() || {
+        let hugr = node.hugr();
+        is_owned_wire(hugr, node.node(), 0.into(), None, &|node, port, field| {
+            owns_list_output(hugr, node, port, field)
+        })
+    };
+    let list_ptr = build_alloca_store(ctx, args.inputs[0])?;
+    let list_ptr = if !owns_list && op != ListOp::get {
+        // The input may have been copied, so modify a copy of it instead.
+        let copy_ptr = ctx.builder().build_alloca(args.inputs[0].get_type(), "")?;
+        build_rt_call(
+            ctx,
+            rt,
+            ListRtFunc::Copy,
+            &[copy_ptr.into(), list_ptr.into(), elem_size],
+        )?;
+        copy_ptr
+    } else {
+        list_ptr
+    };
+    let list: BasicValueEnum = list_ptr.into();
+    let load_list = |ctx: &EmitFuncContext<'c, '_, H>| ctx.builder().build_load(list_ptr, "");
+    let (elem_ptr, elem) = {
+        let ptr = match op {
+            ListOp::push | ListOp::set | ListOp::insert => {
+                build_alloca_store(ctx, *args.inputs.last().unwrap())?
+            }
+            _ => ctx.builder().build_alloca(elem_ty, "")?,
+        };
+        (ptr, build_i8_ptr(ctx, ptr)?)
+    };
+    let returned_bool = |v: Option<BasicValueEnum<'c>>| -> Result<IntValue<'c>> {
+        Ok(v.ok_or(anyhow!("ListOp: runtime function returned void"))?
+            .into_int_value())
+    };
+
+    let results = match op {
+        ListOp::push => {
+            build_rt_call(ctx, rt, ListRtFunc::Push, &[list, elem, elem_size])?;
+            vec![load_list(ctx)?]
+        }
+        ListOp::pop => {
+            let ok = returned_bool(build_rt_call(
+                ctx,
+                rt,
+                ListRtFunc::Pop,
+                &[list, elem, elem_size],
+            )?)?;
+            let v = ctx.builder().build_load(elem_ptr, "")?;
+            let option = build_result(ctx, &out_tys[1], ok, vec![v], vec![])?;
+            vec![load_list(ctx)?, option]
+        }
+        ListOp::get => {
+            let index = index(ctx, 1)?;
+            let ok = returned_bool(build_rt_call(
+                ctx,
+                rt,
+                ListRtFunc::Get,
+                &[list, index, elem, elem_size],
+            )?)?;
+            let v = ctx.builder().build_load(elem_ptr, "")?;
+            if owns_list {
+                rt.emit_list_free(ctx, args.inputs[0], elem_size.into_int_value())?;
+            }
+            vec![build_result(ctx, &out_tys[0], ok, vec![v], vec![])?]
+        }
+        ListOp::set => {
+            let index = index(ctx, 1)?;
+            let ok = returned_bool(build_rt_call(
+                ctx,
+                rt,
+                ListRtFunc::Set,
+                &[list, index, elem, elem_size],
+            )?)?;
+            // On success this is the old element, and otherwise the new one.
+            let v = ctx.builder().build_load(elem_ptr, "")?;
+            let either = build_result(ctx, &out_tys[1], ok, vec![v], vec![v])?;
+            vec![load_list(ctx)?, either]
+        }
+        ListOp::insert => {
+            let index = index(ctx, 1)?;
+            let ok = returned_bool(build_rt_call(
+                ctx,
+                rt,
+                ListRtFunc::Insert,
+                &[list, index, elem, elem_size],
+            )?)?;
+            let unit = emit_value(ctx, &Value::unit())?;
+            let either = build_result(ctx, &out_tys[1], ok, vec![unit], vec![args.inputs[2]])?;
+            vec![load_list(ctx)?, either]
+        }
+        _ => bail!("ListOp: unsupported op: {op:?}"),
+    };
+    args.outputs.finish(ctx.builder(), results)
+}
+
+/// Returns true if output `port` of `node` is always a list which no other
+/// value references, or a sum containing one at `field`. See the
+/// [module docs](self).
+fn owns_list_output(
+    hugr: &impl HugrView,
+    node: Node,
+    port: OutgoingPort,
+    field: Option<(usize, usize)>,
+) -> bool {
+    if field.is_some() || port.index() != 0 {
+        return false;
+    }
+    match hugr.get_optype(node) {
+        OpType::ExtensionOp(ext_op) => match ListOp::from_extension_op(ext_op) {
+            Ok(ListOp::push | ListOp::pop | ListOp::set | ListOp::insert) => true,
+            // `length` outputs its input list unchanged.
+            Ok(ListOp::length) => {
+                is_owned_wire(hugr, node, 0.into(), None, &|node, port, field| {
+                    owns_list_output(hugr, node, port, field)
+                })
+            }
+            _ => false,
+        },
+        // Each load of a list constant creates a new list.
+        OpType::LoadConstant(_) => hugr
+            .single_linked_output(node, 0)
+            .and_then(|(konst, _)| hugr.get_optype(konst).as_const())
+            .is_some_and(|konst| konst.get_custom_value::<ListValue>().is_some()),
+        _ => false,
+    }
+}
+
+/// Builds an LLVM constant array of `elem_ty` from `values`, which must all be
+/// constants.
+fn const_array<'c>(
+    elem_ty: BasicTypeEnum<'c>,
+    values: &[BasicValueEnum<'c>],
+) -> Result<ArrayValue<'c>> {
+    if let Some(v) = values
+        .iter()
+        .find(|v| unsafe { LLVMIsConstant(v.as_value_ref()) } == 0)
+    {
+        bail!("ListValue: element is not constant: {v:?}")
+    }
+    macro_rules! const_array {
+        ($ty:expr, $into:ident) => {
+            $ty.const_array(&values.iter().map(|v| v.$into()).collect_vec())
+        };
+    }
+    Ok(match elem_ty {
+        BasicTypeEnum::ArrayType(t) => const_array!(t, into_array_value),
+        BasicTypeEnum::FloatType(t) => const_array!(t, into_float_value),
+        BasicTypeEnum::IntType(t) => const_array!(t, into_int_value),
+        BasicTypeEnum::PointerType(t) => const_array!(t, into_pointer_value),
+        BasicTypeEnum::StructType(t) => const_array!(t, into_struct_value),
+        BasicTypeEnum::VectorType(t) => const_array!(t, into_vector_value),
+    })
+}
+
+/// Returns the element type and the contents of `k`. [ListValue] has no
+/// accessor for its contents, so they are read by constant folding
+/// [ListOp::get] at each index until it returns none.
+fn list_value_parts(k: &ListValue) -> Result<(HugrType, Vec<Value>)> {
+    let list_ty = k.custom_type();
+    let [TypeArg::Type { ty: elem_ty }] = list_ty.args() else {
+        bail!("ListValue: expected a single type arg: {list_ty}")
+    };
+    let get = collections::EXTENSION
+        .get_op(&ListOp::get.name())
+        .ok_or(anyhow!("ListValue: collections extension has no get op"))?;
+    let mut consts = [
+        (0.into(), Value::extension(k.clone())),
+        (1.into(), ConstUsize::new(0).into()),
+    ];
+    let mut contents = vec![];
+    loop {
+        consts[1].1 = ConstUsize::new(contents.len() as u64).into();
+        let folded = get
+            .constant_fold(list_ty.args(), &consts)
+            .ok_or(anyhow!("ListValue: failed to fold get"))?;
+        match folded.into_iter().next() {
+            Some((_, Value::Sum(Sum { tag: 1, values, .. }))) => contents.extend(values),
+            Some((_, Value::Sum(Sum { tag: 0, .. }))) => break,
+            r => bail!("ListValue: unexpected result of folding get: {r:?}"),
+        }
+    }
+    Ok((elem_ty.clone(), contents))
+}
+
+/// Emit a [ListValue] by storing its elements in a private constant global
+/// and copying them into a new list with [ListRtFunc::FromArray].
+fn emit_list_value<'c, H: HugrView>(
+    ctx: &mut EmitFuncContext<'c, '_, H>,
+    rt: &impl ListRtCodegen,
+    k: &ListValue,
+) -> Result<BasicValueEnum<'c>> {
+    let (hugr_elem_ty, contents) = list_value_parts(k)?;
+    let elem_ty = ctx.llvm_type(&hugr_elem_ty)?;
+    let elem_size = elem_ty.size_of().ok_or(anyhow!(
+        "ListValue: element type is unsized: {hugr_elem_ty}"
+    ))?;
+    let values = contents
+        .iter()
+        .map(|v| emit_value(ctx, v))
+        .collect::<Result<Vec<_>>>()?;
+    let i8_ptr_ty = ctx.iw_context().i8_type().ptr_type(AddressSpace::default());
+    let data = if values.is_empty() {
+        i8_ptr_ty.const_null().into()
+    } else {
+        let array = const_array(elem_ty, &values)?;
+        let global = ctx
+            .get_current_module()
+            .add_global(array.get_type(), None, "list.const");
+        global.set_initializer(&array);
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+        build_i8_ptr(ctx, global.as_pointer_value())?
+    };
+    let len = ctx
+        .iw_context()
+        .i64_type()
+        .const_int(values.len() as u64, false);
+    let list_ty = rt.list_type(&ctx.typing_session());
+    let list_ptr = ctx.builder().build_alloca(list_ty, "")?;
+    build_rt_call(
+        ctx,
+        rt,
+        ListRtFunc::FromArray,
+        &[list_ptr.into(), data, len.into(), elem_size.into()],
+    )?;
+    Ok(ctx
+        .builder()
+        .build_load(list_ptr, "")?
+        .as_basic_value_enum())
+}
+
+/// A codegen extension for the `collections` extension.
+///
+/// A `ListCodegenExtension` carries a `LRC`, which should impl
+/// [ListRtCodegen]. This determines the runtime to which [ListOp]s are
+/// lowered.
+#[derive(Clone, Debug, Default)]
+pub struct ListCodegenExtension<LRC>(LRC);
+
+impl<LRC: ListRtCodegen> ListCodegenExtension<LRC> {
+    /// Returns a new `ListCodegenExtension` with the given [ListRtCodegen].
+    pub fn new(rt: LRC) -> Self {
+        Self(rt)
+    }
+}
+
+impl<LRC: ListRtCodegen> From<LRC> for ListCodegenExtension<LRC> {
+    fn from(rt: LRC) -> Self {
+        Self::new(rt)
+    }
+}
+
+impl<LRC: ListRtCodegen> CodegenExtension for ListCodegenExtension<LRC> {
+    fn add_extension<'a, H: HugrView + 'a>(
+        self,
+        builder: CodegenExtsBuilder<'a, H>,
+    ) -> CodegenExtsBuilder<'a, H>
+    where
+        Self: 'a,
+    {
+        builder
+            .custom_type((collections::EXTENSION_ID, collections::LIST_TYPENAME), {
+                let rt = self.0.clone();
+                move |ts, _| Ok(rt.list_type(&ts).as_basic_type_enum())
+            })
+            .custom_const::<ListValue>({
+                let rt = self.0.clone();
+                move |ctx, k| emit_list_value(ctx, &rt, k)
+            })
+            .simple_extension_op::<ListOp>(move |ctx, args, op| {
+                emit_list_op(ctx, &self.0, args, op)
+            })
+    }
+}
+
+impl<'a, H: HugrView + 'a> CodegenExtsBuilder<'a, H> {
+    /// Add a [ListCodegenExtension] using [DefaultListRtCodegen].
+    pub fn add_default_list_extensions(self) -> Self {
+        self.add_list_extensions(DefaultListRtCodegen)
+    }
+
+    /// Add a [ListCodegenExtension] using `rt` as the implementation.
+    pub fn add_list_extensions(self, rt: impl ListRtCodegen + 'a) -> Self {
+        self.add_extension(ListCodegenExtension::from(rt))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::alloc::{alloc, Layout};
+    use std::ptr::copy;
+
+    use hugr::{
+        builder::{Dataflow, DataflowSubContainer, SubContainer},
+        extension::{
+            prelude::{ConstUsize, USIZE_T},
+            ExtensionRegistry, PRELUDE,
+        },
+        ops::DataflowOpTrait as _,
+        std_extensions::collections::{self, list_type, ListValue},
+        type_row,
+        types::{Type, TypeRow},
+        Hugr, Wire,
+    };
+    use rstest::rstest;
+
+    use super::*;
+    use crate::{
+        emit::test::{Emission, SimpleHugrConfig, DFGW},
+        test::{exec_ctx, llvm_ctx, TestContext},
+        utils::fat::FatExt as _,
+    };
+
+    /// A runtime for the tests, implementing the functions of [ListRtFunc] for
+    /// the default list type. It counts the live buffers and the copies made
+    /// on the current thread, on which the tests execute their HUGRs.
+    mod test_rt {
+        use std::alloc::dealloc;
+        use std::cell::Cell;
+
+        use super::*;
+
+        thread_local! {
+            pub static LIVE_BUFFERS: Cell<i64> = const { Cell::new(0) };
+            pub static COPIES: Cell<u64> = const { Cell::new(0) };
+        }
+
+        #[repr(C)]
+        pub struct List {
+            ptr: *mut u8,
+            len: u64,
+            cap: u64,
+        }
+
+        fn elem_ptr(list: &List, index: u64, elem_size: u64) -> *mut u8 {
+            list.ptr.wrapping_add((index * elem_size) as usize)
+        }
+
+        fn layout(cap: u64, elem_size: u64) -> Layout {
+            Layout::from_size_align((cap * elem_size).max(1) as usize, 16).unwrap()
+        }
+
+        unsafe fn release(list: &mut List, elem_size: u64) {
+            if list.cap > 0 {
+                dealloc(list.ptr, layout(list.cap, elem_size));
+                LIVE_BUFFERS.set(LIVE_BUFFERS.get() - 1);
+            }
+        }
+
+        unsafe fn reserve(list: &mut List, elem_size: u64) {
+            if list.len < list.cap {
+                return;
+            }
+            let cap = (list.cap * 2).max(4);
+            let ptr = alloc(layout(cap, elem_size));
+            LIVE_BUFFERS.set(LIVE_BUFFERS.get() + 1);
+            if list.len > 0 {
+                copy(list.ptr, ptr, (list.len * elem_size) as usize);
+            }
+            release(list, elem_size);
+            list.ptr = ptr;
+            list.cap = cap;
+        }
+
+        pub unsafe extern "C" fn from_array(
+            out: *mut List,
+            data: *const u8,
+            len: u64,
+            elem_size: u64,
+        ) {
+            let out = &mut *out;
+            *out = List {
+                ptr: std::ptr::null_mut(),
+                len: 0,
+                cap: 0,
+            };
+            for i in 0..len {
+                push(out, data.add((i * elem_size) as usize), elem_size);
+            }
+        }
+
+        pub unsafe extern "C" fn copy_list(out: *mut List, list: *const List, elem_size: u64) {
+            let list = &*list;
+            COPIES.set(COPIES.get() + 1);
+            from_array(out, list.ptr, list.len, elem_size);
+        }
+
+        pub unsafe extern "C" fn free_list(list: *mut List, elem_size: u64) {
+            release(&mut *list, elem_size);
+        }
+
+        pub unsafe extern "C" fn push(list: *mut List, elem: *const u8, elem_size: u64) {
+            let list = &mut *list;
+            reserve(list, elem_size);
+            copy(
+                elem,
+                elem_ptr(list, list.len, elem_size),
+                elem_size as usize,
+            );
+            list.len += 1;
+        }
+
+        pub unsafe extern "C" fn pop(list: *mut List, out: *mut u8, elem_size: u64) -> bool {
+            let list = &mut *list;
+            if list.len == 0 {
+                return false;
+            }
+            list.len -= 1;
+            copy(elem_ptr(list, list.len, elem_size), out, elem_size as usize);
+            true
+        }
+
+        pub unsafe extern "C" fn get(
+            list: *const List,
+            index: u64,
+            out: *mut u8,
+            elem_size: u64,
+        ) -> bool {
+            let list = &*list;
+            if index >= list.len {
+                return false;
+            }
+            copy(elem_ptr(list, index, elem_size), out, elem_size as usize);
+            true
+        }
+
+        pub unsafe extern "C" fn set(
+            list: *mut List,
+            index: u64,
+            elem: *mut u8,
+            elem_size: u64,
+        ) -> bool {
+            let list = &mut *list;
+            if index >= list.len {
+                return false;
+            }
+            std::ptr::swap_nonoverlapping(
+                elem_ptr(list, index, elem_size),
+                elem,
+                elem_size as usize,
+            );
+            true
+        }
+
+        pub unsafe extern "C" fn insert(
+            list: *mut List,
+            index: u64,
+            elem: *const u8,
+            elem_size: u64,
+        ) -> bool {
+            let list = &mut *list;
+            if index > list.len {
+                return false;
+            }
+            reserve(list, elem_size);
+            copy(
+                elem_ptr(list, index, elem_size),
+                elem_ptr(list, index + 1, elem_size),
+                ((list.len - index) * elem_size) as usize,
+            );
+            copy(elem, elem_ptr(list, index, elem_size), elem_size as usize);
+            list.len += 1;
+            true
+        }
+    }
+
+    fn add_test_list_rt(ctx: &mut TestContext) {
+        for func in ListRtFunc::ALL {
+            let addr = match func {
+                ListRtFunc::FromArray => test_rt::from_array as *const () as usize,
+                ListRtFunc::Copy => test_rt::copy_list as *const () as usize,
+                ListRtFunc::Push => test_rt::push as *const () as usize,
+                ListRtFunc::Pop => test_rt::pop as *const () as usize,
+                ListRtFunc::Get => test_rt::get as *const () as usize,
+                ListRtFunc::Set => test_rt::set as *const () as usize,
+                ListRtFunc::Insert => test_rt::insert as *const () as usize,
+                ListRtFunc::Free => test_rt::free_list as *const () as usize,
+            };
+            ctx.add_global_mapping(func.symbol(), addr);
+        }
+    }
+
+    /// The result of a step which returns nothing, or an error.
+    const NONE: u64 = 999;
+
+    #[derive(Clone, Copy, Debug)]
+    enum Step {
+        Push(u64),
+        Pop,
+        Get(u64),
+        Set(u64, u64),
+        Insert(u64, u64),
+        Length,
+    }
+
+    fn list_registry() -> ExtensionRegistry {
+        ExtensionRegistry::try_new([collections::EXTENSION.to_owned(), PRELUDE.to_owned()]).unwrap()
+    }
+
+    fn list_op(name: &str) -> ExtensionOp {
+        collections::EXTENSION
+            .instantiate_extension_op(name, [USIZE_T.into()], &list_registry())
+            .unwrap()
+    }
+
+    /// Converts the two-variant sum `sum` to a `usize`: [NONE] for variant 0,
+    /// the field of variant 1 if it is a `usize`, and otherwise 1.
+    fn sum_to_usize(builder: &mut DFGW, rows: [TypeRow; 2], sum: Wire) -> Wire {
+        let mut cond = builder
+            .conditional_builder((rows.clone(), sum), [], type_row![USIZE_T])
+            .unwrap();
+        for (i, row) in rows.iter().enumerate() {
+            let mut case = cond.case_builder(i).unwrap();
+            let r = if i == 1 && row == &type_row![USIZE_T] {
+                case.input_wires().next().unwrap()
+            } else {
+                case.add_load_value(ConstUsize::new(if i == 0 { NONE } else { 1 }))
+            };
+            case.finish_with_outputs([r]).unwrap();
+        }
+        cond.finish_sub_container().unwrap().out_wire(0)
+    }
+
+    /// Applies `step` to `list`, returning the new list and the result of the
+    /// step as a `usize`.
+    fn build_step(builder: &mut DFGW, list: Wire, step: Step) -> (Wire, Wire) {
+        fn usize_val(builder: &mut DFGW, v: u64) -> Wire {
+            builder.add_load_value(ConstUsize::new(v))
+        }
+        let option_rows = [type_row![], type_row![USIZE_T]];
+        match step {
+            Step::Push(v) => {
+                let v = usize_val(builder, v);
+                let [list] = builder
+                    .add_dataflow_op(list_op("push"), [list, v])
+                    .unwrap()
+                    .outputs_arr();
+                (list, usize_val(builder, NONE))
+            }
+            Step::Pop => {
+                let [list, r] = builder
+                    .add_dataflow_op(list_op("pop"), [list])
+                    .unwrap()
+                    .outputs_arr();
+                (list, sum_to_usize(builder, option_rows, r))
+            }
+            Step::Get(i) => {
+                let i = usize_val(builder, i);
+                let [r] = builder
+                    .add_dataflow_op(list_op("get"), [list, i])
+                    .unwrap()
+                    .outputs_arr();
+                (list, sum_to_usize(builder, option_rows, r))
+            }
+            Step::Set(i, v) => {
+                let (i, v) = (usize_val(builder, i), usize_val(builder, v));
+                let [list, r] = builder
+                    .add_dataflow_op(list_op("set"), [list, i, v])
+                    .unwrap()
+                    .outputs_arr();
+                let rows = [type_row![USIZE_T], type_row![USIZE_T]];
+                (list, sum_to_usize(builder, rows, r))
+            }
+            Step::Insert(i, v) => {
+                let (i, v) = (usize_val(builder, i), usize_val(builder, v));
+                let [list, r] = builder
+                    .add_dataflow_op(list_op("insert"), [list, i, v])
+                    .unwrap()
+                    .outputs_arr();
+                let rows = [type_row![USIZE_T], type_row![Type::UNIT]];
+                (list, sum_to_usize(builder, rows, r))
+            }
+            Step::Length => {
+                let [list, len] = builder
+                    .add_dataflow_op(list_op("length"), [list])
+                    .unwrap()
+                    .outputs_arr();
+                (list, len)
+            }
+        }
+    }
+
+    /// A HUGR which applies `steps` to a list constant with `contents`,
+    /// returning the result of the last step.
+    fn list_steps_hugr(contents: &[u64], steps: &[Step]) -> Hugr {
+        SimpleHugrConfig::new()
+            .with_outs(USIZE_T)
+            .with_extensions(list_registry())
+            .finish(|mut builder| {
+                let contents = contents.iter().map(|&x| ConstUsize::new(x).into());
+                let mut list = builder.add_load_value(ListValue::new(USIZE_T, contents));
+                let mut result = None;
+                for &step in steps {
+                    let (new_list, r) = build_step(&mut builder, list, step);
+                    list = new_list;
+                    result = Some(r);
+                }
+                builder.finish_with_outputs([result.unwrap()]).unwrap()
+            })
+    }
+
+    #[rstest]
+    #[case::length(&[1, 2, 3], &[Step::Length], 3)]
+    #[case::length_empty(&[], &[Step::Length], 0)]
+    #[case::push(&[1, 2, 3], &[Step::Push(4), Step::Length], 4)]
+    #[case::push_get(&[1, 2, 3], &[Step::Push(4), Step::Get(3)], 4)]
+    #[case::push_empty(&[], &[Step::Push(4), Step::Push(5), Step::Get(1)], 5)]
+    #[case::push_grow(
+        &[1, 2, 3, 4],
+        &[Step::Push(5), Step::Push(6), Step::Get(0), Step::Get(5)],
+        6
+    )]
+    #[case::get(&[10, 20, 30], &[Step::Get(1)], 20)]
+    #[case::get_out_of_range(&[10, 20, 30], &[Step::Get(3)], NONE)]
+    #[case::pop(&[10, 20, 30], &[Step::Pop], 30)]
+    #[case::pop_length(&[10, 20, 30], &[Step::Pop, Step::Length], 2)]
+    #[case::pop_empty(&[], &[Step::Pop], NONE)]
+    #[case::set(&[10, 20, 30], &[Step::Set(1, 7)], 20)]
+    #[case::set_get(&[10, 20, 30], &[Step::Set(1, 7), Step::Get(1)], 7)]
+    #[case::set_out_of_range(&[10, 20, 30], &[Step::Set(3, 7)], NONE)]
+    #[case::insert(&[10, 20, 30], &[Step::Insert(0, 7)], 1)]
+    #[case::insert_get(&[10, 20, 30], &[Step::Insert(0, 7), Step::Get(0)], 7)]
+    #[case::insert_shift(&[10, 20, 30], &[Step::Insert(0, 7), Step::Get(3)], 30)]
+    #[case::insert_end(&[10, 20, 30], &[Step::Insert(3, 7), Step::Get(3)], 7)]
+    #[case::insert_out_of_range(&[10, 20, 30], &[Step::Insert(4, 7)], NONE)]
+    fn exec_list_ops(
+        mut exec_ctx: TestContext,
+        #[case] contents: &[u64],
+        #[case] steps: &[Step],
+        #[case] expected: u64,
+    ) {
+        exec_ctx.add_extensions(|cge| {
+            cge.add_default_list_extensions()
+                .add_default_prelude_extensions()
+        });
+        add_test_list_rt(&mut exec_ctx);
+        let hugr = list_steps_hugr(contents, steps);
+        assert_eq!(expected, exec_ctx.exec_hugr_u64(hugr, "main"));
+    }
+
+    /// Owned lists are modified in place, and freed by their final `get`.
+    #[rstest]
+    #[case::push_get(&[1, 2, 3], &[Step::Push(4), Step::Get(3)], 4)]
+    #[case::push_grow(&[1, 2, 3, 4], &[Step::Push(5), Step::Push(6), Step::Get(5)], 6)]
+    #[case::all_ops(
+        &[10, 20, 30],
+        &[Step::Pop, Step::Set(0, 7), Step::Insert(0, 5), Step::Length, Step::Get(1)],
+        7
+    )]
+    fn exec_list_owned(
+        mut exec_ctx: TestContext,
+        #[case] contents: &[u64],
+        #[case] steps: &[Step],
+        #[case] expected: u64,
+    ) {
+        exec_ctx.add_extensions(|cge| {
+            cge.add_default_list_extensions()
+                .add_default_prelude_extensions()
+        });
+        add_test_list_rt(&mut exec_ctx);
+        let hugr = list_steps_hugr(contents, steps);
+        test_rt::LIVE_BUFFERS.set(0);
+        test_rt::COPIES.set(0);
+        assert_eq!(expected, exec_ctx.exec_hugr_u64(hugr, "main"));
+        assert_eq!(0, test_rt::COPIES.get());
+        assert_eq!(0, test_rt::LIVE_BUFFERS.get());
+    }
+
+    #[rstest]
+    fn exec_list_copy(mut exec_ctx: TestContext) {
+        exec_ctx.add_extensions(|cge| {
+            cge.add_default_list_extensions()
+                .add_default_prelude_extensions()
+        });
+        add_test_list_rt(&mut exec_ctx);
+        // Pushing to a copy of a list must leave the original unchanged.
+        let hugr = SimpleHugrConfig::new()
+            .with_outs(USIZE_T)
+            .with_extensions(list_registry())
+            .finish(|mut builder| {
+                let contents = [1, 2, 3].map(|x| ConstUsize::new(x).into());
+                let list = builder.add_load_value(ListValue::new(USIZE_T, contents));
+                let (pushed, _) = build_step(&mut builder, list, Step::Push(4));
+                let (_, len) = build_step(&mut builder, list, Step::Length);
+                builder.set_order(&pushed.node(), &len.node());
+                builder.finish_with_outputs([len]).unwrap()
+            });
+        test_rt::COPIES.set(0);
+        assert_eq!(3, exec_ctx.exec_hugr_u64(hugr, "main"));
+        assert_eq!(1, test_rt::COPIES.get());
+    }
+
+    #[derive(Clone)]
+    struct PrefixedListRtCodegen;
+
+    impl ListRtCodegen for PrefixedListRtCodegen {
+        fn get_list_rt_func<'c, H: HugrView>(
+            &self,
+            ctx: &EmitFuncContext<'c, '_, H>,
+            func: ListRtFunc,
+        ) -> Result<FunctionValue<'c>> {
+            let list_ty = self.list_type(&ctx.typing_session());
+            ctx.get_extern_func(format!("my_{}", func.symbol()), func.fn_type(list_ty))
+        }
+    }
+
+    #[rstest]
+    fn custom_list_rt(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| {
+            cge.add_list_extensions(PrefixedListRtCodegen)
+                .add_default_prelude_extensions()
+        });
+        let hugr = list_steps_hugr(&[1, 2], &[Step::Push(3), Step::Get(0)]);
+        let emission =
+            Emission::emit_hugr(hugr.fat_root().unwrap(), llvm_ctx.get_emit_hugr()).unwrap();
+        emission.verify().unwrap();
+        for func in [ListRtFunc::FromArray, ListRtFunc::Push, ListRtFunc::Get] {
+            let module = emission.module();
+            assert!(module.get_function(func.symbol()).is_none());
+            assert!(module
+                .get_function(&format!("my_{}", func.symbol()))
+                .is_some());
+        }
+    }
+
+    #[rstest]
+    fn list_types(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| {
+            cge.add_default_list_extensions()
+                .add_default_prelude_extensions()
+        });
+        let session = llvm_ctx.get_typing_session();
+        let expected = DefaultListRtCodegen
+            .list_type(&session)
+            .as_basic_type_enum();
+        assert_eq!(expected, session.llvm_type(&list_type(USIZE_T)).unwrap());
+        assert_eq!(
+            expected,
+            session.llvm_type(&list_type(list_type(USIZE_T))).unwrap()
+        );
+        // The op signatures are those the lowering expects.
+        assert_eq!(
+            list_op("length").signature().output,
+            TypeRow::from(vec![list_type(USIZE_T), USIZE_T])
+        );
+    }
+}
@@ -1,15 +1,9 @@
-use hugr::{
-    extension::simple_op::MakeExtensionOp,
-    ops::{ExtensionOp, NamedOp, Value},
-    std_extensions::logic::{self, LogicOp},
-    types::SumType,
-    HugrView,
-};
+use hugr::{ops::ExtensionOp, std_extensions::logic::LogicOp, types::SumType, HugrView};
 use inkwell::IntPredicate;
 
 use crate::{
     custom::CodegenExtsBuilder,
-    emit::{emit_value, func::EmitFuncContext, EmitOpArgs},
+    emit::{func::EmitFuncContext, EmitOpArgs},
     sum::LLVMSumValue,
 };
 
@@ -18,20 +12,18 @@ use anyhow::{anyhow, Result};
 fn emit_logic_op<'c, H: HugrView>(
     context: &mut EmitFuncContext<'c, '_, H>,
     args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    op: LogicOp,
 ) -> Result<()> {
-    let lot = LogicOp::from_optype(&args.node().generalise()).ok_or(anyhow!(
-        "LogicOpEmitter: from_optype_failed: {:?}",
-        args.node().as_ref()
-    ))?;
+    // A bool is a sum of two empty variants, so it is determined by its tag,
+    // which is 0 for false and 1 for true. We operate directly on the tags.
+    let bool_ty = context.llvm_sum_type(SumType::new_unary(2))?;
     let builder = context.builder();
-    // Turn bool sum inputs into i1's
     let mut inputs = vec![];
     for inp in args.inputs {
-        let bool_ty = context.llvm_sum_type(SumType::new_unary(2))?;
-        let bool_val = LLVMSumValue::try_new(inp, bool_ty)?;
+        let bool_val = LLVMSumValue::try_new(inp, bool_ty.clone())?;
         inputs.push(bool_val.build_get_tag(builder)?);
     }
-    let res = match lot {
+    let res = match op {
         LogicOp::And => {
             let mut acc = inputs[0];
             for inp in inputs.into_iter().skip(1) {
@@ -54,31 +46,29 @@ fn emit_logic_op<'c, H: HugrView>(
                 let eq = builder.build_int_compare(IntPredicate::EQ, inp, x, "")?;
                 acc = builder.build_and(acc, eq, "")?;
             }
-            acc
+            builder.build_int_z_extend(acc, bool_ty.get_tag_type(), "")?
+        }
+        LogicOp::Not => {
+            let one = bool_ty.get_tag_type().const_int(1, false);
+            builder.build_xor(inputs[0], one, "")?
         }
         op => {
             return Err(anyhow!("LogicOpEmitter: Unknown op: {op:?}"));
         }
     };
-    // Turn result back into sum
-    let res = builder.build_int_cast(res, context.iw_context().bool_type(), "")?;
-    let true_val = emit_value(context, &Value::true_val())?;
-    let false_val = emit_value(context, &Value::false_val())?;
-    let res = context
-        .builder()
-        .build_select(res, true_val, false_val, "")?;
+    let res = bool_ty.build_from_tag(builder, res)?;
     args.outputs.finish(context.builder(), vec![res])
 }
 
 /// Populates a [CodegenExtsBuilder] with all extensions needed to lower logic
 /// ops.
+///
+/// The constants of the logic extension are plain [hugr::ops::Value::Sum]s, and
+/// need no extension to be lowered.
 pub fn add_logic_extensions<'a, H: HugrView + 'a>(
     cem: CodegenExtsBuilder<'a, H>,
 ) -> CodegenExtsBuilder<'a, H> {
-    cem.extension_op(logic::EXTENSION_ID, LogicOp::Eq.name(), emit_logic_op)
-        .extension_op(logic::EXTENSION_ID, LogicOp::And.name(), emit_logic_op)
-        .extension_op(logic::EXTENSION_ID, LogicOp::Or.name(), emit_logic_op)
-        .extension_op(logic::EXTENSION_ID, LogicOp::Not.name(), emit_logic_op)
+    cem.simple_extension_op::<LogicOp>(emit_logic_op)
 }
 
 impl<'a, H: HugrView + 'a> CodegenExtsBuilder<'a, H> {
@@ -92,10 +82,14 @@ impl<'a, H: HugrView + 'a> CodegenExtsBuilder<'a, H> {
 #[cfg(test)]
 mod test {
     use hugr::{
-        builder::{Dataflow, DataflowSubContainer},
-        extension::{prelude::BOOL_T, ExtensionRegistry},
+        builder::{Dataflow, DataflowSubContainer, SubContainer},
+        extension::{
+            prelude::{ConstUsize, BOOL_T, USIZE_T},
+            ExtensionRegistry, PRELUDE,
+        },
+        ops::Value,
         std_extensions::logic::{self, LogicOp},
-        Hugr,
+        type_row, Hugr,
     };
     use rstest::rstest;
 
@@ -103,7 +97,7 @@ mod test {
         check_emission,
         emit::test::SimpleHugrConfig,
         extension::logic::add_logic_extensions,
-        test::{llvm_ctx, TestContext},
+        test::{exec_ctx, llvm_ctx, TestContext},
     };
 
     fn test_logic_op(op: LogicOp, arity: usize) -> Hugr {
@@ -140,4 +134,67 @@ mod test {
         let hugr = test_logic_op(LogicOp::Eq, 2);
         check_emission!(hugr, llvm_ctx);
     }
+
+    /// A HUGR applying `op` to constant `inputs`, returning the result as a
+    /// `usize`.
+    fn exec_logic_op_hugr(op: LogicOp, inputs: &[bool]) -> Hugr {
+        SimpleHugrConfig::new()
+            .with_outs(USIZE_T)
+            .with_extensions(
+                ExtensionRegistry::try_new([logic::EXTENSION.to_owned(), PRELUDE.to_owned()])
+                    .unwrap(),
+            )
+            .finish(|mut builder| {
+                let ins = inputs
+                    .iter()
+                    .map(|&b| {
+                        builder.add_load_value(if b {
+                            Value::true_val()
+                        } else {
+                            Value::false_val()
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let [r] = builder.add_dataflow_op(op, ins).unwrap().outputs_arr();
+                let mut cond = builder
+                    .conditional_builder(([type_row![], type_row![]], r), [], type_row![USIZE_T])
+                    .unwrap();
+                for i in 0..2 {
+                    let mut case = cond.case_builder(i).unwrap();
+                    let result = case.add_load_value(ConstUsize::new(i as u64));
+                    case.finish_with_outputs([result]).unwrap();
+                }
+                let res = cond.finish_sub_container().unwrap();
+                builder.finish_with_outputs(res.outputs()).unwrap()
+            })
+    }
+
+    #[rstest]
+    #[case::and(LogicOp::And, |x: &[bool]| x[0] && x[1])]
+    #[case::or(LogicOp::Or, |x: &[bool]| x[0] || x[1])]
+    #[case::eq(LogicOp::Eq, |x: &[bool]| x[0] == x[1])]
+    fn exec_binary_logic_op(
+        mut exec_ctx: TestContext,
+        #[case] op: LogicOp,
+        #[case] expected: fn(&[bool]) -> bool,
+    ) {
+        exec_ctx.add_extensions(|cge| cge.add_logic_extensions().add_default_prelude_extensions());
+        for inputs in [[false, false], [false, true], [true, false], [true, true]] {
+            let hugr = exec_logic_op_hugr(op, &inputs);
+            assert_eq!(
+                expected(&inputs) as u64,
+                exec_ctx.exec_hugr_u64(hugr, "main"),
+                "{op:?} {inputs:?}"
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(false, 1)]
+    #[case(true, 0)]
+    fn exec_not(mut exec_ctx: TestContext, #[case] input: bool, #[case] expected: u64) {
+        exec_ctx.add_extensions(|cge| cge.add_logic_extensions().add_default_prelude_extensions());
+        let hugr = exec_logic_op_hugr(LogicOp::Not, &[input]);
+        assert_eq!(expected, exec_ctx.exec_hugr_u64(hugr, "main"));
+    }
 }
@@ -8,13 +8,14 @@ use hugr::{
         },
         simple_op::MakeExtensionOp as _,
     },
-    ops::constant::CustomConst,
+    ops::{constant::CustomConst, ExtensionOp, OpType},
+    std_extensions::arithmetic::float_ops::FloatOps,
     types::{SumType, TypeArg},
     HugrView,
 };
 use inkwell::{
-    types::{BasicType, BasicTypeEnum, IntType, PointerType},
-    values::{BasicValue as _, BasicValueEnum, StructValue},
+    types::{BasicType, BasicTypeEnum, IntType, PointerType, StructType},
+    values::{BasicValue as _, BasicValueEnum, IntValue, StructValue},
     AddressSpace,
 };
 use itertools::Itertools;
@@ -23,14 +24,18 @@ use crate::{
     custom::{CodegenExtension, CodegenExtsBuilder},
     emit::{
         func::EmitFuncContext,
-        libc::{emit_libc_abort, emit_libc_printf},
-        RowPromise,
+        libc::{emit_libc_abort, emit_libc_free, emit_libc_printf, emit_libc_snprintf},
+        EmitErrorKind, EmitOpArgs,
     },
     sum::LLVMSumValue,
     types::TypingSession,
+    utils::ownership::is_owned_wire,
 };
 
 pub mod array;
+pub mod heap_array;
+
+use array::{ArrayBoundsCheck, ArrayRepresentation, ARRAY_INDEX_OUT_OF_BOUNDS_SIGNAL};
 
 /// A helper trait for customising the lowering [hugr::extension::prelude]
 /// types, [CustomConst]s, and ops.
@@ -68,6 +73,15 @@ pub trait PreludeCodegen: Clone {
         ))
     }
 
+    /// Return the representation of [hugr::extension::prelude::array_type]
+    /// values, which selects the default implementations of
+    /// [Self::array_type] and [Self::emit_array_op].
+    ///
+    /// The default implementation returns [ArrayRepresentation::Value].
+    fn array_representation(&self) -> ArrayRepresentation {
+        ArrayRepresentation::default()
+    }
+
     /// Return the llvm type of [hugr::extension::prelude::array_type].
     fn array_type<'c>(
         &self,
@@ -75,18 +89,33 @@ pub trait PreludeCodegen: Clone {
         elem_ty: BasicTypeEnum<'c>,
         size: u64,
     ) -> impl BasicType<'c> {
-        elem_ty.array_type(size as u32)
+        match self.array_representation() {
+            ArrayRepresentation::Value => elem_ty.array_type(size as u32).as_basic_type_enum(),
+            ArrayRepresentation::Heap => heap_array::heap_array_type(elem_ty).as_basic_type_enum(),
+        }
+    }
+
+    /// Return how out of bounds indices to array ops are handled by the
+    /// default implementation of [Self::emit_array_op].
+    ///
+    /// The default implementation returns [ArrayBoundsCheck::Checked].
+    fn array_bounds_check(&self) -> ArrayBoundsCheck {
+        ArrayBoundsCheck::default()
     }
 
     /// Emit a [hugr::extension::prelude::ArrayOp].
     fn emit_array_op<'c, H: HugrView>(
         &self,
         ctx: &mut EmitFuncContext<'c, '_, H>,
-        op: ArrayOp,
-        inputs: Vec<BasicValueEnum<'c>>,
-        outputs: RowPromise<'c>,
+        args: EmitOpArgs<'c, '_, ExtensionOp, H>,
     ) -> Result<()> {
-        array::emit_array_op(self, ctx, op, inputs, outputs)
+        match self.array_representation() {
+            ArrayRepresentation::Value => {
+                let op = ArrayOp::from_extension_op(args.node().as_ref())?;
+                array::emit_array_op(self, ctx, op, args.inputs, args.outputs)
+            }
+            ArrayRepresentation::Heap => heap_array::emit_heap_array_op(self, ctx, args),
+        }
     }
 
     /// Emit a [hugr::extension::prelude::PRINT_OP_ID] node.
@@ -102,6 +131,25 @@ pub trait PreludeCodegen: Clone {
         emit_libc_printf(ctx, &[format_str.into(), text.into()])
     }
 
+    /// Emit instructions to release a string allocated at runtime, after its
+    /// last use.
+    ///
+    /// Strings are copyable and have no drop op, so this is only called for a
+    /// string which is owned by the op consuming it, as described in
+    /// [crate::utils::ownership]. Currently these are the outputs of
+    /// [FloatOps::ftostring] which are passed straight to a
+    /// [hugr::extension::prelude::PRINT_OP_ID] node.
+    ///
+    /// The default implementation calls libc's `free`, matching the `malloc`
+    /// with which `ftostring` allocates its buffer.
+    fn emit_free_string<H: HugrView>(
+        &self,
+        ctx: &mut EmitFuncContext<H>,
+        text: BasicValueEnum,
+    ) -> Result<()> {
+        emit_libc_free(ctx, text.into_pointer_value())
+    }
+
     /// Emit instructions to materialise an LLVM value representing `err`.
     ///
     /// The type of the returned value must match [Self::error_type].
@@ -158,6 +206,86 @@ pub trait PreludeCodegen: Clone {
         emit_libc_printf(ctx, &[format_str.into(), signal.into(), msg.into()])?;
         emit_libc_abort(ctx)
     }
+
+    /// Emit instructions to halt execution because `index` is out of bounds
+    /// of an array of length `size`. This is used when
+    /// [Self::array_bounds_check] is [ArrayBoundsCheck::Panic].
+    ///
+    /// The default implementation formats a message containing `size` and
+    /// `index` with libc's `snprintf`, and passes an error with that message
+    /// and signal [ARRAY_INDEX_OUT_OF_BOUNDS_SIGNAL] to [Self::emit_panic]. It
+    /// requires [Self::error_type] to be a struct of an integer and an `i8*`,
+    /// as in its default implementation.
+    ///
+    /// As for [Self::emit_panic], implementations must not emit `unreachable`
+    /// terminators.
+    fn emit_array_index_panic<'c, H: HugrView>(
+        &self,
+        ctx: &mut EmitFuncContext<'c, '_, H>,
+        size: u64,
+        index: IntValue<'c>,
+    ) -> Result<()> {
+        let iw_ctx = ctx.iw_context();
+        let str_ty = iw_ctx.i8_type().ptr_type(AddressSpace::default());
+        let Some(err_ty) = StructType::try_from(ctx.llvm_type(&ERROR_TYPE)?).ok() else {
+            bail!("emit_array_index_panic: Expected error type to be a struct type")
+        };
+        ensure!(err_ty.count_fields() == 2);
+        let Some(signal_ty) = IntType::try_from(err_ty.get_field_type_at_index(0).unwrap()).ok()
+        else {
+            bail!("emit_array_index_panic: Expected error signal to be an int type")
+        };
+        ensure!(err_ty.get_field_type_at_index(1) == Some(str_ty.as_basic_type_enum()));
+
+        let builder = ctx.builder();
+        let format_str = builder
+            .build_global_string_ptr(
+                "Index out of bounds: the length is %llu but the index is %llu",
+                "prelude.array_index_panic_template",
+            )?
+            .as_basic_value_enum();
+        let buffer_size = iw_ctx
+            .i64_type()
+            .const_int(ARRAY_INDEX_PANIC_BUFFER_SIZE, false);
+        let index = builder.build_int_z_extend_or_bit_cast(index, iw_ctx.i64_type(), "")?;
+        let buffer = ctx.build_prologue(|builder| {
+            builder.build_array_alloca(iw_ctx.i8_type(), buffer_size, "")
+        })?;
+        emit_libc_snprintf(
+            ctx,
+            &[
+                buffer.into(),
+                buffer_size.into(),
+                format_str.into(),
+                iw_ctx.i64_type().const_int(size, false).into(),
+                index.into(),
+            ],
+        )?;
+
+        let builder = ctx.builder();
+        let signal = signal_ty.const_int(ARRAY_INDEX_OUT_OF_BOUNDS_SIGNAL as u64, false);
+        let err = builder
+            .build_insert_value(err_ty.get_undef(), signal, 0, "")?
+            .into_struct_value();
+        let err = builder
+            .build_insert_value(err, buffer, 1, "")?
+            .into_struct_value();
+        self.emit_panic(ctx, err.into())
+    }
+}
+
+/// The size of the buffer into which the message of the default
+/// implementation of [PreludeCodegen::emit_array_index_panic] is formatted.
+const ARRAY_INDEX_PANIC_BUFFER_SIZE: u64 = 128;
+
+/// Returns true if the output of `op` is always a string allocated at
+/// runtime, which may be released with [PreludeCodegen::emit_free_string].
+fn owns_string_output(op: &OpType, field: Option<(usize, usize)>) -> bool {
+    field.is_none()
+        && op
+            .as_extension_op()
+            .and_then(|ext_op| FloatOps::from_extension_op(ext_op).ok())
+            == Some(FloatOps::ftostring)
 }
 
 /// A trivial implementation of [PreludeCodegen] which passes all methods
@@ -299,31 +427,39 @@ fn add_prelude_extensions<'a, H: HugrView + 'a>(
             let r = llvm_sum_type.build_tag(context.builder(), 0, args.inputs)?;
             args.outputs.finish(context.builder(), [r])
         }
-        _ => Err(anyhow!("Unsupported TupleOpDef")),
+        op => Err(EmitErrorKind::UnsupportedOp(format!(
+            "Unsupported TupleOpDef: {op:?}"
+        )))?,
     })
     .simple_extension_op::<ArrayOpDef>({
         let pcg = pcg.clone();
-        move |context, args, _| {
-            pcg.emit_array_op(
-                context,
-                ArrayOp::from_extension_op(args.node().as_ref())?,
-                args.inputs,
-                args.outputs,
-            )
-        }
+        move |context, args, _| pcg.emit_array_op(context, args)
     })
     .extension_op(prelude::PRELUDE_ID, prelude::PRINT_OP_ID, {
         let pcg = pcg.clone();
         move |context, args| {
-            let text = args.inputs[0];
+            let node = args.node();
+            let [text] = args
+                .inputs
+                .try_into()
+                .map_err(|_| anyhow!("print expects one argument"))?;
             pcg.emit_print(context, text)?;
+            let hugr = node.hugr();
+            if is_owned_wire(hugr, node.node(), 0.into(), None, &|node, _, field| {
+                owns_string_output(hugr.get_optype(node), field)
+            }) {
+                pcg.emit_free_string(context, text)?;
+            }
             args.outputs.finish(context.builder(), [])
         }
     })
     .extension_op(prelude::PRELUDE_ID, prelude::PANIC_OP_ID, {
         let pcg = pcg.clone();
         move |context, args| {
-            let err = args.inputs[0];
+            let err = *args
+                .inputs
+                .first()
+                .ok_or(anyhow!("panic expects an error argument"))?;
             ensure!(
                 err.get_type()
                     == pcg
@@ -11,19 +11,159 @@ use hugr::{
 };
 use inkwell::{
     builder::{Builder, BuilderError},
-    types::BasicType,
+    types::{ArrayType, BasicType},
     values::{ArrayValue, BasicValue as _, BasicValueEnum, IntValue, PointerValue},
     IntPredicate,
 };
 
 use crate::{
-    emit::{EmitFuncContext, RowPromise},
+    emit::{EmitErrorKind, EmitFuncContext, RowPromise},
     sum::LLVMSumType,
     types::{HugrType, TypingSession},
 };
 
 use super::PreludeCodegen;
 
+/// The llvm representation of [hugr::extension::prelude::array_type], as
+/// returned by [PreludeCodegen::array_representation].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ArrayRepresentation {
+    /// Arrays are llvm array values, i.e. `[N x T]`. Ops are emitted by
+    /// [emit_array_op], which copies the array to the stack to access its
+    /// elements.
+    #[default]
+    Value,
+    /// Arrays are pointers to heap allocated buffers, i.e. `T*`. Ops are
+    /// emitted by [super::heap_array::emit_heap_array_op].
+    Heap,
+}
+
+/// How out of bounds indices to the `get`, `set` and `swap` ops of
+/// [hugr::extension::prelude::array_type] are handled, as returned by
+/// [PreludeCodegen::array_bounds_check].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ArrayBoundsCheck {
+    /// Indices are checked at runtime. Out of bounds accesses return the
+    /// failure variant of the op's result.
+    #[default]
+    Checked,
+    /// Indices are assumed to be in bounds and are not checked, so the op
+    /// always returns the success variant of its result. Out of bounds
+    /// accesses are undefined behaviour.
+    Unchecked,
+    /// Indices are checked at runtime. Out of bounds accesses panic via
+    /// [PreludeCodegen::emit_array_index_panic], with the length of the array
+    /// and the index.
+    Panic,
+}
+
+/// The signal of the error with which [ArrayBoundsCheck::Panic] panics in the
+/// default implementation of [PreludeCodegen::emit_array_index_panic].
+pub const ARRAY_INDEX_OUT_OF_BOUNDS_SIGNAL: u32 = 1;
+
+/// Returns an `i1` which is true iff `index` is less than `size`.
+fn build_in_bounds<'c>(
+    builder: &Builder<'c>,
+    index: IntValue<'c>,
+    size: u64,
+) -> Result<IntValue<'c>> {
+    Ok(builder.build_int_compare(
+        IntPredicate::ULT,
+        index,
+        index.get_type().const_int(size, false),
+        "",
+    )?)
+}
+
+/// Emits the bounds checks of an op with `indices` into an array of length
+/// `size`, according to [PreludeCodegen::array_bounds_check], and returns the
+/// result of the op, a sum of type `res_hugr_ty`.
+///
+/// If all `indices` are in bounds the result has tag 1 and the fields returned
+/// by `success`, which may assume that the indices are in bounds. Otherwise the
+/// result has tag 0 and the fields `failure`.
+pub(super) fn emit_bounds_checked<'c, H: HugrView>(
+    pcg: &impl PreludeCodegen,
+    ctx: &mut EmitFuncContext<'c, '_, H>,
+    res_hugr_ty: &HugrType,
+    size: u64,
+    indices: &[IntValue<'c>],
+    success: impl FnOnce(&Builder<'c>) -> Result<Vec<BasicValueEnum<'c>>>,
+    failure: Vec<BasicValueEnum<'c>>,
+) -> Result<BasicValueEnum<'c>> {
+    let res_sum_ty = {
+        let TypeEnum::Sum(st) = res_hugr_ty.as_type_enum() else {
+            Err(anyhow!("ArrayOp output is not a sum type"))?
+        };
+        ctx.llvm_sum_type(st.clone())?
+    };
+
+    match pcg.array_bounds_check() {
+        ArrayBoundsCheck::Checked => {
+            let exit_rmb = ctx.new_row_mail_box([res_hugr_ty], "")?;
+            let exit_block = ctx.new_basic_block("", None);
+
+            let success_block =
+                ctx.build_positioned_new_block("", Some(exit_block), |ctx, bb| {
+                    let builder = ctx.builder();
+                    let success_v = res_sum_ty.build_tag(builder, 1, success(builder)?)?;
+                    exit_rmb.write(builder, [success_v])?;
+                    builder.build_unconditional_branch(exit_block)?;
+                    Ok(bb)
+                })?;
+
+            let failure_block =
+                ctx.build_positioned_new_block("", Some(success_block), |ctx, bb| {
+                    let builder = ctx.builder();
+                    let failure_v = res_sum_ty.build_tag(builder, 0, failure)?;
+                    exit_rmb.write(builder, [failure_v])?;
+                    builder.build_unconditional_branch(exit_block)?;
+                    Ok(bb)
+                })?;
+
+            let builder = ctx.builder();
+            let indices_ok = indices
+                .iter()
+                .map(|&index| build_in_bounds(builder, index, size))
+                .collect::<Result<Vec<_>>>()?;
+            let (first_ok, rest_ok) = indices_ok
+                .split_first()
+                .ok_or(anyhow!("ArrayOp has no indices"))?;
+            let is_success = rest_ok.iter().try_fold(*first_ok, |acc, &index_ok| {
+                builder.build_and(acc, index_ok, "")
+            })?;
+            builder.build_conditional_branch(is_success, success_block, failure_block)?;
+            builder.position_at_end(exit_block);
+            let [r] = exit_rmb
+                .read_vec(builder, [])?
+                .try_into()
+                .map_err(|_| anyhow!("ArrayOp has more than one output"))?;
+            Ok(r)
+        }
+        ArrayBoundsCheck::Unchecked => {
+            let builder = ctx.builder();
+            res_sum_ty.build_tag(builder, 1, success(builder)?)
+        }
+        ArrayBoundsCheck::Panic => {
+            for &index in indices {
+                let in_bounds = build_in_bounds(ctx.builder(), index, size)?;
+                let ok_block = ctx.new_basic_block("", None);
+                let panic_block =
+                    ctx.build_positioned_new_block("", Some(ok_block), |ctx, bb| {
+                        pcg.emit_array_index_panic(ctx, size, index)?;
+                        ctx.builder().build_unreachable()?;
+                        Ok(bb)
+                    })?;
+                let builder = ctx.builder();
+                builder.build_conditional_branch(in_bounds, ok_block, panic_block)?;
+                builder.position_at_end(ok_block);
+            }
+            let builder = ctx.builder();
+            res_sum_ty.build_tag(builder, 1, success(builder)?)
+        }
+    }
+}
+
 /// Helper function to allocate an array on the stack and pass a pointer to it
 /// to a closure.
 ///
@@ -57,16 +197,21 @@ pub fn emit_array_op<'c, H: HugrView>(
 ) -> Result<()> {
     let builder = ctx.builder();
     let ts = ctx.typing_session();
-    let sig = op.clone().to_extension_op().unwrap().signature();
+    let sig = op
+        .clone()
+        .to_extension_op()
+        .ok_or(anyhow!("Failed to build ExtensionOp for {:?}", op.def))?
+        .signature();
     let ArrayOp {
         def,
         ref elem_ty,
         size,
     } = op;
-    let llvm_array_ty = pcg
+    let llvm_array_ty: ArrayType = pcg
         .array_type(&ts, ts.llvm_type(elem_ty)?, size)
         .as_basic_type_enum()
-        .into_array_type();
+        .try_into()
+        .map_err(|_| anyhow!("PreludeCodegen::array_type is not an llvm array type"))?;
     match def {
         ArrayOpDef::new_array => {
             let mut array_v = llvm_array_ty.get_undef();
@@ -88,56 +233,24 @@ pub fn emit_array_op<'c, H: HugrView>(
                 .get(0)
                 .ok_or(anyhow!("ArrayOp::get has no outputs"))?;
 
-            let res_sum_ty = {
-                let TypeEnum::Sum(st) = res_hugr_ty.as_type_enum() else {
-                    Err(anyhow!("ArrayOp::get output is not a sum type"))?
-                };
-                LLVMSumType::try_new(&ts, st.clone())?
-            };
-
-            let exit_rmb = ctx.new_row_mail_box([res_hugr_ty], "")?;
-
-            let exit_block = ctx.build_positioned_new_block("", None, |ctx, bb| {
-                outputs.finish(ctx.builder(), exit_rmb.read_vec(ctx.builder(), [])?)?;
-                Ok(bb)
-            })?;
-
-            let success_block =
-                ctx.build_positioned_new_block("", Some(exit_block), |ctx, bb| {
-                    let builder = ctx.builder();
+            let r = emit_bounds_checked(
+                pcg,
+                ctx,
+                res_hugr_ty,
+                size,
+                &[index_v],
+                |builder| {
                     let elem_v = with_array_alloca(builder, array_v, |ptr| {
-                        // inside `success_block` we know `index_v` to be in
-                        // bounds.
+                        // here we know `index_v` to be in bounds.
                         let elem_addr =
                             unsafe { builder.build_in_bounds_gep(ptr, &[index_v], "")? };
                         builder.build_load(elem_addr, "")
                     })?;
-                    let success_v = res_sum_ty.build_tag(builder, 1, vec![elem_v])?;
-                    exit_rmb.write(ctx.builder(), [success_v])?;
-                    builder.build_unconditional_branch(exit_block)?;
-                    Ok(bb)
-                })?;
-
-            let failure_block =
-                ctx.build_positioned_new_block("", Some(success_block), |ctx, bb| {
-                    let builder = ctx.builder();
-                    let failure_v = res_sum_ty.build_tag(builder, 0, vec![])?;
-                    exit_rmb.write(ctx.builder(), [failure_v])?;
-                    builder.build_unconditional_branch(exit_block)?;
-                    Ok(bb)
-                })?;
-
-            let builder = ctx.builder();
-            let is_success = builder.build_int_compare(
-                IntPredicate::ULT,
-                index_v,
-                index_v.get_type().const_int(size, false),
-                "",
+                    Ok(vec![elem_v])
+                },
+                vec![],
             )?;
-
-            builder.build_conditional_branch(is_success, success_block, failure_block)?;
-            builder.position_at_end(exit_block);
-            Ok(())
+            outputs.finish(ctx.builder(), [r])
         }
         ArrayOpDef::set => {
             let [array_v0, index_v, value_v] = inputs
@@ -151,26 +264,15 @@ pub fn emit_array_op<'c, H: HugrView>(
                 .get(0)
                 .ok_or(anyhow!("ArrayOp::set has no outputs"))?;
 
-            let res_sum_ty = {
-                let TypeEnum::Sum(st) = res_hugr_ty.as_type_enum() else {
-                    Err(anyhow!("ArrayOp::set output is not a sum type"))?
-                };
-                LLVMSumType::try_new(&ts, st.clone())?
-            };
-
-            let exit_rmb = ctx.new_row_mail_box([res_hugr_ty], "")?;
-
-            let exit_block = ctx.build_positioned_new_block("", None, |ctx, bb| {
-                outputs.finish(ctx.builder(), exit_rmb.read_vec(ctx.builder(), [])?)?;
-                Ok(bb)
-            })?;
-
-            let success_block =
-                ctx.build_positioned_new_block("", Some(exit_block), |ctx, bb| {
-                    let builder = ctx.builder();
+            let r = emit_bounds_checked(
+                pcg,
+                ctx,
+                res_hugr_ty,
+                size,
+                &[index_v],
+                |builder| {
                     let (elem_v, array_v) = with_array_alloca(builder, array_v, |ptr| {
-                        // inside `success_block` we know `index_v` to be in
-                        // bounds.
+                        // here we know `index_v` to be in bounds.
                         let elem_addr =
                             unsafe { builder.build_in_bounds_gep(ptr, &[index_v], "")? };
                         let elem_v = builder.build_load(elem_addr, "")?;
@@ -185,32 +287,11 @@ pub fn emit_array_op<'c, H: HugrView>(
                         let array_v = builder.build_load(ptr, "")?;
                         Ok((elem_v, array_v))
                     })?;
-                    let success_v = res_sum_ty.build_tag(builder, 1, vec![elem_v, array_v])?;
-                    exit_rmb.write(ctx.builder(), [success_v])?;
-                    builder.build_unconditional_branch(exit_block)?;
-                    Ok(bb)
-                })?;
-
-            let failure_block =
-                ctx.build_positioned_new_block("", Some(success_block), |ctx, bb| {
-                    let builder = ctx.builder();
-                    let failure_v =
-                        res_sum_ty.build_tag(builder, 0, vec![value_v, array_v.into()])?;
-                    exit_rmb.write(ctx.builder(), [failure_v])?;
-                    builder.build_unconditional_branch(exit_block)?;
-                    Ok(bb)
-                })?;
-
-            let builder = ctx.builder();
-            let is_success = builder.build_int_compare(
-                IntPredicate::ULT,
-                index_v,
-                index_v.get_type().const_int(size, false),
-                "",
+                    Ok(vec![elem_v, array_v])
+                },
+                vec![value_v, array_v.into()],
             )?;
-            builder.build_conditional_branch(is_success, success_block, failure_block)?;
-            builder.position_at_end(exit_block);
-            Ok(())
+            outputs.finish(ctx.builder(), [r])
         }
         ArrayOpDef::swap => {
             let [array_v0, index1_v, index2_v] = inputs
@@ -225,22 +306,13 @@ pub fn emit_array_op<'c, H: HugrView>(
                 .get(0)
                 .ok_or(anyhow!("ArrayOp::swap has no outputs"))?;
 
-            let res_sum_ty = {
-                let TypeEnum::Sum(st) = res_hugr_ty.as_type_enum() else {
-                    Err(anyhow!("ArrayOp::swap output is not a sum type"))?
-                };
-                LLVMSumType::try_new(&ts, st.clone())?
-            };
-
-            let exit_rmb = ctx.new_row_mail_box([res_hugr_ty], "")?;
-
-            let exit_block = ctx.build_positioned_new_block("", None, |ctx, bb| {
-                outputs.finish(ctx.builder(), exit_rmb.read_vec(ctx.builder(), [])?)?;
-                Ok(bb)
-            })?;
-
-            let success_block =
-                ctx.build_positioned_new_block("", Some(exit_block), |ctx, bb| {
+            let r = emit_bounds_checked(
+                pcg,
+                ctx,
+                res_hugr_ty,
+                size,
+                &[index1_v, index2_v],
+                |builder| {
                     // if `index1_v` == `index2_v` then the following is a no-op.
                     // We could check for this: either with a select instruction
                     // here, or by branching to another case in earlier.
@@ -248,10 +320,9 @@ pub fn emit_array_op<'c, H: HugrView>(
                     // optimiser can determine that the indices are the same, at
                     // the cost of worse code in cases where it cannot.
                     // For now we choose the simpler option of omitting the check.
-                    let builder = ctx.builder();
                     let array_v = with_array_alloca(builder, array_v, |ptr| {
-                        // inside `success_block` we know `index1_v` and `index2_v`
-                        // to be in bounds.
+                        // here we know `index1_v` and `index2_v` to be in
+                        // bounds.
                         let elem1_addr =
                             unsafe { builder.build_in_bounds_gep(ptr, &[index1_v], "")? };
                         let elem1_v = builder.build_load(elem1_addr, "")?;
@@ -269,40 +340,11 @@ pub fn emit_array_op<'c, H: HugrView>(
                             .into_pointer_value();
                         builder.build_load(ptr, "")
                     })?;
-                    let success_v = res_sum_ty.build_tag(builder, 1, vec![array_v])?;
-                    exit_rmb.write(ctx.builder(), [success_v])?;
-                    builder.build_unconditional_branch(exit_block)?;
-                    Ok(bb)
-                })?;
-
-            let failure_block =
-                ctx.build_positioned_new_block("", Some(success_block), |ctx, bb| {
-                    let builder = ctx.builder();
-                    let failure_v = res_sum_ty.build_tag(builder, 0, vec![array_v.into()])?;
-                    exit_rmb.write(ctx.builder(), [failure_v])?;
-                    builder.build_unconditional_branch(exit_block)?;
-                    Ok(bb)
-                })?;
-
-            let builder = ctx.builder();
-            let is_success = {
-                let index1_ok = builder.build_int_compare(
-                    IntPredicate::ULT,
-                    index1_v,
-                    index1_v.get_type().const_int(size, false),
-                    "",
-                )?;
-                let index2_ok = builder.build_int_compare(
-                    IntPredicate::ULT,
-                    index2_v,
-                    index2_v.get_type().const_int(size, false),
-                    "",
-                )?;
-                builder.build_and(index1_ok, index2_ok, "")?
-            };
-            builder.build_conditional_branch(is_success, success_block, failure_block)?;
-            builder.position_at_end(exit_block);
-            Ok(())
+                    Ok(vec![array_v])
+                },
+                vec![array_v.into()],
+            )?;
+            outputs.finish(ctx.builder(), [r])
         }
         ArrayOpDef::pop_left => {
             let [array_v] = inputs
@@ -333,7 +375,9 @@ pub fn emit_array_op<'c, H: HugrView>(
             outputs.finish(ctx.builder(), [r])
         }
         ArrayOpDef::discard_empty => Ok(()),
-        _ => todo!(),
+        _ => Err(EmitErrorKind::UnsupportedOp(format!(
+            "Unsupported ArrayOpDef: {def:?}"
+        )))?,
     }
 }
 
@@ -389,10 +433,13 @@ mod test {
     use hugr::{
         builder::{Dataflow, DataflowSubContainer, SubContainer},
         extension::{
-            prelude::{self, array_type, option_type, ConstUsize, BOOL_T, USIZE_T},
+            prelude::{
+                self, array_type, either_type, option_type, ArrayOpDef, ConstUsize, BOOL_T, USIZE_T,
+            },
+            simple_op::MakeRegisteredOp as _,
             ExtensionRegistry,
         },
-        ops::Value,
+        ops::{DataflowOpTrait as _, Value},
         std_extensions::{
             arithmetic::{
                 int_ops::{self},
@@ -404,17 +451,38 @@ mod test {
     };
     use itertools::Itertools as _;
     use rstest::rstest;
+    use strum::IntoEnumIterator as _;
 
     use crate::{
         check_emission,
         custom::CodegenExtsBuilder,
-        emit::test::SimpleHugrConfig,
+        emit::{
+            test::{Emission, SimpleHugrConfig},
+            EmitError, EmitErrorKind,
+        },
+        extension::prelude::PreludeCodegen,
         test::{exec_ctx, llvm_ctx, TestContext},
         utils::{
-            array_op_builder, ArrayOpBuilder, IntOpBuilder, LogicOpBuilder, UnwrapBuilder as _,
+            array_op_builder, fat::FatExt as _, ArrayOpBuilder, IntOpBuilder, LogicOpBuilder,
+            UnwrapBuilder as _,
         },
     };
 
+    use super::{ArrayBoundsCheck, ArrayRepresentation};
+
+    #[derive(Clone)]
+    struct ArrayPreludeCodegen(ArrayRepresentation, ArrayBoundsCheck);
+
+    impl PreludeCodegen for ArrayPreludeCodegen {
+        fn array_representation(&self) -> ArrayRepresentation {
+            self.0
+        }
+
+        fn array_bounds_check(&self) -> ArrayBoundsCheck {
+            self.1
+        }
+    }
+
     #[rstest]
     fn emit_all_ops(mut llvm_ctx: TestContext) {
         let hugr = SimpleHugrConfig::new()
@@ -444,6 +512,149 @@ mod test {
         check_emission!(hugr, llvm_ctx);
     }
 
+    #[rstest]
+    #[case::checked(ArrayBoundsCheck::Checked, &["icmp ult"], &["Index out of bounds"])]
+    #[case::unchecked(ArrayBoundsCheck::Unchecked, &[], &["icmp ult", "Index out of bounds"])]
+    #[case::panic(
+        ArrayBoundsCheck::Panic,
+        &["icmp ult", "Index out of bounds", "@snprintf", "unreachable"],
+        &[]
+    )]
+    fn emit_bounds_check(
+        mut llvm_ctx: TestContext,
+        #[values(ArrayRepresentation::Value, ArrayRepresentation::Heap)]
+        representation: ArrayRepresentation,
+        #[case] bounds_check: ArrayBoundsCheck,
+        #[case] expected: &[&str],
+        #[case] unexpected: &[&str],
+    ) {
+        let hugr = SimpleHugrConfig::new()
+            .with_extensions(prelude::PRELUDE_REGISTRY.to_owned())
+            .finish(|mut builder| {
+                array_op_builder::test::all_array_ops(builder.dfg_builder_endo([]).unwrap())
+                    .finish_sub_container()
+                    .unwrap();
+                builder.finish_sub_container().unwrap()
+            });
+        let pcg = ArrayPreludeCodegen(representation, bounds_check);
+        llvm_ctx.add_extensions(move |cge| cge.add_prelude_extensions(pcg.clone()));
+        let emission =
+            Emission::emit_hugr(hugr.fat_root().unwrap(), llvm_ctx.get_emit_hugr()).unwrap();
+        emission.verify().unwrap();
+        let module = emission.module().print_to_string().to_string();
+        for s in expected {
+            assert!(module.contains(s), "{s} not in {module}");
+        }
+        for s in unexpected {
+            assert!(!module.contains(s), "{s} in {module}");
+        }
+    }
+
+    #[rstest]
+    fn exec_bounds_check_in_bounds(
+        mut exec_ctx: TestContext,
+        #[values(ArrayRepresentation::Value, ArrayRepresentation::Heap)]
+        representation: ArrayRepresentation,
+        #[values(
+            ArrayBoundsCheck::Checked,
+            ArrayBoundsCheck::Unchecked,
+            ArrayBoundsCheck::Panic
+        )]
+        bounds_check: ArrayBoundsCheck,
+    ) {
+        // We build a HUGR that:
+        // - Creates an array: [1,2]
+        // - Swaps the elements, giving [2,1]
+        // - Sets the element at index 1 to 5, giving [2,5]
+        // - Returns the element at index 1
+        let arr_ty = array_type(2, USIZE_T);
+        let hugr = SimpleHugrConfig::new()
+            .with_outs(USIZE_T)
+            .with_extensions(exec_registry())
+            .finish_with_exts(|mut builder, reg| {
+                let us0 = builder.add_load_value(ConstUsize::new(0));
+                let us1 = builder.add_load_value(ConstUsize::new(1));
+                let us2 = builder.add_load_value(ConstUsize::new(2));
+                let us5 = builder.add_load_value(ConstUsize::new(5));
+                let arr = builder.add_new_array(USIZE_T, [us1, us2]).unwrap();
+                let r = builder.add_array_swap(USIZE_T, 2, arr, us0, us1).unwrap();
+                let [arr] = builder
+                    .build_unwrap_sum(reg, 1, either_type(arr_ty.clone(), arr_ty.clone()), r)
+                    .unwrap();
+                let r = builder.add_array_set(USIZE_T, 2, arr, us1, us5).unwrap();
+                let [_, arr] = {
+                    let row = vec![USIZE_T, arr_ty];
+                    builder
+                        .build_unwrap_sum(reg, 1, either_type(row.clone(), row), r)
+                        .unwrap()
+                };
+                let r = builder.add_array_get(USIZE_T, 2, arr, us1).unwrap();
+                let [elem] = builder
+                    .build_unwrap_sum(reg, 1, option_type(USIZE_T), r)
+                    .unwrap();
+                builder.finish_with_outputs([elem]).unwrap()
+            });
+        let pcg = ArrayPreludeCodegen(representation, bounds_check);
+        exec_ctx.add_extensions(move |cge| cge.add_prelude_extensions(pcg.clone()));
+        assert_eq!(5, exec_ctx.exec_hugr_u64(hugr, "main"));
+    }
+
+    #[rstest]
+    fn emit_every_array_op_def(
+        mut llvm_ctx: TestContext,
+        #[values(ArrayRepresentation::Value, ArrayRepresentation::Heap)]
+        representation: ArrayRepresentation,
+        #[values(
+            ArrayBoundsCheck::Checked,
+            ArrayBoundsCheck::Unchecked,
+            ArrayBoundsCheck::Panic
+        )]
+        bounds_check: ArrayBoundsCheck,
+    ) {
+        // Every ArrayOpDef must either be emitted or fail with an
+        // `UnsupportedOp` error, in particular new variants must not panic.
+        let pcg = ArrayPreludeCodegen(representation, bounds_check);
+        llvm_ctx.add_extensions(move |cge| cge.add_prelude_extensions(pcg.clone()));
+        for def in ArrayOpDef::iter() {
+            let size = if def == ArrayOpDef::discard_empty {
+                0
+            } else {
+                2
+            };
+            let op = def.to_concrete(USIZE_T, size);
+            let sig = op.clone().to_extension_op().unwrap().signature();
+            let hugr = SimpleHugrConfig::new()
+                .with_ins(sig.input().clone())
+                .with_outs(sig.output().clone())
+                .with_extensions(prelude::PRELUDE_REGISTRY.to_owned())
+                .finish(|mut builder| {
+                    let inputs = builder.input_wires();
+                    let outputs = builder.add_dataflow_op(op, inputs).unwrap().outputs();
+                    builder.finish_with_outputs(outputs).unwrap()
+                });
+            let result = Emission::emit_hugr(hugr.fat_root().unwrap(), llvm_ctx.get_emit_hugr());
+            match def {
+                ArrayOpDef::new_array
+                | ArrayOpDef::get
+                | ArrayOpDef::set
+                | ArrayOpDef::swap
+                | ArrayOpDef::pop_left
+                | ArrayOpDef::pop_right
+                | ArrayOpDef::discard_empty => result.unwrap().verify().unwrap(),
+                _ => match result
+                    .map(|_| ())
+                    .map_err(|err| err.downcast::<EmitError>())
+                {
+                    Err(Ok(EmitError {
+                        kind: EmitErrorKind::UnsupportedOp(_),
+                        ..
+                    })) => (),
+                    result => panic!("Expected an unsupported op error for {def:?}: {result:?}"),
+                },
+            }
+        }
+    }
+
     fn exec_registry() -> ExtensionRegistry {
         ExtensionRegistry::try_new([
             int_types::EXTENSION.to_owned(),
@@ -518,7 +729,6 @@ mod test {
         //   - The element returned from set is `expected_elem`
         //   - The Oth element of the resulting array is `expected_arr_0`
 
-        use hugr::extension::prelude::either_type;
         let int_ty = int_type(3);
         let hugr = SimpleHugrConfig::new()
             .with_outs(USIZE_T)
@@ -0,0 +1,661 @@
+//! Codegen for prelude array operations, where arrays are represented as
+//! pointers to heap buffers.
+//!
+//! This lowering is selected by returning [ArrayRepresentation::Heap] from
+//! [PreludeCodegen::array_representation]. An `array<N, T>` is then lowered to
+//! a `T*` pointing to a buffer of `N` elements allocated with `malloc`. Unlike
+//! [super::array], element accesses do not copy the array, so code size and
+//! stack use do not grow with `N`.
+//!
+//! Copying a HUGR value does not emit any code, so copies of an array share
+//! its buffer. An op may therefore only mutate or free the buffer of an input
+//! array which it owns, i.e. which is not referenced by any other value:
+//! - Arrays of linear elements cannot be copied, so are always owned.
+//! - An array of copyable elements is owned if it can be traced back to the
+//!   array op which created its buffer, as described in
+//!   [crate::utils::ownership].
+//!
+//! `set`, `swap`, `pop_left` and `pop_right` mutate the buffer of an owned
+//! input array in place and output it, and otherwise copy the buffer first.
+//! An owned buffer is freed after its last use by `get`, by `discard_empty`,
+//! or by `pop_left` and `pop_right` on an empty array. Buffers of arrays
+//! which are not owned by such an op are leaked.
+//!
+//! [ArrayRepresentation::Heap]: super::array::ArrayRepresentation::Heap
+use anyhow::{anyhow, Result};
+use hugr::{
+    extension::{
+        prelude::{array_type, option_type, ArrayOp, ArrayOpDef},
+        simple_op::{MakeExtensionOp as _, MakeRegisteredOp as _},
+    },
+    ops::{DataflowOpTrait as _, ExtensionOp, OpType},
+    HugrView,
+};
+use inkwell::{
+    builder::Builder,
+    types::{BasicType, BasicTypeEnum, PointerType},
+    values::{BasicValueEnum, IntValue, PointerValue},
+    AddressSpace,
+};
+
+use crate::{
+    emit::{
+        libc::{emit_libc_free, emit_libc_malloc},
+        EmitErrorKind, EmitFuncContext, EmitOpArgs,
+    },
+    utils::{fat::FatNode, ownership::is_owned_wire},
+};
+
+use super::{array::emit_bounds_checked, PreludeCodegen};
+
+/// Returns the llvm type of a heap allocated array with elements of type
+/// `elem_ty`.
+pub fn heap_array_type(elem_ty: BasicTypeEnum<'_>) -> PointerType<'_> {
+    elem_ty.ptr_type(AddressSpace::default())
+}
+
+/// Emits a computation of the size in bytes of `len` elements of type
+/// `elem_ty`.
+fn build_num_bytes<'c>(
+    builder: &Builder<'c>,
+    elem_ty: BasicTypeEnum<'c>,
+    len: u64,
+) -> Result<IntValue<'c>> {
+    let elem_size = elem_ty
+        .size_of()
+        .ok_or(anyhow!("Array element type is unsized"))?;
+    Ok(builder.build_int_mul(elem_size, elem_size.get_type().const_int(len, false), "")?)
+}
+
+/// Emits a call to `malloc` allocating a buffer for `size` elements of type
+/// `elem_ty`, returning a pointer to the first element.
+fn emit_heap_array_alloc<'c, H: HugrView>(
+    ctx: &mut EmitFuncContext<'c, '_, H>,
+    elem_ty: BasicTypeEnum<'c>,
+    size: u64,
+) -> Result<PointerValue<'c>> {
+    let num_bytes = build_num_bytes(ctx.builder(), elem_ty, size)?;
+    let ptr = emit_libc_malloc(ctx, num_bytes)?;
+    Ok(ctx
+        .builder()
+        .build_pointer_cast(ptr, heap_array_type(elem_ty), "")?)
+}
+
+/// Emits a copy of the first `len` elements of the array `array_ptr`, starting
+/// at `offset`, into a new buffer, returning a pointer to its first element.
+fn emit_heap_array_copy<'c, H: HugrView>(
+    ctx: &mut EmitFuncContext<'c, '_, H>,
+    elem_ty: BasicTypeEnum<'c>,
+    array_ptr: PointerValue<'c>,
+    offset: u64,
+    len: u64,
+) -> Result<PointerValue<'c>> {
+    let new_ptr = emit_heap_array_alloc(ctx, elem_ty, len)?;
+    let offset = ctx.iw_context().i64_type().const_int(offset, false);
+    let builder = ctx.builder();
+    let src_ptr = build_elem_ptr(builder, array_ptr, offset)?;
+    let num_bytes = build_num_bytes(builder, elem_ty, len)?;
+    builder
+        .build_memcpy(new_ptr, 1, src_ptr, 1, num_bytes)
+        .map_err(|e| anyhow!("Failed to build memcpy: {e}"))?;
+    Ok(new_ptr)
+}
+
+/// Returns a pointer to the element at `index` of the array `array_ptr`.
+///
+/// `index` must be in bounds.
+fn build_elem_ptr<'c>(
+    builder: &Builder<'c>,
+    array_ptr: PointerValue<'c>,
+    index: IntValue<'c>,
+) -> Result<PointerValue<'c>> {
+    Ok(unsafe { builder.build_in_bounds_gep(array_ptr, &[index], "")? })
+}
+
+/// Returns true if the array at input `port` of `node` is owned by `node`, as
+/// described in the [module-level documentation](self).
+fn owns_array_input<H: HugrView>(
+    node: FatNode<'_, ExtensionOp, H>,
+    op: &ArrayOp,
+    port: usize,
+) -> bool {
+    let hugr = node.hugr();
+    !op.elem_ty.copyable()
+        || is_owned_wire(hugr, node.node(), port.into(), None, &|node, _, field| {
+            owns_array_output(hugr.get_optype(node), field)
+        })
+}
+
+/// Returns true if the output of `op` is always an array in a buffer which it
+/// allocated or owns, or a sum containing one at `field`.
+fn owns_array_output(op: &OpType, field: Option<(usize, usize)>) -> bool {
+    let Some(ArrayOp { def, .. }) = op
+        .as_extension_op()
+        .and_then(|ext_op| ArrayOp::from_extension_op(ext_op).ok())
+    else {
+        return false;
+    };
+    matches!(
+        (def, field),
+        (ArrayOpDef::new_array, None)
+            | (ArrayOpDef::set, Some((_, 1)))
+            | (ArrayOpDef::swap, Some((_, 0)))
+            | (ArrayOpDef::pop_left | ArrayOpDef::pop_right, Some((1, 1)))
+    )
+}
+
+/// Emits a [ArrayOp] on heap allocated arrays, as described in the
+/// [module-level documentation](self).
+pub fn emit_heap_array_op<'c, H: HugrView>(
+    pcg: &impl PreludeCodegen,
+    ctx: &mut EmitFuncContext<'c, '_, H>,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+) -> Result<()> {
+    let node = args.node();
+    let op = ArrayOp::from_extension_op(node.as_ref())?;
+    let sig = op
+        .clone()
+        .to_extension_op()
+        .ok_or(anyhow!("Failed to build ExtensionOp for {:?}", op.def))?
+        .signature();
+    let owns_array = owns_array_input(node, &op, 0);
+    let ArrayOp {
+        def,
+        ref elem_ty,
+        size,
+    } = op;
+    let EmitOpArgs {
+        inputs, outputs, ..
+    } = args;
+    let llvm_elem_ty = ctx.llvm_type(elem_ty)?;
+    let res_hugr_ty = || {
+        sig.output()
+            .get(0)
+            .ok_or(anyhow!("ArrayOp::{def:?} has no outputs"))
+    };
+    // Returns a pointer to a buffer holding the elements of the input array,
+    // which `def` may mutate.
+    let owned_array_ptr = |ctx: &mut EmitFuncContext<'c, '_, H>, array_v: BasicValueEnum<'c>| {
+        let array_ptr = array_v.into_pointer_value();
+        if owns_array {
+            Ok(array_ptr)
+        } else {
+            emit_heap_array_copy(ctx, llvm_elem_ty, array_ptr, 0, size)
+        }
+    };
+    match def {
+        ArrayOpDef::new_array => {
+            let array_ptr = emit_heap_array_alloc(ctx, llvm_elem_ty, size)?;
+            let builder = ctx.builder();
+            let usize_ty = pcg.usize_type(&ctx.typing_session());
+            for (i, v) in inputs.into_iter().enumerate() {
+                let elem_ptr =
+                    build_elem_ptr(builder, array_ptr, usize_ty.const_int(i as u64, false))?;
+                builder.build_store(elem_ptr, v)?;
+            }
+            outputs.finish(builder, [array_ptr.into()])
+        }
+        ArrayOpDef::get => {
+            let [array_v, index_v] = inputs
+                .try_into()
+                .map_err(|_| anyhow!("ArrayOpDef::get expects two arguments"))?;
+            let array_ptr = array_v.into_pointer_value();
+            let index_v = index_v.into_int_value();
+            let r = emit_bounds_checked(
+                pcg,
+                ctx,
+                res_hugr_ty()?,
+                size,
+                &[index_v],
+                |builder| {
+                    let elem_ptr = build_elem_ptr(builder, array_ptr, index_v)?;
+                    Ok(vec![builder.build_load(elem_ptr, "")?])
+                },
+                vec![],
+            )?;
+            if owns_array {
+                emit_libc_free(ctx, array_ptr)?;
+            }
+            outputs.finish(ctx.builder(), [r])
+        }
+        ArrayOpDef::set => {
+            let [array_v, index_v, value_v] = inputs
+                .try_into()
+                .map_err(|_| anyhow!("ArrayOpDef::set expects three arguments"))?;
+            let array_ptr = owned_array_ptr(ctx, array_v)?;
+            let array_v = array_ptr.into();
+            let index_v = index_v.into_int_value();
+            let r = emit_bounds_checked(
+                pcg,
+                ctx,
+                res_hugr_ty()?,
+                size,
+                &[index_v],
+                |builder| {
+                    let elem_ptr = build_elem_ptr(builder, array_ptr, index_v)?;
+                    let elem_v = builder.build_load(elem_ptr, "")?;
+                    builder.build_store(elem_ptr, value_v)?;
+                    Ok(vec![elem_v, array_v])
+                },
+                vec![value_v, array_v],
+            )?;
+            outputs.finish(ctx.builder(), [r])
+        }
+        ArrayOpDef::swap => {
+            let [array_v, index1_v, index2_v] = inputs
+                .try_into()
+                .map_err(|_| anyhow!("ArrayOpDef::swap expects three arguments"))?;
+            let array_ptr = owned_array_ptr(ctx, array_v)?;
+            let array_v = array_ptr.into();
+            let index1_v = index1_v.into_int_value();
+            let index2_v = index2_v.into_int_value();
+            let r = emit_bounds_checked(
+                pcg,
+                ctx,
+                res_hugr_ty()?,
+                size,
+                &[index1_v, index2_v],
+                |builder| {
+                    let elem1_ptr = build_elem_ptr(builder, array_ptr, index1_v)?;
+                    let elem2_ptr = build_elem_ptr(builder, array_ptr, index2_v)?;
+                    let elem1_v = builder.build_load(elem1_ptr, "")?;
+                    let elem2_v = builder.build_load(elem2_ptr, "")?;
+                    builder.build_store(elem1_ptr, elem2_v)?;
+                    builder.build_store(elem2_ptr, elem1_v)?;
+                    Ok(vec![array_v])
+                },
+                vec![array_v],
+            )?;
+            outputs.finish(ctx.builder(), [r])
+        }
+        ArrayOpDef::pop_left | ArrayOpDef::pop_right => {
+            let [array_v] = inputs
+                .try_into()
+                .map_err(|_| anyhow!("ArrayOpDef::{def:?} expects one argument"))?;
+            let array_ptr = array_v.into_pointer_value();
+            let ret_ty = ctx.llvm_sum_type(option_type(vec![
+                elem_ty.clone(),
+                array_type(size.saturating_sub(1), elem_ty.clone()),
+            ]))?;
+            if size == 0 {
+                if owns_array {
+                    emit_libc_free(ctx, array_ptr)?;
+                }
+                let r = ret_ty.build_tag(ctx.builder(), 0, vec![])?;
+                return outputs.finish(ctx.builder(), [r]);
+            }
+            let usize_ty = pcg.usize_type(&ctx.typing_session());
+            let (elem_ptr, rest_offset) = if def == ArrayOpDef::pop_left {
+                (array_ptr, 1)
+            } else {
+                let last = usize_ty.const_int(size - 1, false);
+                (build_elem_ptr(ctx.builder(), array_ptr, last)?, 0)
+            };
+            let elem_v = ctx.builder().build_load(elem_ptr, "")?;
+            let rest_ptr = if !owns_array {
+                emit_heap_array_copy(ctx, llvm_elem_ty, array_ptr, rest_offset, size - 1)?
+            } else if def == ArrayOpDef::pop_left {
+                // Shift the remaining elements down, so that the buffer can
+                // still be freed through the returned pointer.
+                let builder = ctx.builder();
+                let rest_ptr = build_elem_ptr(builder, array_ptr, usize_ty.const_int(1, false))?;
+                let num_bytes = build_num_bytes(builder, llvm_elem_ty, size - 1)?;
+                builder
+                    .build_memmove(array_ptr, 1, rest_ptr, 1, num_bytes)
+                    .map_err(|e| anyhow!("Failed to build memmove: {e}"))?;
+                array_ptr
+            } else {
+                array_ptr
+            };
+            let builder = ctx.builder();
+            let r = ret_ty.build_tag(builder, 1, vec![elem_v, rest_ptr.into()])?;
+            outputs.finish(builder, [r])
+        }
+        ArrayOpDef::discard_empty => {
+            let [array_v] = inputs
+                .try_into()
+                .map_err(|_| anyhow!("ArrayOpDef::discard_empty expects one argument"))?;
+            if owns_array {
+                emit_libc_free(ctx, array_v.into_pointer_value())?;
+            }
+            outputs.finish(ctx.builder(), [])
+        }
+        _ => Err(EmitErrorKind::UnsupportedOp(format!(
+            "Unsupported ArrayOpDef: {def:?}"
+        )))?,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::{
+        builder::{Dataflow, DataflowSubContainer, SubContainer},
+        extension::{
+            prelude::{self, array_type, option_type, ConstUsize},
+            ExtensionRegistry,
+        },
+        std_extensions::arithmetic::{
+            int_ops,
+            int_types::{self, int_type, ConstInt},
+        },
+        type_row,
+        types::Type,
+        Hugr, Wire,
+    };
+    use itertools::Itertools as _;
+    use rstest::rstest;
+
+    use crate::{
+        emit::test::{Emission, SimpleHugrConfig},
+        extension::prelude::array::ArrayRepresentation,
+        test::{exec_ctx, llvm_ctx, TestContext},
+        utils::{
+            array_op_builder, fat::FatExt as _, ArrayOpBuilder, IntOpBuilder, UnwrapBuilder as _,
+        },
+    };
+
+    use super::PreludeCodegen;
+
+    #[derive(Clone)]
+    struct HeapPreludeCodegen;
+
+    impl PreludeCodegen for HeapPreludeCodegen {
+        fn array_representation(&self) -> ArrayRepresentation {
+            ArrayRepresentation::Heap
+        }
+    }
+
+    fn exec_registry() -> ExtensionRegistry {
+        ExtensionRegistry::try_new([
+            int_types::EXTENSION.to_owned(),
+            int_ops::EXTENSION.to_owned(),
+            prelude::PRELUDE.to_owned(),
+        ])
+        .unwrap()
+    }
+
+    /// Builds an array of the 64 bit integers `values`.
+    fn build_int_array(builder: &mut impl Dataflow, values: &[u64]) -> Wire {
+        let elems = values
+            .iter()
+            .map(|&v| builder.add_load_value(ConstInt::new_u(6, v).unwrap()))
+            .collect_vec();
+        builder.add_new_array(int_type(6), elems).unwrap()
+    }
+
+    /// Unwraps a sum of two variants both containing `row`.
+    fn build_merge_either(builder: &mut impl Dataflow, row: Vec<Type>, either: Wire) -> Vec<Wire> {
+        let mut conditional = builder
+            .conditional_builder(
+                ([row.clone().into(), row.clone().into()], either),
+                [],
+                row.into(),
+            )
+            .unwrap();
+        for i in 0..2 {
+            let case = conditional.case_builder(i).unwrap();
+            let inputs = case.input_wires();
+            case.finish_with_outputs(inputs).unwrap();
+        }
+        conditional
+            .finish_sub_container()
+            .unwrap()
+            .outputs()
+            .collect()
+    }
+
+    /// Builds a HUGR which swaps and sets elements of an array, see
+    /// [exec_swap_set].
+    fn swap_set_hugr(index1: u64, index2: u64, set_index: u64) -> Hugr {
+        // We build a HUGR that:
+        // - Creates an array: [1,2,4]
+        // - Swaps the elements at `index1` and `index2`
+        // - Sets the element at `set_index` to 8
+        // - Pops all elements, returning the hex digits of the element
+        //   returned by set followed by the elements of the array
+        let int_ty = int_type(6);
+        let arr_ty = array_type(3, int_ty.clone());
+        SimpleHugrConfig::new()
+            .with_outs(int_ty.clone())
+            .with_extensions(exec_registry())
+            .finish_with_exts(|mut builder, reg| {
+                let arr = build_int_array(&mut builder, &[1, 2, 4]);
+                let index1 = builder.add_load_value(ConstUsize::new(index1));
+                let index2 = builder.add_load_value(ConstUsize::new(index2));
+                let r = builder
+                    .add_array_swap(int_ty.clone(), 3, arr, index1, index2)
+                    .unwrap();
+                let [arr] = build_merge_either(&mut builder, vec![arr_ty.clone()], r)
+                    .try_into()
+                    .unwrap();
+                let set_index = builder.add_load_value(ConstUsize::new(set_index));
+                let value = builder.add_load_value(ConstInt::new_u(6, 8).unwrap());
+                let r = builder
+                    .add_array_set(int_ty.clone(), 3, arr, set_index, value)
+                    .unwrap();
+                let [mut acc, mut arr] =
+                    build_merge_either(&mut builder, vec![int_ty.clone(), arr_ty], r)
+                        .try_into()
+                        .unwrap();
+                for size in (1..=3).rev() {
+                    for _ in 0..4 {
+                        acc = builder.add_iadd(6, acc, acc).unwrap();
+                    }
+                    let r = builder
+                        .add_array_pop_left(int_ty.clone(), size, arr)
+                        .unwrap();
+                    let [elem, new_arr] = builder
+                        .build_unwrap_sum(
+                            reg,
+                            1,
+                            option_type(vec![int_ty.clone(), array_type(size - 1, int_ty.clone())]),
+                            r,
+                        )
+                        .unwrap();
+                    acc = builder.add_iadd(6, acc, elem).unwrap();
+                    arr = new_arr;
+                }
+                builder.add_array_discard_empty(int_ty, arr).unwrap();
+                builder.finish_with_outputs([acc]).unwrap()
+            })
+    }
+
+    #[rstest]
+    fn emit_all_ops(mut llvm_ctx: TestContext) {
+        let hugr = SimpleHugrConfig::new()
+            .with_extensions(prelude::PRELUDE_REGISTRY.to_owned())
+            .finish(|mut builder| {
+                array_op_builder::test::all_array_ops(builder.dfg_builder_endo([]).unwrap())
+                    .finish_sub_container()
+                    .unwrap();
+                builder.finish_sub_container().unwrap()
+            });
+        llvm_ctx.add_extensions(|cge| cge.add_prelude_extensions(HeapPreludeCodegen));
+        let emission =
+            Emission::emit_hugr(hugr.fat_root().unwrap(), llvm_ctx.get_emit_hugr()).unwrap();
+        emission.verify().unwrap();
+        let module = emission.module().print_to_string().to_string();
+        for expected in ["@malloc", "@free", "@llvm.memmove"] {
+            assert!(module.contains(expected), "{expected} not in {module}");
+        }
+        // No llvm array values are created.
+        assert!(!module.contains(" x i64]"), "array value in {module}");
+    }
+
+    #[rstest]
+    fn emit_owned_arrays_in_place(mut llvm_ctx: TestContext) {
+        // Every array op in this HUGR owns its input array, so the only
+        // allocation is that of `new_array`, which is freed by
+        // `discard_empty`.
+        let hugr = swap_set_hugr(0, 1, 2);
+        llvm_ctx.add_extensions(|cge| {
+            cge.add_prelude_extensions(HeapPreludeCodegen)
+                .add_int_extensions()
+        });
+        let emission =
+            Emission::emit_hugr(hugr.fat_root().unwrap(), llvm_ctx.get_emit_hugr()).unwrap();
+        emission.verify().unwrap();
+        let module = emission.module().print_to_string().to_string();
+        assert_eq!(1, module.matches("call i8* @malloc").count(), "{module}");
+        assert_eq!(1, module.matches("call void @free").count(), "{module}");
+    }
+
+    #[rstest]
+    #[case(0, 1)]
+    #[case(2, 4)]
+    #[case(3, 0)]
+    #[case(999999, 0)]
+    fn exec_get(mut exec_ctx: TestContext, #[case] index: u64, #[case] expected: u64) {
+        // We build a HUGR that:
+        // - Creates an array of [1,2,4]
+        // - Gets the element at the given index
+        // - Returns the element if the index is in bounds, otherwise 0
+        let int_ty = int_type(6);
+        let hugr = SimpleHugrConfig::new()
+            .with_outs(int_ty.clone())
+            .with_extensions(exec_registry())
+            .finish(|mut builder| {
+                let arr = build_int_array(&mut builder, &[1, 2, 4]);
+                let zero = builder.add_load_value(ConstInt::new_u(6, 0).unwrap());
+                let i = builder.add_load_value(ConstUsize::new(index));
+                let get_r = builder.add_array_get(int_ty.clone(), 3, arr, i).unwrap();
+                let r = {
+                    let mut conditional = builder
+                        .conditional_builder(
+                            ([type_row![], vec![int_ty.clone()].into()], get_r),
+                            [],
+                            vec![int_ty].into(),
+                        )
+                        .unwrap();
+                    conditional
+                        .case_builder(0)
+                        .unwrap()
+                        .finish_with_outputs([zero])
+                        .unwrap();
+                    let case = conditional.case_builder(1).unwrap();
+                    let inputs = case.input_wires();
+                    case.finish_with_outputs(inputs).unwrap();
+                    conditional.finish_sub_container().unwrap().out_wire(0)
+                };
+                builder.finish_with_outputs([r]).unwrap()
+            });
+        exec_ctx.add_extensions(|cge| {
+            cge.add_prelude_extensions(HeapPreludeCodegen)
+                .add_int_extensions()
+        });
+        assert_eq!(expected, exec_ctx.exec_hugr_u64(hugr, "main"));
+    }
+
+    #[rstest]
+    #[case(0, 1)]
+    #[case(1, 2)]
+    fn exec_copy(mut exec_ctx: TestContext, #[case] index: u64, #[case] expected: u64) {
+        // We build a HUGR that:
+        // - Creates an array: [1,2,4]
+        // - Copies it, setting the element at `index` of one copy to 8
+        // - Then gets the element at `index` of the other copy
+        // - Returns it if `index` is in bounds, otherwise 0
+        let int_ty = int_type(6);
+        let hugr = SimpleHugrConfig::new()
+            .with_outs(int_ty.clone())
+            .with_extensions(exec_registry())
+            .finish_with_exts(|mut builder, reg| {
+                let arr = build_int_array(&mut builder, &[1, 2, 4]);
+                let i = builder.add_load_value(ConstUsize::new(index));
+                let value = builder.add_load_value(ConstInt::new_u(6, 8).unwrap());
+                let set_r = builder
+                    .add_array_set(int_ty.clone(), 3, arr, i, value)
+                    .unwrap();
+                let get_r = builder.add_array_get(int_ty.clone(), 3, arr, i).unwrap();
+                builder.set_order(&set_r.node(), &get_r.node());
+                let [r] = builder
+                    .build_unwrap_sum(reg, 1, option_type(int_ty.clone()), get_r)
+                    .unwrap();
+                builder.finish_with_outputs([r]).unwrap()
+            });
+        exec_ctx.add_extensions(|cge| {
+            cge.add_prelude_extensions(HeapPreludeCodegen)
+                .add_int_extensions()
+        });
+        assert_eq!(expected, exec_ctx.exec_hugr_u64(hugr, "main"));
+    }
+
+    #[rstest]
+    #[case(0, 1, 2, 0x4218)]
+    #[case(0, 2, 0, 0x4821)]
+    #[case(1, 1, 1, 0x2184)]
+    #[case(0, 3, 3, 0x8124)]
+    #[case(999999, 0, 1, 0x2184)]
+    fn exec_swap_set(
+        mut exec_ctx: TestContext,
+        #[case] index1: u64,
+        #[case] index2: u64,
+        #[case] set_index: u64,
+        #[case] expected: u64,
+    ) {
+        let hugr = swap_set_hugr(index1, index2, set_index);
+        exec_ctx.add_extensions(|cge| {
+            cge.add_prelude_extensions(HeapPreludeCodegen)
+                .add_int_extensions()
+        });
+        assert_eq!(expected, exec_ctx.exec_hugr_u64(hugr, "main"));
+    }
+
+    #[rstest]
+    #[case(true, 0, 0)]
+    #[case(true, 1, 1)]
+    #[case(true, 2, 3)]
+    #[case(true, 3, 7)]
+    #[case(false, 0, 0)]
+    #[case(false, 1, 4)]
+    #[case(false, 2, 6)]
+    #[case(false, 3, 7)]
+    fn exec_pop(
+        mut exec_ctx: TestContext,
+        #[case] from_left: bool,
+        #[case] num: u64,
+        #[case] expected: u64,
+    ) {
+        // We build a HUGR that:
+        // - Creates an array: [1,2,4]
+        // - Pops `num` elements from the left or right
+        // - Discards the array if it is empty
+        // - Returns the sum of the popped elements
+        let int_ty = int_type(6);
+        let hugr = SimpleHugrConfig::new()
+            .with_outs(int_ty.clone())
+            .with_extensions(exec_registry())
+            .finish_with_exts(|mut builder, reg| {
+                let mut r = builder.add_load_value(ConstInt::new_u(6, 0).unwrap());
+                let mut arr = build_int_array(&mut builder, &[1, 2, 4]);
+                for i in 0..num {
+                    let size = 3 - i;
+                    let pop_res = if from_left {
+                        builder.add_array_pop_left(int_ty.clone(), size, arr)
+                    } else {
+                        builder.add_array_pop_right(int_ty.clone(), size, arr)
+                    }
+                    .unwrap();
+                    let [elem, new_arr] = builder
+                        .build_unwrap_sum(
+                            reg,
+                            1,
+                            option_type(vec![int_ty.clone(), array_type(size - 1, int_ty.clone())]),
+                            pop_res,
+                        )
+                        .unwrap();
+                    arr = new_arr;
+                    r = builder.add_iadd(6, r, elem).unwrap();
+                }
+                if num == 3 {
+                    builder.add_array_discard_empty(int_ty, arr).unwrap();
+                }
+                builder.finish_with_outputs([r]).unwrap()
+            });
+        exec_ctx.add_extensions(|cge| {
+            cge.add_prelude_extensions(HeapPreludeCodegen)
+                .add_int_extensions()
+        });
+        assert_eq!(expected, exec_ctx.exec_hugr_u64(hugr, "main"));
+    }
+}
@@ -0,0 +1,746 @@
+//! Codegen for the `tket2.quantum` extension, lowering [Tk2Op]s to calls to
+//! the functions of the [QIR](https://github.com/qir-alliance/qir-spec)
+//! quantum instruction set.
+//!
+//! Qubits must be lowered to the QIR type `%Qubit*`, see [qir_qubit_type].
+//! This is done by [QirPreludeCodegen], which should be used in place of
+//! [super::DefaultPreludeCodegen]. Angles must be lowered to `double`s
+//! counting half-turns, as done by [super::rotation::RotationCodegenExtension].
+//! They are converted to radians before being passed to QIR.
+//!
+//! Ops are lowered as follows, where `__qis__` abbreviates
+//! `__quantum__qis__` and `__rt__` abbreviates `__quantum__rt__`:
+//!
+//! | Op                           | QIR                                                 |
+//! |------------------------------|-----------------------------------------------------|
+//! | `H`, `X`, `Y`, `Z`, `S`, `T` | `__qis__h__body`, `__qis__x__body`, etc.            |
+//! | `Sdg`, `Tdg`                 | `__qis__s__adj`, `__qis__t__adj`                    |
+//! | `Rx`, `Ry`, `Rz`             | `__qis__rx__body`, etc.                             |
+//! | `CX`, `CZ`                   | `__qis__cnot__body`, `__qis__cz__body`              |
+//! | `CY`                         | `__qis__cnot__body` conjugated by `S`               |
+//! | `CRz`                        | two `__qis__cnot__body`s and two `__qis__rz__body`s |
+//! | `Toffoli`                    | `__qis__ccx__body`                                  |
+//! | `Reset`                      | `__qis__reset__body`                                |
+//! | `Measure`                    | `__qis__m__body` then `__qis__read_result__body`    |
+//! | `QAlloc`, `QFree`            | `__rt__qubit_allocate`, `__rt__qubit_release`       |
+//!
+//! Under the QIR base profile, see
+//! [crate::emit::EmitHugr::with_qir_base_profile], qubits are not allocated
+//! or released at runtime, but `QAlloc` returns the next static qubit
+//! address. Measurements are emitted as `__qis__mz__body` into the next
+//! static result address, and since results can't be read, the bools they
+//! output must be unused.
+use std::f64::consts::PI;
+
+use anyhow::{anyhow, ensure, Result};
+use hugr::{extension::prelude::QB_T, ops::ExtensionOp, types::SumType, HugrView, OutgoingPort};
+use inkwell::{
+    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum},
+    values::{BasicMetadataValueEnum, BasicValue as _, BasicValueEnum, FloatValue},
+};
+use tket2::Tk2Op;
+
+use crate::{
+    custom::CodegenExtsBuilder,
+    emit::{EmitErrorKind, EmitFuncContext, EmitOpArgs},
+    types::TypingSession,
+    utils::fat::FatNode,
+    CodegenExtension,
+};
+
+pub use crate::emit::qir::{qir_qubit_type, qir_result_type};
+
+use super::PreludeCodegen;
+
+/// An implementation of [PreludeCodegen] which lowers
+/// [hugr::extension::prelude::QB_T] to [qir_qubit_type], as required by
+/// [QuantumCodegenExtension]. All other methods use their default
+/// implementations.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QirPreludeCodegen;
+
+impl PreludeCodegen for QirPreludeCodegen {
+    fn qubit_type<'c>(&self, session: &TypingSession<'c, '_>) -> impl BasicType<'c> {
+        qir_qubit_type(session.iw_context())
+    }
+}
+
+/// Emits a call to the external function `name`, whose parameter types are
+/// those of `args`, and which returns `ret` if it is not `None`.
+fn emit_qir_call<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    name: &str,
+    args: &[BasicValueEnum<'c>],
+    ret: Option<BasicTypeEnum<'c>>,
+) -> Result<Option<BasicValueEnum<'c>>> {
+    let param_tys = args
+        .iter()
+        .map(|arg| arg.get_type().into())
+        .collect::<Vec<BasicMetadataTypeEnum>>();
+    let fn_ty = match ret {
+        Some(ret) => ret.fn_type(&param_tys, false),
+        None => context.iw_context().void_type().fn_type(&param_tys, false),
+    };
+    let func = context.get_extern_func(name, fn_ty)?;
+    let args = args
+        .iter()
+        .map(|&arg| arg.into())
+        .collect::<Vec<BasicMetadataValueEnum>>();
+    Ok(context
+        .builder()
+        .build_call(func, &args, "")?
+        .try_as_basic_value()
+        .left())
+}
+
+/// Emits a call to the QIR gate `__quantum__qis__{gate}`, which takes `qubits`.
+fn emit_qis_gate<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    gate: &str,
+    qubits: &[BasicValueEnum<'c>],
+) -> Result<()> {
+    emit_qir_call(context, &format!("__quantum__qis__{gate}"), qubits, None)?;
+    Ok(())
+}
+
+/// Emits a call to the QIR rotation `__quantum__qis__{gate}__body`, by an
+/// angle of `radians` of `qubit`.
+fn emit_qis_rotation<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    gate: &str,
+    radians: FloatValue<'c>,
+    qubit: BasicValueEnum<'c>,
+) -> Result<()> {
+    emit_qis_gate(context, &format!("{gate}__body"), &[radians.into(), qubit])
+}
+
+/// Converts `half_turns`, an angle lowered by
+/// [super::rotation::RotationCodegenExtension], to radians.
+fn emit_radians<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    half_turns: BasicValueEnum<'c>,
+) -> Result<FloatValue<'c>> {
+    let half_turns = FloatValue::try_from(half_turns)
+        .map_err(|_| anyhow!("Expected angle to be lowered to a float"))?;
+    let pi = half_turns.get_type().const_float(PI);
+    Ok(context.builder().build_float_mul(half_turns, pi, "")?)
+}
+
+/// Emits a measurement of `qubit` by the `Measure` op `node`, returning the
+/// result as a HUGR bool.
+fn emit_measure<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    node: FatNode<'_, ExtensionOp, H>,
+    qubit: BasicValueEnum<'c>,
+) -> Result<BasicValueEnum<'c>> {
+    let iw_context = context.iw_context();
+    if let Some(profile) = context.qir_base_profile() {
+        if node
+            .hugr()
+            .linked_inputs(node.node(), OutgoingPort::from(1))
+            .next()
+            .is_some()
+        {
+            Err(EmitErrorKind::QirBaseProfile(
+                "The results of measurements can't be used, they are only recorded as output"
+                    .into(),
+            ))?
+        }
+        let result = profile.allocate_result(iw_context);
+        emit_qir_call(
+            context,
+            "__quantum__qis__mz__body",
+            &[qubit, result.into()],
+            None,
+        )?;
+        let bool_ty = context.llvm_sum_type(SumType::new_unary(2))?;
+        return Ok(bool_ty.get_poison().as_basic_value_enum());
+    }
+    let result = emit_qir_call(
+        context,
+        "__quantum__qis__m__body",
+        &[qubit],
+        Some(qir_result_type(iw_context).into()),
+    )?
+    .ok_or(anyhow!("__quantum__qis__m__body returned void"))?;
+    let bit = emit_qir_call(
+        context,
+        "__quantum__qis__read_result__body",
+        &[result],
+        Some(iw_context.bool_type().into()),
+    )?
+    .ok_or(anyhow!("__quantum__qis__read_result__body returned void"))?;
+    emit_bool(context, bit)
+}
+
+/// Converts `bit`, an `i1`, to a HUGR bool.
+fn emit_bool<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    bit: BasicValueEnum<'c>,
+) -> Result<BasicValueEnum<'c>> {
+    let bool_ty = context.llvm_sum_type(SumType::new_unary(2))?;
+    let builder = context.builder();
+    let tag = builder.build_int_z_extend(bit.into_int_value(), bool_ty.get_tag_type(), "")?;
+    bool_ty.build_from_tag(builder, tag)
+}
+
+fn emit_quantum_op<'c, H: HugrView>(
+    context: &mut EmitFuncContext<'c, '_, H>,
+    args: EmitOpArgs<'c, '_, ExtensionOp, H>,
+    op: Tk2Op,
+) -> Result<()> {
+    let qubit_ty = qir_qubit_type(context.iw_context());
+    ensure!(
+        context.llvm_type(&QB_T)? == qubit_ty.as_basic_type_enum(),
+        "QuantumCodegenExtension requires qubits to be lowered to %Qubit*, e.g. by QirPreludeCodegen"
+    );
+    let node = args.node;
+    let profile = context.qir_base_profile();
+    let inputs = args.inputs;
+    let num_inputs = inputs.len();
+    let expect_inputs = |n: usize| -> Result<()> {
+        ensure!(
+            num_inputs == n,
+            "Tk2Op::{op:?} expects {n} arguments, but has {num_inputs}"
+        );
+        Ok(())
+    };
+
+    let outputs = match op {
+        Tk2Op::H
+        | Tk2Op::X
+        | Tk2Op::Y
+        | Tk2Op::Z
+        | Tk2Op::S
+        | Tk2Op::Sdg
+        | Tk2Op::T
+        | Tk2Op::Tdg
+        | Tk2Op::Reset => {
+            expect_inputs(1)?;
+            let gate = match op {
+                Tk2Op::H => "h__body",
+                Tk2Op::X => "x__body",
+                Tk2Op::Y => "y__body",
+                Tk2Op::Z => "z__body",
+                Tk2Op::S => "s__body",
+                Tk2Op::Sdg => "s__adj",
+                Tk2Op::T => "t__body",
+                Tk2Op::Tdg => "t__adj",
+                _ => "reset__body",
+            };
+            emit_qis_gate(context, gate, &inputs)?;
+            inputs
+        }
+        Tk2Op::CX | Tk2Op::CZ | Tk2Op::Toffoli => {
+            let gate = match op {
+                Tk2Op::CX => "cnot__body",
+                Tk2Op::CZ => "cz__body",
+                _ => "ccx__body",
+            };
+            expect_inputs(if op == Tk2Op::Toffoli { 3 } else { 2 })?;
+            emit_qis_gate(context, gate, &inputs)?;
+            inputs
+        }
+        Tk2Op::CY => {
+            expect_inputs(2)?;
+            // CY = (I ⊗ S) CX (I ⊗ S†)
+            emit_qis_gate(context, "s__adj", &inputs[1..])?;
+            emit_qis_gate(context, "cnot__body", &inputs)?;
+            emit_qis_gate(context, "s__body", &inputs[1..])?;
+            inputs
+        }
+        Tk2Op::Rx | Tk2Op::Ry | Tk2Op::Rz => {
+            expect_inputs(2)?;
+            let gate = match op {
+                Tk2Op::Rx => "rx",
+                Tk2Op::Ry => "ry",
+                _ => "rz",
+            };
+            let radians = emit_radians(context, inputs[1])?;
+            emit_qis_rotation(context, gate, radians, inputs[0])?;
+            vec![inputs[0]]
+        }
+        Tk2Op::CRz => {
+            expect_inputs(3)?;
+            // CRz(θ) = CX (I ⊗ Rz(-θ/2)) CX (I ⊗ Rz(θ/2))
+            let radians = emit_radians(context, inputs[2])?;
+            let builder = context.builder();
+            let half = builder.build_float_mul(radians, radians.get_type().const_float(0.5), "")?;
+            let neg_half = builder.build_float_neg(half, "")?;
+            emit_qis_rotation(context, "rz", half, inputs[1])?;
+            emit_qis_gate(context, "cnot__body", &inputs[..2])?;
+            emit_qis_rotation(context, "rz", neg_half, inputs[1])?;
+            emit_qis_gate(context, "cnot__body", &inputs[..2])?;
+            inputs[..2].to_vec()
+        }
+        Tk2Op::Measure => {
+            expect_inputs(1)?;
+            vec![inputs[0], emit_measure(context, node, inputs[0])?]
+        }
+        Tk2Op::QAlloc => {
+            expect_inputs(0)?;
+            let qubit = match profile {
+                Some(profile) => profile.allocate_qubit(context.iw_context()).into(),
+                None => emit_qir_call(
+                    context,
+                    "__quantum__rt__qubit_allocate",
+                    &[],
+                    Some(qubit_ty.into()),
+                )?
+                .ok_or(anyhow!("__quantum__rt__qubit_allocate returned void"))?,
+            };
+            vec![qubit]
+        }
+        Tk2Op::QFree => {
+            expect_inputs(1)?;
+            if profile.is_none() {
+                emit_qir_call(context, "__quantum__rt__qubit_release", &inputs, None)?;
+            }
+            vec![]
+        }
+        op => Err(EmitErrorKind::UnsupportedOp(format!(
+            "Unsupported Tk2Op: {op:?}"
+        )))?,
+    };
+    args.outputs.finish(context.builder(), outputs)
+}
+
+/// A codegen extension for the `tket2.quantum` extension, lowering [Tk2Op]s
+/// to QIR as described in the [module-level documentation](self).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QuantumCodegenExtension;
+
+impl CodegenExtension for QuantumCodegenExtension {
+    fn add_extension<'a, H: HugrView + 'a>(
+        self,
+        builder: CodegenExtsBuilder<'a, H>,
+    ) -> CodegenExtsBuilder<'a, H>
+    where
+        Self: 'a,
+    {
+        builder.simple_extension_op::<Tk2Op>(emit_quantum_op)
+    }
+}
+
+impl<'a, H: HugrView + 'a> CodegenExtsBuilder<'a, H> {
+    /// Add a [QuantumCodegenExtension] to the given [CodegenExtsBuilder].
+    ///
+    /// Note that qubits must be lowered by [QirPreludeCodegen], see
+    /// [CodegenExtsBuilder::add_prelude_extensions].
+    pub fn add_quantum_extensions(self) -> Self {
+        self.add_extension(QuantumCodegenExtension)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use hugr::{
+        builder::{Dataflow, DataflowSubContainer as _, SubContainer as _},
+        extension::{
+            prelude::{ConstUsize, BOOL_T, USIZE_T},
+            simple_op::MakeRegisteredOp as _,
+        },
+        ops::{DataflowOpTrait as _, Tag},
+        type_row, Hugr, Wire,
+    };
+    use rstest::rstest;
+    use tket2::extension::rotation::ConstRotation;
+
+    use crate::{
+        emit::{
+            test::{Emission, SimpleHugrConfig, DFGW},
+            EmitError, MailBoxMode, QirBaseProfileOptions,
+        },
+        test::{
+            exec_ctx, llvm_ctx,
+            qir_rt::{add_qir_rt, qir_rt_funcs, run_qir_sim, QirSimOutput},
+            TestContext,
+        },
+        utils::fat::FatExt as _,
+    };
+
+    use super::*;
+
+    /// Emits a HUGR whose entry point takes the inputs of `op`, applies it,
+    /// and returns its outputs. Returns the emitted module as a string.
+    fn emit_op(llvm_ctx: &TestContext, op: Tk2Op) -> Result<String> {
+        let sig = op.to_extension_op().unwrap().signature();
+        let hugr = SimpleHugrConfig::new()
+            .with_ins(sig.input().clone())
+            .with_outs(sig.output().clone())
+            .with_extensions(tket2::extension::REGISTRY.to_owned())
+            .finish(|mut builder| {
+                let inputs = builder.input_wires();
+                let outputs = builder.add_dataflow_op(op, inputs).unwrap().outputs();
+                builder.finish_with_outputs(outputs).unwrap()
+            });
+        let emission = Emission::emit_hugr(hugr.fat_root().unwrap(), llvm_ctx.get_emit_hugr())?;
+        emission.verify()?;
+        Ok(emission.module().print_to_string().to_string())
+    }
+
+    #[rstest]
+    #[case(Tk2Op::H, &["@__quantum__qis__h__body(%Qubit*"])]
+    #[case(Tk2Op::X, &["@__quantum__qis__x__body(%Qubit*"])]
+    #[case(Tk2Op::Y, &["@__quantum__qis__y__body(%Qubit*"])]
+    #[case(Tk2Op::Z, &["@__quantum__qis__z__body(%Qubit*"])]
+    #[case(Tk2Op::S, &["@__quantum__qis__s__body(%Qubit*"])]
+    #[case(Tk2Op::Sdg, &["@__quantum__qis__s__adj(%Qubit*"])]
+    #[case(Tk2Op::T, &["@__quantum__qis__t__body(%Qubit*"])]
+    #[case(Tk2Op::Tdg, &["@__quantum__qis__t__adj(%Qubit*"])]
+    #[case(Tk2Op::Rx, &["@__quantum__qis__rx__body(double", "fmul double"])]
+    #[case(Tk2Op::Ry, &["@__quantum__qis__ry__body(double"])]
+    #[case(Tk2Op::Rz, &["@__quantum__qis__rz__body(double"])]
+    #[case(Tk2Op::CX, &["@__quantum__qis__cnot__body(%Qubit*"])]
+    #[case(Tk2Op::CY, &["@__quantum__qis__s__adj(", "@__quantum__qis__cnot__body(", "@__quantum__qis__s__body("])]
+    #[case(Tk2Op::CZ, &["@__quantum__qis__cz__body(%Qubit*"])]
+    #[case(Tk2Op::CRz, &["@__quantum__qis__rz__body(double", "@__quantum__qis__cnot__body("])]
+    #[case(Tk2Op::Toffoli, &["@__quantum__qis__ccx__body(%Qubit*"])]
+    #[case(Tk2Op::Reset, &["@__quantum__qis__reset__body(%Qubit*"])]
+    #[case(Tk2Op::Measure, &["@__quantum__qis__m__body(%Qubit*", "i1 @__quantum__qis__read_result__body(%Result*"])]
+    #[case(Tk2Op::QAlloc, &["%Qubit* @__quantum__rt__qubit_allocate()"])]
+    #[case(Tk2Op::QFree, &["@__quantum__rt__qubit_release(%Qubit*"])]
+    fn emit_qir_calls(mut llvm_ctx: TestContext, #[case] op: Tk2Op, #[case] expected: &[&str]) {
+        llvm_ctx.add_extensions(|cge| {
+            cge.add_prelude_extensions(QirPreludeCodegen)
+                .add_default_rotation_extensions()
+                .add_float_extensions()
+                .add_quantum_extensions()
+        });
+        let module = emit_op(&llvm_ctx, op).unwrap();
+        for s in expected {
+            assert!(module.contains(s), "{s} not in {module}");
+        }
+    }
+
+    #[rstest]
+    fn emit_quantum_op_requires_qir_qubits(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| {
+            cge.add_default_prelude_extensions()
+                .add_quantum_extensions()
+        });
+        let err = emit_op(&llvm_ctx, Tk2Op::H).unwrap_err();
+        assert!(format!("{err:#}").contains("%Qubit*"), "{err:#}");
+    }
+
+    #[rstest]
+    fn qir_prelude_qubit_type(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| cge.add_prelude_extensions(QirPreludeCodegen));
+        let qubit_ty = llvm_ctx.get_typing_session().llvm_type(&QB_T).unwrap();
+        assert_eq!(
+            qir_qubit_type(llvm_ctx.iw_context()).as_basic_type_enum(),
+            qubit_ty
+        );
+        assert_eq!("%Qubit*", qubit_ty.print_to_string().to_str().unwrap());
+    }
+
+    /// Builds a HUGR which prepares a Bell pair and measures both qubits.
+    /// If `use_result` then the result of the first measurement is used.
+    fn bell_hugr(use_result: bool) -> Hugr {
+        SimpleHugrConfig::new()
+            .with_extensions(tket2::extension::REGISTRY.to_owned())
+            .finish(|mut builder| {
+                let [q0, q1] = [(); 2].map(|_| {
+                    builder
+                        .add_dataflow_op(Tk2Op::QAlloc, [])
+                        .unwrap()
+                        .out_wire(0)
+                });
+                let [q0] = builder
+                    .add_dataflow_op(Tk2Op::H, [q0])
+                    .unwrap()
+                    .outputs_arr();
+                let [q0, q1] = builder
+                    .add_dataflow_op(Tk2Op::CX, [q0, q1])
+                    .unwrap()
+                    .outputs_arr();
+                let [q0, b] = builder
+                    .add_dataflow_op(Tk2Op::Measure, [q0])
+                    .unwrap()
+                    .outputs_arr();
+                let [q1, _] = builder
+                    .add_dataflow_op(Tk2Op::Measure, [q1])
+                    .unwrap()
+                    .outputs_arr();
+                for q in [q0, q1] {
+                    builder.add_dataflow_op(Tk2Op::QFree, [q]).unwrap();
+                }
+                if use_result {
+                    let tag = Tag::new(0, vec![type_row![BOOL_T], type_row![]]);
+                    builder.add_dataflow_op(tag, [b]).unwrap();
+                }
+                builder.finish_with_outputs([]).unwrap()
+            })
+    }
+
+    fn emit_base_profile(llvm_ctx: &TestContext, hugr: &Hugr) -> Result<String> {
+        let emit_hugr = llvm_ctx
+            .get_emit_hugr()
+            .with_mailbox_mode(MailBoxMode::Ssa)
+            .with_qir_base_profile(&QirBaseProfileOptions::default());
+        let emission = Emission::emit_hugr(hugr.fat_root().unwrap(), emit_hugr)?;
+        emission.verify()?;
+        Ok(emission.module().print_to_string().to_string())
+    }
+
+    #[rstest]
+    fn emit_base_profile_bell(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| {
+            cge.add_prelude_extensions(QirPreludeCodegen)
+                .add_quantum_extensions()
+        });
+        let module = emit_base_profile(&llvm_ctx, &bell_hugr(false)).unwrap();
+        // The static addresses of the qubits depend on the order in which
+        // their allocations are emitted.
+        let [q0, q1] = ["null", "inttoptr (i64 1 to %Qubit*)"];
+        let [q0, q1] = if module.contains(&format!("@__quantum__qis__h__body(%Qubit* {q0})")) {
+            [q0, q1]
+        } else {
+            [q1, q0]
+        };
+        for s in [
+            "\"EntryPoint\"",
+            "\"required_num_qubits\"=\"2\"",
+            "\"required_num_results\"=\"2\"",
+            &format!("call void @__quantum__qis__h__body(%Qubit* {q0})"),
+            &format!("call void @__quantum__qis__cnot__body(%Qubit* {q0}, %Qubit* {q1})"),
+            &format!("call void @__quantum__qis__mz__body(%Qubit* {q0}, %Result* "),
+            &format!("call void @__quantum__qis__mz__body(%Qubit* {q1}, %Result* "),
+            "call void @__quantum__rt__result_record_output(%Result* null, i8* null)",
+            "call void @__quantum__rt__result_record_output(%Result* inttoptr (i64 1 to %Result*), i8* null)",
+        ] {
+            assert!(module.contains(s), "{s} not in {module}");
+        }
+        for s in ["qubit_allocate", "qubit_release", "read_result"] {
+            assert!(!module.contains(s), "{s} in {module}");
+        }
+    }
+
+    #[rstest]
+    fn reject_base_profile_used_result(mut llvm_ctx: TestContext) {
+        llvm_ctx.add_extensions(|cge| {
+            cge.add_prelude_extensions(QirPreludeCodegen)
+                .add_quantum_extensions()
+        });
+        let err = emit_base_profile(&llvm_ctx, &bell_hugr(true)).unwrap_err();
+        match err.downcast::<EmitError>() {
+            Ok(EmitError {
+                kind: EmitErrorKind::QirBaseProfile(message),
+                ..
+            }) => {
+                assert!(message.contains("results of measurements"), "{message}")
+            }
+            err => panic!("Expected a QIR base profile error, got {err:?}"),
+        }
+    }
+
+    fn add_qir_extensions(ctx: &mut TestContext) {
+        ctx.add_extensions(|cge| {
+            cge.add_prelude_extensions(QirPreludeCodegen)
+                .add_default_rotation_extensions()
+                .add_float_extensions()
+                .add_quantum_extensions()
+        });
+    }
+
+    /// Executes the entry point of `hugr`, which must return nothing, with
+    /// the QIR runtime seeded with `seed`.
+    fn exec_qir(
+        exec_ctx: &TestContext,
+        hugr: &Hugr,
+        base_profile: bool,
+        seed: u64,
+    ) -> QirSimOutput {
+        let mut emit_hugr = exec_ctx.get_emit_hugr();
+        if base_profile {
+            emit_hugr = emit_hugr.with_qir_base_profile(&QirBaseProfileOptions::default());
+        }
+        let mut emission = Emission::emit_hugr(hugr.fat_root().unwrap(), emit_hugr).unwrap();
+        emission.verify().unwrap();
+        for (symbol, addr) in qir_rt_funcs() {
+            emission.add_global_mapping(symbol, addr);
+        }
+        run_qir_sim(seed, || emission.exec_impl("main").unwrap()).1
+    }
+
+    /// Converts the bool `b` to a `usize`.
+    fn bool_to_usize(builder: &mut DFGW, b: Wire) -> Wire {
+        let mut cond = builder
+            .conditional_builder(([type_row![], type_row![]], b), [], type_row![USIZE_T])
+            .unwrap();
+        for i in 0..2 {
+            let mut case = cond.case_builder(i).unwrap();
+            let r = case.add_load_value(ConstUsize::new(i as u64));
+            case.finish_with_outputs([r]).unwrap();
+        }
+        cond.finish_sub_container().unwrap().out_wire(0)
+    }
+
+    /// Builds a HUGR which allocates `num_qubits` qubits, applies `build` to
+    /// them, and returns the result of measuring the last of them as a
+    /// `usize`.
+    fn measure_last_hugr(
+        num_qubits: usize,
+        build: impl FnOnce(&mut DFGW, Vec<Wire>) -> Vec<Wire>,
+    ) -> Hugr {
+        SimpleHugrConfig::new()
+            .with_outs(USIZE_T)
+            .with_extensions(tket2::extension::REGISTRY.to_owned())
+            .finish(|mut builder| {
+                let qubits = (0..num_qubits)
+                    .map(|_| {
+                        builder
+                            .add_dataflow_op(Tk2Op::QAlloc, [])
+                            .unwrap()
+                            .out_wire(0)
+                    })
+                    .collect();
+                let mut qubits = build(&mut builder, qubits);
+                let last = qubits.pop().unwrap();
+                let [last, b] = builder
+                    .add_dataflow_op(Tk2Op::Measure, [last])
+                    .unwrap()
+                    .outputs_arr();
+                for q in qubits.into_iter().chain([last]) {
+                    builder.add_dataflow_op(Tk2Op::QFree, [q]).unwrap();
+                }
+                let r = bool_to_usize(&mut builder, b);
+                builder.finish_with_outputs([r]).unwrap()
+            })
+    }
+
+    #[rstest]
+    #[case::zero(&[], 0)]
+    #[case::x(&[Tk2Op::X], 1)]
+    #[case::y(&[Tk2Op::Y], 1)]
+    #[case::hh(&[Tk2Op::H, Tk2Op::H], 0)]
+    #[case::hzh(&[Tk2Op::H, Tk2Op::Z, Tk2Op::H], 1)]
+    #[case::hssh(&[Tk2Op::H, Tk2Op::S, Tk2Op::S, Tk2Op::H], 1)]
+    #[case::hsdgsh(&[Tk2Op::H, Tk2Op::Sdg, Tk2Op::S, Tk2Op::H], 0)]
+    #[case::htttth(&[Tk2Op::H, Tk2Op::T, Tk2Op::T, Tk2Op::T, Tk2Op::T, Tk2Op::H], 1)]
+    #[case::htdgth(&[Tk2Op::H, Tk2Op::Tdg, Tk2Op::T, Tk2Op::H], 0)]
+    #[case::reset(&[Tk2Op::X, Tk2Op::Reset], 0)]
+    fn exec_measure(mut exec_ctx: TestContext, #[case] ops: &[Tk2Op], #[case] expected: u64) {
+        add_qir_extensions(&mut exec_ctx);
+        add_qir_rt(&mut exec_ctx);
+        let hugr = measure_last_hugr(1, |builder, mut qubits| {
+            for &op in ops {
+                qubits = builder
+                    .add_dataflow_op(op, qubits)
+                    .unwrap()
+                    .outputs()
+                    .collect();
+            }
+            qubits
+        });
+        let (r, _) = run_qir_sim(0, || exec_ctx.exec_hugr_u64(hugr, "main"));
+        assert_eq!(expected, r);
+    }
+
+    #[rstest]
+    #[case(Tk2Op::Rx, 1.0, 1)]
+    #[case(Tk2Op::Rx, 0.0, 0)]
+    #[case(Tk2Op::Ry, 1.0, 1)]
+    #[case(Tk2Op::Rz, 1.0, 0)]
+    fn exec_rotation(
+        mut exec_ctx: TestContext,
+        #[case] op: Tk2Op,
+        #[case] half_turns: f64,
+        #[case] expected: u64,
+    ) {
+        add_qir_extensions(&mut exec_ctx);
+        add_qir_rt(&mut exec_ctx);
+        let hugr = measure_last_hugr(1, |builder, qubits| {
+            let angle = builder.add_load_value(ConstRotation::new(half_turns).unwrap());
+            let [q] = qubits.try_into().unwrap();
+            builder
+                .add_dataflow_op(op, [q, angle])
+                .unwrap()
+                .outputs()
+                .collect()
+        });
+        let (r, _) = run_qir_sim(0, || exec_ctx.exec_hugr_u64(hugr, "main"));
+        assert_eq!(expected, r);
+    }
+
+    #[rstest]
+    #[case::cx_0(Tk2Op::CX, false, 0)]
+    #[case::cx_1(Tk2Op::CX, true, 1)]
+    #[case::cy_1(Tk2Op::CY, true, 1)]
+    #[case::cz_0(Tk2Op::CZ, false, 0)]
+    #[case::cz_1(Tk2Op::CZ, true, 1)]
+    #[case::toffoli_0(Tk2Op::Toffoli, false, 0)]
+    #[case::toffoli_1(Tk2Op::Toffoli, true, 1)]
+    fn exec_controlled(
+        mut exec_ctx: TestContext,
+        #[case] op: Tk2Op,
+        #[case] controls_set: bool,
+        #[case] expected: u64,
+    ) {
+        add_qir_extensions(&mut exec_ctx);
+        add_qir_rt(&mut exec_ctx);
+        let num_qubits = if op == Tk2Op::Toffoli { 3 } else { 2 };
+        let hugr = measure_last_hugr(num_qubits, |builder, mut qubits| {
+            let mut apply = |op: Tk2Op, qubits: Vec<Wire>| -> Vec<Wire> {
+                builder
+                    .add_dataflow_op(op, qubits)
+                    .unwrap()
+                    .outputs()
+                    .collect()
+            };
+            let target = qubits.pop().unwrap();
+            if controls_set {
+                qubits = qubits
+                    .into_iter()
+                    .flat_map(|q| apply(Tk2Op::X, vec![q]))
+                    .collect();
+            }
+            // Z only flips the phase of the target, which H turns into a flip.
+            let target = if op == Tk2Op::CZ {
+                apply(Tk2Op::H, vec![target])[0]
+            } else {
+                target
+            };
+            qubits.push(target);
+            let mut qubits = apply(op, qubits);
+            if op == Tk2Op::CZ {
+                let target = qubits.pop().unwrap();
+                qubits.extend(apply(Tk2Op::H, vec![target]));
+            }
+            qubits
+        });
+        let (r, _) = run_qir_sim(0, || exec_ctx.exec_hugr_u64(hugr, "main"));
+        assert_eq!(expected, r);
+    }
+
+    #[rstest]
+    fn exec_bell(mut exec_ctx: TestContext, #[values(false, true)] base_profile: bool) {
+        add_qir_extensions(&mut exec_ctx);
+        let outcomes = (0..16)
+            .map(|seed| {
+                let output = exec_qir(&exec_ctx, &bell_hugr(false), base_profile, seed);
+                assert_eq!(2, output.measurements.len(), "{output:?}");
+                assert_eq!(output.measurements[0], output.measurements[1]);
+                if base_profile {
+                    assert_eq!(output.measurements, output.recorded);
+                } else {
+                    assert!(output.recorded.is_empty());
+                }
+                output.measurements[0]
+            })
+            .collect::<HashSet<_>>();
+        assert_eq!(2, outcomes.len());
+    }
+
+    #[rstest]
+    fn exec_deterministic(mut exec_ctx: TestContext) {
+        add_qir_extensions(&mut exec_ctx);
+        let hugr = bell_hugr(false);
+        assert_eq!(
+            exec_qir(&exec_ctx, &hugr, false, 7),
+            exec_qir(&exec_ctx, &hugr, false, 7)
+        );
+    }
+}
@@ -119,6 +119,32 @@ impl<'c> LLVMSumType<'c> {
             .as_basic_value_enum())
     }
 
+    /// Emit instructions to build a value of type `LLVMSumType` whose variant
+    /// is given by `tag`, which must be of type [LLVMSumType::get_tag_type].
+    ///
+    /// Fails unless every variant is empty, as in e.g. `bool`, so that a value
+    /// is determined by its tag alone.
+    pub fn build_from_tag(
+        &self,
+        builder: &Builder<'c>,
+        tag: IntValue<'c>,
+    ) -> Result<BasicValueEnum<'c>> {
+        if !self.has_tag_field() {
+            Err(anyhow!("LLVMSumType::build_from_tag: no tag field"))?
+        }
+        for i in 0..self.num_variants() {
+            if self.variant_num_fields(i)? != 0 {
+                Err(anyhow!(
+                    "LLVMSumType::build_from_tag: variant {i} of {} is not empty",
+                    self.1
+                ))?
+            }
+        }
+        Ok(builder
+            .build_insert_value(self.0.get_undef(), tag, 0, "")?
+            .as_basic_value_enum())
+    }
+
     /// Get the type of the value that would be returned by `build_get_tag`.
     pub fn get_tag_type(&self) -> IntType<'c> {
         self.0.get_context().i32_type()
@@ -15,6 +15,8 @@ use crate::{
     utils::fat::FatExt as _,
 };
 
+pub mod qir_rt;
+
 pub type THugrView = Hugr;
 
 pub struct InstaSettingsBuilder {
@@ -82,6 +84,7 @@ pub struct TestContext {
     mk_exts: MakeCodegenExtsMapBox,
     _insta: Option<insta::internals::SettingsBindDropGuard>,
     namer: Namer,
+    global_mappings: Vec<(String, usize)>,
 }
 
 impl TestContext {
@@ -95,6 +98,7 @@ impl TestContext {
             mk_exts: Box::new(ext_builder),
             _insta: insta_settings.and_then(InstaSettingsBuilder::finish),
             namer: Default::default(),
+            global_mappings: Vec::new(),
         }
     }
 
@@ -138,6 +142,21 @@ impl TestContext {
         self.namer = namer;
     }
 
+    /// Map the function named `symbol` to the native function at `addr` when
+    /// executing HUGRs with the `exec_hugr_*` methods.
+    pub fn add_global_mapping(&mut self, symbol: impl Into<String>, addr: usize) {
+        self.global_mappings.push((symbol.into(), addr));
+    }
+
+    fn emit_for_exec<'a>(&'a self, hugr: &'a THugrView) -> Emission<'a> {
+        let mut emission =
+            Emission::emit_hugr(hugr.fat_root().unwrap(), self.get_emit_hugr()).unwrap();
+        for (symbol, addr) in &self.global_mappings {
+            emission.add_global_mapping(symbol.clone(), *addr);
+        }
+        emission
+    }
+
     pub fn get_emit_module_context(&'_ self) -> EmitModuleContext<'_, 'static, THugrView> {
         let ctx = self.iw_context();
         let m = ctx.create_module("test_context");
@@ -154,17 +173,24 @@ impl TestContext {
     ///
     /// That function must take no arguments and return an LLVM `i64`.
     pub fn exec_hugr_u64(&self, hugr: THugrView, entry_point: impl AsRef<str>) -> u64 {
-        let emission = Emission::emit_hugr(hugr.fat_root().unwrap(), self.get_emit_hugr()).unwrap();
+        let emission = self.emit_for_exec(&hugr);
         emission.verify().unwrap();
 
         emission.exec_u64(entry_point).unwrap()
     }
 
     pub fn exec_hugr_f64(&self, hugr: THugrView, entry_point: impl AsRef<str>) -> f64 {
-        let emission = Emission::emit_hugr(hugr.fat_root().unwrap(), self.get_emit_hugr()).unwrap();
+        let emission = self.emit_for_exec(&hugr);
 
         emission.exec_f64(entry_point).unwrap()
     }
+
+    pub fn exec_hugr_string(&self, hugr: THugrView, entry_point: impl AsRef<str>) -> String {
+        let emission = self.emit_for_exec(&hugr);
+        emission.verify().unwrap();
+
+        emission.exec_string(entry_point).unwrap()
+    }
 }
 
 #[fixture]
@@ -0,0 +1,430 @@
+//! A runtime for executing programs lowered by
+//! [crate::extension::quantum::QuantumCodegenExtension] in tests.
+//!
+//! The QIR functions are implemented on a small state-vector simulator.
+//! Register them with [add_qir_rt] or [qir_rt_funcs], and execute the program
+//! inside [run_qir_sim], which gives each execution a fresh simulator with a
+//! fixed seed, so that results are deterministic.
+//!
+//! Qubits and results are identified by their addresses, which are never
+//! dereferenced: the qubit at address `i` is bit `i` of the state.
+//! Dynamically allocated qubits start at address 1, and address 0, the null
+//! pointer, is never allocated, so a null dynamic qubit is always an error.
+//! Static qubits under the QIR base profile, see
+//! [crate::emit::EmitHugr::with_qir_base_profile], are numbered from 0 and
+//! used at their addresses directly. The state grows as qubits are used, so
+//! static qubits need no allocation.
+//!
+//! Invalid qubits or results are ignored, except that
+//! `__quantum__rt__qubit_release` and `__quantum__qis__m__body` report a null
+//! qubit with `eprintln!` and then call [std::process::abort], rather than
+//! panicking. The QIR functions are `extern "C"`, and a panic cannot unwind
+//! out of an `extern "C"` function, so it would abort the test process
+//! anyway. Unwinding through the frames of the JIT-compiled caller, which
+//! have no unwind tables, would in any case be unsound.
+use std::{
+    cell::RefCell,
+    f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4},
+    ffi::c_void,
+    ops::{Add, Mul},
+};
+
+use super::TestContext;
+
+/// The outcomes of an execution of [run_qir_sim].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QirSimOutput {
+    /// The outcome of every measurement, in the order in which they were made.
+    pub measurements: Vec<bool>,
+    /// The results recorded by `__quantum__rt__result_record_output`, in the
+    /// order in which they were recorded.
+    pub recorded: Vec<bool>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Complex(f64, f64);
+
+impl Complex {
+    const ZERO: Self = Self(0.0, 0.0);
+    const ONE: Self = Self(1.0, 0.0);
+    const I: Self = Self(0.0, 1.0);
+
+    fn from_polar(theta: f64) -> Self {
+        Self(theta.cos(), theta.sin())
+    }
+
+    fn scale(self, k: f64) -> Self {
+        Self(self.0 * k, self.1 * k)
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.0 * self.0 + self.1 * self.1
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0, self.1 + other.1)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self(
+            self.0 * other.0 - self.1 * other.1,
+            self.0 * other.1 + self.1 * other.0,
+        )
+    }
+}
+
+type Matrix = [[Complex; 2]; 2];
+
+fn diag(a: Complex, b: Complex) -> Matrix {
+    [[a, Complex::ZERO], [Complex::ZERO, b]]
+}
+
+/// A state-vector simulator. Qubit `i` is bit `i` of the index of an
+/// amplitude.
+struct QirSim {
+    amplitudes: Vec<Complex>,
+    allocated: Vec<bool>,
+    results: Vec<bool>,
+    rng_state: u64,
+    output: QirSimOutput,
+}
+
+impl QirSim {
+    fn new(seed: u64) -> Self {
+        Self {
+            amplitudes: vec![Complex::ONE],
+            // Address 0 is the null pointer, which is never allocated.
+            allocated: vec![true],
+            results: Vec::new(),
+            rng_state: seed,
+            output: Default::default(),
+        }
+    }
+
+    /// Returns a uniformly distributed number in `[0, 1)`, using SplitMix64.
+    fn next_f64(&mut self) -> f64 {
+        self.rng_state = self.rng_state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn num_qubits(&self) -> usize {
+        self.amplitudes.len().trailing_zeros() as usize
+    }
+
+    /// Adds qubits in the zero state until `qubit` exists.
+    fn ensure_qubit(&mut self, qubit: usize) {
+        while self.num_qubits() <= qubit {
+            let len = self.amplitudes.len();
+            self.amplitudes.resize(2 * len, Complex::ZERO);
+        }
+    }
+
+    /// Applies `matrix` to `target` in the subspace where all `controls` are
+    /// one.
+    fn apply(&mut self, controls: &[usize], target: usize, matrix: Matrix) {
+        for &q in controls.iter().chain([&target]) {
+            self.ensure_qubit(q);
+        }
+        let control_mask = controls.iter().fold(0, |mask, q| mask | 1 << q);
+        let target_bit = 1 << target;
+        for i in 0..self.amplitudes.len() {
+            if i & target_bit != 0 || i & control_mask != control_mask {
+                continue;
+            }
+            let (a0, a1) = (self.amplitudes[i], self.amplitudes[i | target_bit]);
+            self.amplitudes[i] = matrix[0][0] * a0 + matrix[0][1] * a1;
+            self.amplitudes[i | target_bit] = matrix[1][0] * a0 + matrix[1][1] * a1;
+        }
+    }
+
+    /// Measures `qubit`, collapsing the state.
+    fn measure(&mut self, qubit: usize) -> bool {
+        self.ensure_qubit(qubit);
+        let bit = 1 << qubit;
+        let p_one: f64 = (0..self.amplitudes.len())
+            .filter(|i| i & bit != 0)
+            .map(|i| self.amplitudes[i].norm_sqr())
+            .sum();
+        let outcome = self.next_f64() < p_one;
+        let norm = if outcome { p_one } else { 1.0 - p_one }.sqrt();
+        for (i, amplitude) in self.amplitudes.iter_mut().enumerate() {
+            *amplitude = if (i & bit != 0) == outcome {
+                amplitude.scale(1.0 / norm)
+            } else {
+                Complex::ZERO
+            };
+        }
+        self.output.measurements.push(outcome);
+        outcome
+    }
+
+    fn reset(&mut self, qubit: usize) {
+        if self.measure(qubit) {
+            self.apply(&[], qubit, X);
+        }
+        // A reset is not a measurement the program can observe.
+        self.output.measurements.pop();
+    }
+
+    fn allocate(&mut self) -> usize {
+        let qubit = match self.allocated.iter().position(|a| !a) {
+            Some(qubit) => qubit,
+            None => {
+                self.allocated.push(false);
+                self.allocated.len() - 1
+            }
+        };
+        self.allocated[qubit] = true;
+        self.ensure_qubit(qubit);
+        qubit
+    }
+
+    fn release(&mut self, qubit: usize) {
+        self.reset(qubit);
+        if let Some(allocated) = self.allocated.get_mut(qubit) {
+            *allocated = false;
+        }
+    }
+
+    fn set_result(&mut self, result: usize, outcome: bool) {
+        if self.results.len() <= result {
+            self.results.resize(result + 1, false);
+        }
+        self.results[result] = outcome;
+    }
+
+    fn result(&self, result: usize) -> bool {
+        self.results.get(result).copied().unwrap_or(false)
+    }
+}
+
+const H: Matrix = [
+    [Complex(FRAC_1_SQRT_2, 0.0), Complex(FRAC_1_SQRT_2, 0.0)],
+    [Complex(FRAC_1_SQRT_2, 0.0), Complex(-FRAC_1_SQRT_2, 0.0)],
+];
+const X: Matrix = [[Complex::ZERO, Complex::ONE], [Complex::ONE, Complex::ZERO]];
+const Y: Matrix = [
+    [Complex::ZERO, Complex(0.0, -1.0)],
+    [Complex::I, Complex::ZERO],
+];
+const Z: Matrix = [
+    [Complex::ONE, Complex::ZERO],
+    [Complex::ZERO, Complex(-1.0, 0.0)],
+];
+
+fn phase(theta: f64) -> Matrix {
+    diag(Complex::ONE, Complex::from_polar(theta))
+}
+
+fn rx(theta: f64) -> Matrix {
+    let (c, s) = (Complex((theta / 2.0).cos(), 0.0), (theta / 2.0).sin());
+    [[c, Complex(0.0, -s)], [Complex(0.0, -s), c]]
+}
+
+fn ry(theta: f64) -> Matrix {
+    let (c, s) = ((theta / 2.0).cos(), (theta / 2.0).sin());
+    [
+        [Complex(c, 0.0), Complex(-s, 0.0)],
+        [Complex(s, 0.0), Complex(c, 0.0)],
+    ]
+}
+
+fn rz(theta: f64) -> Matrix {
+    diag(
+        Complex::from_polar(-theta / 2.0),
+        Complex::from_polar(theta / 2.0),
+    )
+}
+
+thread_local! {
+    static QIR_SIM: RefCell<QirSim> = RefCell::new(QirSim::new(0));
+}
+
+/// Runs `f`, typically the execution of a program, with a fresh simulator
+/// seeded with `seed`. Returns the result of `f` and the outcomes of the
+/// simulation.
+///
+/// The simulator is thread-local, and JIT-compiled code runs on the calling
+/// thread, so tests may run in parallel.
+pub fn run_qir_sim<T>(seed: u64, f: impl FnOnce() -> T) -> (T, QirSimOutput) {
+    QIR_SIM.with(|sim| *sim.borrow_mut() = QirSim::new(seed));
+    let r = f();
+    let output = QIR_SIM.with(|sim| std::mem::take(&mut sim.borrow_mut().output));
+    (r, output)
+}
+
+fn with_sim<T>(f: impl FnOnce(&mut QirSim) -> T) -> T {
+    QIR_SIM.with(|sim| f(&mut sim.borrow_mut()))
+}
+
+fn index(ptr: *mut c_void) -> usize {
+    ptr as usize
+}
+
+/// Returns the index of the dynamically allocated `qubit`, aborting if it is
+/// null. This aborts rather than panics, as a panic cannot unwind out of the
+/// `extern "C"` functions which call this. See the
+/// [module-level documentation](self).
+fn dynamic_index(qubit: *mut c_void) -> usize {
+    if qubit.is_null() {
+        eprintln!("null pointer passed as a dynamically allocated qubit");
+        std::process::abort();
+    }
+    index(qubit)
+}
+
+macro_rules! qis_gates {
+    ($($name:ident => $matrix:expr),* $(,)?) => {
+        $(
+            extern "C" fn $name(qubit: *mut c_void) {
+                with_sim(|sim| sim.apply(&[], index(qubit), $matrix))
+            }
+        )*
+    };
+}
+
+qis_gates! {
+    h => H,
+    x => X,
+    y => Y,
+    z => Z,
+    s => phase(FRAC_PI_2),
+    s_adj => phase(-FRAC_PI_2),
+    t => phase(FRAC_PI_4),
+    t_adj => phase(-FRAC_PI_4),
+}
+
+macro_rules! qis_rotations {
+    ($($name:ident => $matrix:ident),* $(,)?) => {
+        $(
+            extern "C" fn $name(theta: f64, qubit: *mut c_void) {
+                with_sim(|sim| sim.apply(&[], index(qubit), $matrix(theta)))
+            }
+        )*
+    };
+}
+
+qis_rotations! {
+    rx_body => rx,
+    ry_body => ry,
+    rz_body => rz,
+}
+
+extern "C" fn cnot(control: *mut c_void, target: *mut c_void) {
+    with_sim(|sim| sim.apply(&[index(control)], index(target), X))
+}
+
+extern "C" fn cz(control: *mut c_void, target: *mut c_void) {
+    with_sim(|sim| sim.apply(&[index(control)], index(target), Z))
+}
+
+extern "C" fn ccx(control1: *mut c_void, control2: *mut c_void, target: *mut c_void) {
+    with_sim(|sim| sim.apply(&[index(control1), index(control2)], index(target), X))
+}
+
+extern "C" fn reset(qubit: *mut c_void) {
+    with_sim(|sim| sim.reset(index(qubit)))
+}
+
+/// Measures `qubit` into a new result, whose address is returned.
+extern "C" fn m(qubit: *mut c_void) -> *mut c_void {
+    with_sim(|sim| {
+        let outcome = sim.measure(dynamic_index(qubit));
+        let result = sim.results.len();
+        sim.set_result(result, outcome);
+        result as *mut c_void
+    })
+}
+
+/// Measures `qubit` into the static `result`.
+extern "C" fn mz(qubit: *mut c_void, result: *mut c_void) {
+    with_sim(|sim| {
+        let outcome = sim.measure(index(qubit));
+        sim.set_result(index(result), outcome)
+    })
+}
+
+extern "C" fn read_result(result: *mut c_void) -> bool {
+    with_sim(|sim| sim.result(index(result)))
+}
+
+extern "C" fn initialize(_: *mut c_void) {}
+
+extern "C" fn qubit_allocate() -> *mut c_void {
+    with_sim(|sim| sim.allocate() as *mut c_void)
+}
+
+extern "C" fn qubit_release(qubit: *mut c_void) {
+    with_sim(|sim| sim.release(dynamic_index(qubit)))
+}
+
+extern "C" fn result_record_output(result: *mut c_void, _label: *mut c_void) {
+    with_sim(|sim| {
+        let outcome = sim.result(index(result));
+        sim.output.recorded.push(outcome)
+    })
+}
+
+/// Returns the symbol and address of each QIR function implemented by the
+/// runtime.
+pub fn qir_rt_funcs() -> Vec<(&'static str, usize)> {
+    vec![
+        ("__quantum__qis__h__body", h as *const () as usize),
+        ("__quantum__qis__x__body", x as *const () as usize),
+        ("__quantum__qis__y__body", y as *const () as usize),
+        ("__quantum__qis__z__body", z as *const () as usize),
+        ("__quantum__qis__s__body", s as *const () as usize),
+        ("__quantum__qis__s__adj", s_adj as *const () as usize),
+        ("__quantum__qis__t__body", t as *const () as usize),
+        ("__quantum__qis__t__adj", t_adj as *const () as usize),
+        ("__quantum__qis__rx__body", rx_body as *const () as usize),
+        ("__quantum__qis__ry__body", ry_body as *const () as usize),
+        ("__quantum__qis__rz__body", rz_body as *const () as usize),
+        ("__quantum__qis__cnot__body", cnot as *const () as usize),
+        ("__quantum__qis__cz__body", cz as *const () as usize),
+        ("__quantum__qis__ccx__body", ccx as *const () as usize),
+        ("__quantum__qis__reset__body", reset as *const () as usize),
+        ("__quantum__qis__m__body", m as *const () as usize),
+        ("__quantum__qis__mz__body", mz as *const () as usize),
+        (
+            "__quantum__qis__read_result__body",
+            read_result as *const () as usize,
+        ),
+        (
+            "__quantum__rt__initialize",
+            initialize as *const () as usize,
+        ),
+        (
+            "__quantum__rt__qubit_allocate",
+            qubit_allocate as *const () as usize,
+        ),
+        (
+            "__quantum__rt__qubit_release",
+            qubit_release as *const () as usize,
+        ),
+        (
+            "__quantum__rt__result_record_output",
+            result_record_output as *const () as usize,
+        ),
+    ]
+}
+
+/// Registers the QIR functions of the runtime with `ctx`, see
+/// [TestContext::add_global_mapping].
+pub fn add_qir_rt(ctx: &mut TestContext) {
+    for (symbol, addr) in qir_rt_funcs() {
+        ctx.add_global_mapping(symbol, addr);
+    }
+}
@@ -1,14 +1,15 @@
+use std::borrow::Cow;
 use std::rc::Rc;
 
 use anyhow::Result;
-use delegate::delegate;
 use hugr::extension::ExtensionId;
-use hugr::types::{SumType, Type, TypeName};
+use hugr::types::{SumType, Type, TypeArg, TypeName};
 use inkwell::types::FunctionType;
 use inkwell::{context::Context, types::BasicTypeEnum};
 
 use crate::custom::types::{LLVMCustomTypeFn, LLVMTypeMapping};
 pub use crate::sum::LLVMSumType;
+use crate::utils::substitute::{substitute_func_type, substitute_sum_type, substitute_type};
 use crate::utils::type_map::TypeMap;
 
 /// A type alias for a hugr function type. We use this to disambiguate from
@@ -23,22 +24,40 @@ pub type HugrSumType = SumType;
 
 /// A type that holds [Rc] shared pointers to everything needed to convert from
 /// a hugr [HugrType] to an LLVM [Type](inkwell::types).
+///
+/// A `TypingSession` may also hold [TypeArg]s, which are substituted for the
+/// type variables of any [HugrType] before it is converted. This is used when
+/// emitting instantiations of polymorphic functions.
 #[derive(Clone)]
 pub struct TypingSession<'c, 'a> {
     iw_context: &'c Context,
     type_converter: Rc<TypeConverter<'a>>,
+    type_args: Rc<[TypeArg]>,
 }
 
 impl<'c, 'a> TypingSession<'c, 'a> {
-    delegate! {
-        to self.type_converter.clone() {
-            /// Convert a [HugrType] into an LLVM [Type](BasicTypeEnum).
-            pub fn llvm_type(&self, [self.clone()], hugr_type: &HugrType) -> Result<BasicTypeEnum<'c>>;
-            /// Convert a [HugrFuncType] into an LLVM [FunctionType].
-            pub fn llvm_func_type(&self, [self.clone()], hugr_type: &HugrFuncType) -> Result<FunctionType<'c>>;
-            /// Convert a hugr [HugrSumType] into an LLVM [LLVMSumType].
-            pub fn llvm_sum_type(&self, [self.clone()], hugr_type: HugrSumType) -> Result<LLVMSumType<'c>>;
-        }
+    /// Convert a [HugrType] into an LLVM [Type](BasicTypeEnum).
+    pub fn llvm_type(&self, hugr_type: &HugrType) -> Result<BasicTypeEnum<'c>> {
+        let hugr_type = self.substitute(hugr_type, substitute_type)?;
+        self.type_converter
+            .clone()
+            .llvm_type(self.clone(), hugr_type.as_ref())
+    }
+
+    /// Convert a [HugrFuncType] into an LLVM [FunctionType].
+    pub fn llvm_func_type(&self, hugr_type: &HugrFuncType) -> Result<FunctionType<'c>> {
+        let hugr_type = self.substitute(hugr_type, substitute_func_type)?;
+        self.type_converter
+            .clone()
+            .llvm_func_type(self.clone(), hugr_type.as_ref())
+    }
+
+    /// Convert a hugr [HugrSumType] into an LLVM [LLVMSumType].
+    pub fn llvm_sum_type(&self, hugr_type: HugrSumType) -> Result<LLVMSumType<'c>> {
+        let hugr_type = self.substitute(&hugr_type, substitute_sum_type)?;
+        self.type_converter
+            .clone()
+            .llvm_sum_type(self.clone(), hugr_type.into_owned())
     }
 
     /// Creates a new `TypingSession`.
@@ -46,9 +65,36 @@ impl<'c, 'a> TypingSession<'c, 'a> {
         Self {
             iw_context,
             type_converter,
+            type_args: Rc::new([]),
         }
     }
 
+    /// Returns a `TypingSession` which substitutes `type_args` for type
+    /// variables before converting types.
+    pub fn with_type_args(self, type_args: impl Into<Rc<[TypeArg]>>) -> Self {
+        Self {
+            type_args: type_args.into(),
+            ..self
+        }
+    }
+
+    /// Returns the [TypeArg]s substituted for type variables by this session.
+    pub fn type_args(&self) -> &[TypeArg] {
+        &self.type_args
+    }
+
+    fn substitute<'t, T: Clone>(
+        &self,
+        t: &'t T,
+        subst: impl FnOnce(&T, &[TypeArg]) -> Result<T>,
+    ) -> Result<Cow<'t, T>> {
+        Ok(if self.type_args.is_empty() {
+            Cow::Borrowed(t)
+        } else {
+            Cow::Owned(subst(t, &self.type_args)?)
+        })
+    }
+
     /// Returns a reference to the inner [Context].
     pub fn iw_context(&self) -> &'c Context {
         self.iw_context
@@ -67,6 +113,11 @@ impl<'a> TypeConverter<'a> {
         self.0.set_callback(custom_type, handler);
     }
 
+    /// Returns whether a callback is registered for the given custom type.
+    pub fn has_custom_type(&self, custom_type: &(ExtensionId, TypeName)) -> bool {
+        self.0.has_callback(custom_type)
+    }
+
     pub fn llvm_type<'c>(
         self: Rc<Self>,
         context: TypingSession<'c, 'a>,
@@ -1,11 +1,17 @@
 //! Module for utilities that do not depend on LLVM. These are candidates for
 //! upstreaming.
+#[allow(clippy::result_large_err)]
 pub mod array_op_builder;
 pub mod fat;
 pub mod inline_constant_functions;
+#[allow(clippy::result_large_err)]
 pub mod int_op_builder;
+#[allow(clippy::result_large_err)]
 pub mod logic_op_builder;
+pub mod ownership;
+pub mod substitute;
 pub mod type_map;
+#[allow(clippy::result_large_err)]
 pub mod unwrap_builder;
 
 pub use array_op_builder::ArrayOpBuilder;
@@ -317,7 +317,7 @@ impl<OT, H> NodeIndex for &FatNode<'_, OT, H> {
 /// TODO: Add the remaining [HugrView] equivalents that make sense.
 pub trait FatExt: HugrView {
     /// Try to create a specific [FatNode] for a given [Node].
-    fn try_fat<OT>(&self, node: Node) -> Option<FatNode<OT, Self>>
+    fn try_fat<OT>(&self, node: Node) -> Option<FatNode<'_, OT, Self>>
     where
         for<'a> &'a OpType: TryInto<&'a OT>,
     {
@@ -325,23 +325,23 @@ pub trait FatExt: HugrView {
     }
 
     /// Create a general [FatNode] for a given [Node].
-    fn fat_optype(&self, node: Node) -> FatNode<OpType, Self> {
+    fn fat_optype(&self, node: Node) -> FatNode<'_, OpType, Self> {
         FatNode::new_optype(self, node)
     }
 
     /// Try to create [Input] and [Output] [FatNode]s for a given [Node]. This
     /// will succeed only for DataFlow Parent Nodes.
-    fn fat_io(&self, node: Node) -> Option<(FatNode<Input, Self>, FatNode<Output, Self>)> {
+    fn fat_io(&self, node: Node) -> Option<(FatNode<'_, Input, Self>, FatNode<'_, Output, Self>)> {
         self.fat_optype(node).get_io()
     }
 
     /// Create general [FatNode]s for each of a [Node]'s children.
-    fn fat_children(&self, node: Node) -> impl Iterator<Item = FatNode<OpType, Self>> {
+    fn fat_children(&self, node: Node) -> impl Iterator<Item = FatNode<'_, OpType, Self>> {
         self.children(node).map(|x| self.fat_optype(x))
     }
 
     /// Try to create a specific [FatNode] for the root of a [HugrView].
-    fn fat_root<OT>(&self) -> Option<FatNode<OT, Self>>
+    fn fat_root<OT>(&self) -> Option<FatNode<'_, OT, Self>>
     where
         for<'a> &'a OpType: TryInto<&'a OT>,
     {
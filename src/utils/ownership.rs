@@ -0,0 +1,93 @@
+//! Provides an analysis of which values are owned by the op consuming them.
+//!
+//! Copying a HUGR value does not emit any code, so when a value is lowered to
+//! a pointer to a heap buffer, all copies of the value share that buffer. An
+//! op may therefore only mutate or free the buffer of an input which it owns,
+//! i.e. which is not referenced by any other value. An input is owned if it
+//! can be traced back to an op which allocated or owns its buffer, through
+//! wires with no other uses, possibly via the inputs and outputs of `DFG`s and
+//! `Conditional`s.
+use hugr::{
+    extension::prelude::{PANIC_OP_ID, PRELUDE_ID},
+    ops::OpType,
+    HugrView, IncomingPort, Node, OutgoingPort, PortIndex as _,
+};
+
+/// Returns true if the value at input `port` of `node` is the only use of an
+/// owned value, as described in the [module-level documentation](self).
+///
+/// If `field` is `Some((tag, index))` the value is a sum, and the owned value
+/// is the field `index` of its variant `tag`.
+///
+/// `owns_output(node, port, field)` must return true if output `port` of
+/// `node` is always an owned value, or a sum containing one at `field`. It is
+/// called for nodes other than `DFG`s, `Conditional`s, their `Input`s, and
+/// panics, which are handled here.
+pub fn is_owned_wire(
+    hugr: &impl HugrView,
+    node: Node,
+    port: IncomingPort,
+    field: Option<(usize, usize)>,
+    owns_output: &impl Fn(Node, OutgoingPort, Option<(usize, usize)>) -> bool,
+) -> bool {
+    let Some((src, src_port)) = hugr.single_linked_output(node, port) else {
+        return false;
+    };
+    hugr.linked_inputs(src, src_port).count() == 1
+        && is_owned_output(hugr, src, src_port, field, owns_output)
+}
+
+/// Returns true if output `port` of `node` is an owned value, or a sum
+/// containing one at `field`. See [is_owned_wire].
+fn is_owned_output(
+    hugr: &impl HugrView,
+    node: Node,
+    port: OutgoingPort,
+    field: Option<(usize, usize)>,
+    owns_output: &impl Fn(Node, OutgoingPort, Option<(usize, usize)>) -> bool,
+) -> bool {
+    let owned_wire =
+        |node: Node, port: usize, field| is_owned_wire(hugr, node, port.into(), field, owns_output);
+    match hugr.get_optype(node) {
+        // A panic never produces its outputs.
+        OpType::ExtensionOp(ext_op)
+            if ext_op.def().extension() == &PRELUDE_ID && ext_op.def().name() == &PANIC_OP_ID =>
+        {
+            true
+        }
+        OpType::Input(_) => {
+            let Some(parent) = hugr.get_parent(node) else {
+                return false;
+            };
+            match hugr.get_optype(parent) {
+                OpType::DFG(_) => owned_wire(parent, port.index(), field),
+                OpType::Case(_) => {
+                    let Some(conditional) = hugr.get_parent(parent) else {
+                        return false;
+                    };
+                    let OpType::Conditional(cond_op) = hugr.get_optype(conditional) else {
+                        return false;
+                    };
+                    let Some(tag) = hugr.children(conditional).position(|c| c == parent) else {
+                        return false;
+                    };
+                    let num_fields = cond_op.sum_rows[tag].len();
+                    if port.index() < num_fields {
+                        field.is_none() && owned_wire(conditional, 0, Some((tag, port.index())))
+                    } else {
+                        owned_wire(conditional, port.index() - num_fields + 1, field)
+                    }
+                }
+                _ => false,
+            }
+        }
+        OpType::DFG(_) => hugr
+            .get_io(node)
+            .is_some_and(|[_, output]| owned_wire(output, port.index(), field)),
+        OpType::Conditional(_) => hugr.children(node).all(|case| {
+            hugr.get_io(case)
+                .is_some_and(|[_, output]| owned_wire(output, port.index(), field))
+        }),
+        _ => owns_output(node, port, field),
+    }
+}
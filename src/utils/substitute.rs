@@ -0,0 +1,143 @@
+//! Provides functions for substituting [TypeArg]s for the type variables of
+//! [HugrType]s.
+//!
+//! These are used when emitting instantiations of polymorphic functions.
+use anyhow::{anyhow, bail, Result};
+use hugr::types::{CustomType, SumType, Type, TypeArg, TypeEnum, TypeRow};
+use itertools::Itertools as _;
+
+use crate::types::{HugrFuncType, HugrSumType, HugrType};
+
+/// Substitute `type_args` for the type variables of `arg`.
+///
+/// Row variables are not supported.
+pub fn substitute_type_arg(arg: &TypeArg, type_args: &[TypeArg]) -> Result<TypeArg> {
+    Ok(match arg {
+        TypeArg::Type { ty } => TypeArg::Type {
+            ty: substitute_type(ty, type_args)?,
+        },
+        TypeArg::Sequence { elems } => TypeArg::Sequence {
+            elems: elems
+                .iter()
+                .map(|x| substitute_type_arg(x, type_args))
+                .collect::<Result<_>>()?,
+        },
+        TypeArg::Variable { v } => {
+            if v.bound_if_row_var().is_some() {
+                bail!("Row variables are not supported: {arg:?}")
+            }
+            lookup(v.index(), type_args)?.clone()
+        }
+        _ => arg.clone(),
+    })
+}
+
+/// Substitute `type_args` for the type variables of `hugr_type`.
+///
+/// Row variables are not supported.
+pub fn substitute_type(hugr_type: &HugrType, type_args: &[TypeArg]) -> Result<HugrType> {
+    Ok(match hugr_type.as_type_enum() {
+        TypeEnum::Extension(custom_type) => {
+            let args = custom_type
+                .args()
+                .iter()
+                .map(|x| substitute_type_arg(x, type_args))
+                .collect::<Result<Vec<_>>>()?;
+            // We keep the original bound. It may be more general than
+            // necessary, but that does not matter for lowering.
+            Type::new_extension(CustomType::new(
+                custom_type.name().clone(),
+                args,
+                custom_type.extension().clone(),
+                custom_type.bound(),
+            ))
+        }
+        TypeEnum::Sum(sum_type) => substitute_sum_type(sum_type, type_args)?.into(),
+        TypeEnum::Function(function_type) => Type::new_function(substitute_func_type(
+            &function_type.as_ref().clone().try_into()?,
+            type_args,
+        )?),
+        TypeEnum::Variable(idx, _) => {
+            let TypeArg::Type { ty } = lookup(*idx, type_args)? else {
+                bail!("Type variable {idx} is not bound to a type: {type_args:?}")
+            };
+            ty.clone()
+        }
+        _ => hugr_type.clone(),
+    })
+}
+
+/// Substitute `type_args` for the type variables of `sum_type`.
+pub fn substitute_sum_type(sum_type: &HugrSumType, type_args: &[TypeArg]) -> Result<HugrSumType> {
+    let variants = (0..sum_type.num_variants())
+        .map(|i| {
+            let tr: TypeRow = sum_type.get_variant(i).unwrap().clone().try_into()?;
+            substitute_type_row(&tr, type_args)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(SumType::new(variants))
+}
+
+/// Substitute `type_args` for the type variables of `func_type`.
+pub fn substitute_func_type(
+    func_type: &HugrFuncType,
+    type_args: &[TypeArg],
+) -> Result<HugrFuncType> {
+    Ok(HugrFuncType::new(
+        substitute_type_row(&func_type.input, type_args)?,
+        substitute_type_row(&func_type.output, type_args)?,
+    )
+    .with_extension_delta(func_type.extension_reqs.clone()))
+}
+
+fn substitute_type_row(type_row: &TypeRow, type_args: &[TypeArg]) -> Result<TypeRow> {
+    Ok(type_row
+        .iter()
+        .map(|t| substitute_type(t, type_args))
+        .collect::<Result<Vec<_>>>()?
+        .into())
+}
+
+fn lookup(idx: usize, type_args: &[TypeArg]) -> Result<&TypeArg> {
+    type_args.get(idx).ok_or(anyhow!(
+        "Type variable {idx} is not bound. Type args: [{}]",
+        type_args.iter().map(|x| format!("{x:?}")).join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::{
+        extension::prelude::{array_type, BOOL_T, USIZE_T},
+        type_row,
+        types::{Signature, Type, TypeBound},
+    };
+
+    use super::*;
+
+    #[test]
+    fn substitute() {
+        let tv0 = Type::new_var_use(0, TypeBound::Any);
+        let tv1 = Type::new_var_use(1, TypeBound::Copyable);
+        let type_args = [USIZE_T.into(), BOOL_T.into()];
+
+        assert_eq!(USIZE_T, substitute_type(&tv0, &type_args).unwrap());
+        assert_eq!(
+            Type::new_tuple(vec![BOOL_T, USIZE_T]),
+            substitute_type(&Type::new_tuple(vec![tv1.clone(), tv0.clone()]), &type_args).unwrap()
+        );
+        assert_eq!(
+            array_type(3, BOOL_T),
+            substitute_type(&array_type(3, tv1.clone()), &type_args).unwrap()
+        );
+        assert_eq!(
+            Type::new_function(Signature::new(USIZE_T, type_row![BOOL_T])),
+            substitute_type(
+                &Type::new_function(Signature::new(tv0.clone(), vec![tv1])),
+                &type_args
+            )
+            .unwrap()
+        );
+        assert!(substitute_type(&tv0, &[]).is_err());
+    }
+}
@@ -112,6 +112,11 @@ impl<'a, TM: TypeMapping + 'a> TypeMap<'a, TM> {
             .is_none()
     }
 
+    /// Returns whether a callback is registered for the given custom type.
+    pub fn has_callback(&self, custom_type_key: &CustomTypeKey) -> bool {
+        self.custom_hooks.contains_key(custom_type_key)
+    }
+
     /// Map `hugr_type` using the [TypeMapping] `TM`, the registered callbacks,
     /// and the auxilliary data `inv`.
     pub fn map_type<'c>(&self, hugr_type: &HugrType, inv: TM::InV<'c>) -> Result<TM::OutV<'c>> {